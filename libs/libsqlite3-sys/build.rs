@@ -20,6 +20,7 @@ fn main() {
         .flag("-DSQLITE_DISABLE_LFS")
         .flag("-DSQLITE_ENABLE_FTS5")
         .flag("-DSQLITE_ENABLE_FTS5_PARENTHESIS")
+        .flag("-DSQLITE_ENABLE_COLUMN_METADATA")
         .flag("-DSQLITE_THREADSAFE=0");
 
     // Older versions of visual studio don't support c99 (including isnan), which
@@ -35,6 +36,12 @@ fn main() {
     if cfg!(feature = "preupdate_hook") {
         cfg.flag("-DSQLITE_ENABLE_PREUPDATE_HOOK");
     }
+    if cfg!(feature = "deserialize") {
+        cfg.flag("-DSQLITE_ENABLE_DESERIALIZE");
+    }
+    if cfg!(feature = "snapshot") {
+        cfg.flag("-DSQLITE_ENABLE_SNAPSHOT");
+    }
 
 
     if let Ok(limit) = env::var("SQLITE_MAX_VARIABLE_NUMBER") {
@@ -0,0 +1,270 @@
+//! Derive macros for rusqlite, re-exported by `rusqlite` with the `derive`
+//! feature.
+//!
+//! * `#[derive(FromRow)]` implements `TryFrom<&Row>`, reading each field
+//!   with `Row::get` by column name (or by index with
+//!   `#[rusqlite(by_index)]`).
+//! * `#[derive(ToParams)]` implements `rusqlite::ToParams`.
+//! * `#[derive(Table)]` implements `rusqlite::Table`, whose
+//!   `create_table_sql` picks each column's type from
+//!   `rusqlite::types::ColumnType`.
+//!
+//! Struct attributes: `#[rusqlite(table = "name")]`, `#[rusqlite(by_index)]`.
+//! Field attributes: `#[rusqlite(rename = "name")]`,
+//! `#[rusqlite(index = N)]`, `#[rusqlite(primary_key)]`.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Ident, Index, Lit, LitStr,
+    Member, Meta, NestedMeta, Result, Type,
+};
+
+/// Derive `TryFrom<&rusqlite::Row<'_>>`.
+#[proc_macro_derive(FromRow, attributes(rusqlite))]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    expand(parse_macro_input!(input as DeriveInput), from_row)
+}
+
+/// Derive `rusqlite::ToParams`.
+#[proc_macro_derive(ToParams, attributes(rusqlite))]
+pub fn derive_to_params(input: TokenStream) -> TokenStream {
+    expand(parse_macro_input!(input as DeriveInput), to_params)
+}
+
+/// Derive `rusqlite::Table`.
+#[proc_macro_derive(Table, attributes(rusqlite))]
+pub fn derive_table(input: TokenStream) -> TokenStream {
+    expand(parse_macro_input!(input as DeriveInput), table)
+}
+
+fn expand(input: DeriveInput, f: fn(&Struct<'_>) -> TokenStream2) -> TokenStream {
+    match Struct::parse(&input) {
+        Ok(s) => f(&s).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// A struct and its `#[rusqlite(...)]` attributes.
+struct Struct<'a> {
+    input: &'a DeriveInput,
+    table: String,
+    by_index: bool,
+    fields: Vec<Field<'a>>,
+}
+
+struct Field<'a> {
+    member: Member,
+    ty: &'a Type,
+    column: String,
+    index: usize,
+    primary_key: bool,
+}
+
+fn rusqlite_attrs(attrs: &[syn::Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut nested = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("rusqlite")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => nested.extend(list.nested),
+            meta => return Err(Error::new_spanned(meta, "expected #[rusqlite(...)]")),
+        }
+    }
+    Ok(nested)
+}
+
+fn lit_str(lit: &Lit) -> Result<String> {
+    match *lit {
+        Lit::Str(ref s) => Ok(s.value()),
+        _ => Err(Error::new_spanned(lit, "expected a string")),
+    }
+}
+
+fn lit_index(lit: &Lit) -> Result<usize> {
+    match *lit {
+        Lit::Int(ref i) => i.base10_parse(),
+        _ => Err(Error::new_spanned(lit, "expected an integer")),
+    }
+}
+
+/// `name` in double quotes, with embedded quotes doubled, so that any name
+/// can be used in SQL.
+fn quoted_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn snake_case(ident: &Ident) -> String {
+    let mut name = String::new();
+    for (i, c) in ident.to_string().chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                name.push('_');
+            }
+            name.extend(c.to_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+impl<'a> Struct<'a> {
+    fn parse(input: &'a DeriveInput) -> Result<Struct<'a>> {
+        let fields = match input.data {
+            Data::Struct(ref data) => &data.fields,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "rusqlite derives only support structs",
+                ))
+            }
+        };
+
+        let mut table = snake_case(&input.ident);
+        let mut by_index = false;
+        for meta in rusqlite_attrs(&input.attrs)? {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("table") => {
+                    table = lit_str(&nv.lit)?;
+                }
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("by_index") => {
+                    by_index = true;
+                }
+                meta => return Err(Error::new_spanned(meta, "unknown rusqlite attribute")),
+            }
+        }
+        if let Fields::Unnamed(_) = *fields {
+            by_index = true;
+        }
+
+        let mut parsed = Vec::new();
+        for (i, field) in fields.iter().enumerate() {
+            let (member, mut column) = match field.ident {
+                Some(ref ident) => (Member::Named(ident.clone()), ident.to_string()),
+                None => (Member::Unnamed(Index::from(i)), format!("c{}", i)),
+            };
+            let mut index = i;
+            let mut primary_key = false;
+            for meta in rusqlite_attrs(&field.attrs)? {
+                match meta {
+                    NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("rename") => {
+                        column = lit_str(&nv.lit)?;
+                    }
+                    NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("index") => {
+                        if !by_index {
+                            return Err(Error::new_spanned(
+                                nv,
+                                "`index` needs `#[rusqlite(by_index)]` on the struct",
+                            ));
+                        }
+                        index = lit_index(&nv.lit)?;
+                    }
+                    NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("primary_key") => {
+                        primary_key = true;
+                    }
+                    meta => return Err(Error::new_spanned(meta, "unknown rusqlite attribute")),
+                }
+            }
+            parsed.push(Field {
+                member,
+                ty: &field.ty,
+                column,
+                index,
+                primary_key,
+            });
+        }
+
+        Ok(Struct {
+            input,
+            table,
+            by_index,
+            fields: parsed,
+        })
+    }
+}
+
+fn from_row(s: &Struct<'_>) -> TokenStream2 {
+    let ident = &s.input.ident;
+    let mut generics = s.input.generics.clone();
+    generics.params.insert(0, parse_quote!('stmt));
+    generics.params.insert(0, parse_quote!('__row));
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let (_, ty_generics, where_clause) = s.input.generics.split_for_impl();
+
+    let fields = s.fields.iter().map(|field| {
+        let member = &field.member;
+        if s.by_index {
+            let index = field.index;
+            quote!(#member: row.get(#index)?)
+        } else {
+            let column = LitStr::new(&field.column, Span::call_site());
+            quote!(#member: row.get(#column)?)
+        }
+    });
+
+    quote! {
+        impl #impl_generics ::std::convert::TryFrom<&'__row ::rusqlite::Row<'stmt>>
+            for #ident #ty_generics #where_clause
+        {
+            type Error = ::rusqlite::Error;
+
+            fn try_from(row: &'__row ::rusqlite::Row<'stmt>) -> ::rusqlite::Result<Self> {
+                Ok(#ident { #(#fields),* })
+            }
+        }
+    }
+}
+
+fn to_params(s: &Struct<'_>) -> TokenStream2 {
+    let ident = &s.input.ident;
+    let (impl_generics, ty_generics, where_clause) = s.input.generics.split_for_impl();
+
+    let members: Vec<_> = s.fields.iter().map(|field| &field.member).collect();
+    let names = s
+        .fields
+        .iter()
+        .map(|field| LitStr::new(&format!(":{}", field.column), Span::call_site()));
+
+    quote! {
+        impl #impl_generics ::rusqlite::ToParams for #ident #ty_generics #where_clause {
+            fn to_params(&self) -> ::std::vec::Vec<&dyn ::rusqlite::ToSql> {
+                vec![#(&self.#members as &dyn ::rusqlite::ToSql),*]
+            }
+
+            fn to_named_params(&self) -> ::std::vec::Vec<(&'static str, &dyn ::rusqlite::ToSql)> {
+                vec![#((#names, &self.#members as &dyn ::rusqlite::ToSql)),*]
+            }
+        }
+    }
+}
+
+fn table(s: &Struct<'_>) -> TokenStream2 {
+    let ident = &s.input.ident;
+    let (impl_generics, ty_generics, where_clause) = s.input.generics.split_for_impl();
+
+    let table = LitStr::new(&s.table, Span::call_site());
+    let quoted_table = LitStr::new(&quoted_identifier(&s.table), Span::call_site());
+    let columns: Vec<_> = s
+        .fields
+        .iter()
+        .map(|field| LitStr::new(&field.column, Span::call_site()))
+        .collect();
+    let definitions = s.fields.iter().zip(&columns).map(|(field, column)| {
+        let ty = field.ty;
+        let primary_key = field.primary_key;
+        quote!(::rusqlite::types::column_definition::<#ty>(#column, #primary_key))
+    });
+
+    quote! {
+        impl #impl_generics ::rusqlite::Table for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #table;
+            const COLUMNS: &'static [&'static str] = &[#(#columns),*];
+
+            fn create_table_sql() -> ::std::string::String {
+                let columns: ::std::vec::Vec<::std::string::String> = vec![#(#definitions),*];
+                format!("CREATE TABLE {} ({})", #quoted_table, columns.join(", "))
+            }
+        }
+    }
+}
@@ -0,0 +1,627 @@
+//! Export query results as Apache Arrow record batches.
+//!
+//! Column types are inferred from the values returned by the query: the
+//! first non-null value of each column picks its type and the declared type
+//! is only used for columns that are entirely `NULL`. Columns mixing integers
+//! and reals become `Float64`, any other mix falls back to `Utf8`.
+//!
+//! Batches can be serialized with `write_ipc_stream` into the Arrow IPC
+//! streaming format, which Arrow implementations read with their
+//! `RecordBatchStreamReader` (`tableFromIPC` in Arrow JS).
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! fn totals(conn: &Connection) -> Result<Vec<u8>> {
+//!     let mut stmt = conn.prepare("SELECT day, sum(amount) FROM sales GROUP BY day")?;
+//!     stmt.query_arrow_ipc(NO_PARAMS, 1024)
+//! }
+//! ```
+use std::cmp::Reverse;
+
+use crate::types::{ToSql, Value, ValueRef};
+use crate::{Result, Statement};
+
+/// The Arrow type of a column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataType {
+    /// Signed 64-bit integers.
+    Int64,
+    /// Double precision floating point numbers.
+    Float64,
+    /// UTF-8 strings.
+    Utf8,
+    /// Variable length binary data.
+    Binary,
+}
+
+impl DataType {
+    fn of(value: ValueRef<'_>) -> Option<DataType> {
+        match value {
+            ValueRef::Null => None,
+            ValueRef::Integer(_) => Some(DataType::Int64),
+            ValueRef::Real(_) => Some(DataType::Float64),
+            ValueRef::Text(_) => Some(DataType::Utf8),
+            ValueRef::Blob(_) => Some(DataType::Binary),
+        }
+    }
+
+    /// Map a declared column type to a data type using SQLite's column
+    /// affinity rules.
+    fn from_decl_type(decl_type: &str) -> Option<DataType> {
+        let decl_type = decl_type.to_ascii_uppercase();
+        if decl_type.contains("INT") {
+            Some(DataType::Int64)
+        } else if decl_type.contains("CHAR")
+            || decl_type.contains("CLOB")
+            || decl_type.contains("TEXT")
+        {
+            Some(DataType::Utf8)
+        } else if decl_type.contains("BLOB") {
+            Some(DataType::Binary)
+        } else if decl_type.contains("REAL")
+            || decl_type.contains("FLOA")
+            || decl_type.contains("DOUB")
+        {
+            Some(DataType::Float64)
+        } else {
+            None
+        }
+    }
+
+    fn unify(self, other: DataType) -> DataType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => {
+                DataType::Float64
+            }
+            _ => DataType::Utf8,
+        }
+    }
+}
+
+/// A named, nullable column of a `Schema`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// The column name.
+    pub name: String,
+    /// The column type.
+    pub data_type: DataType,
+}
+
+/// The columns shared by every batch of a query result.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schema {
+    /// The columns, in result order.
+    pub fields: Vec<Field>,
+}
+
+/// The values of one column of a `RecordBatch`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArrayData {
+    Int64(Vec<Option<i64>>),
+    Float64(Vec<Option<f64>>),
+    Utf8(Vec<Option<String>>),
+    Binary(Vec<Option<Vec<u8>>>),
+}
+
+impl ArrayData {
+    fn from_column(data_type: DataType, rows: &[Vec<Value>], col: usize) -> ArrayData {
+        let values = rows.iter().map(|row| &row[col]);
+        match data_type {
+            DataType::Int64 => ArrayData::Int64(
+                values
+                    .map(|v| match *v {
+                        Value::Integer(i) => Some(i),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            DataType::Float64 => ArrayData::Float64(
+                values
+                    .map(|v| match *v {
+                        Value::Integer(i) => Some(i as f64),
+                        Value::Real(f) => Some(f),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            DataType::Utf8 => ArrayData::Utf8(
+                values
+                    .map(|v| match *v {
+                        Value::Null => None,
+                        Value::Integer(i) => Some(i.to_string()),
+                        Value::Real(f) => Some(f.to_string()),
+                        Value::Text(ref s) => Some(s.clone()),
+                        Value::Blob(ref b) => Some(String::from_utf8_lossy(b).into_owned()),
+                    })
+                    .collect(),
+            ),
+            DataType::Binary => ArrayData::Binary(
+                values
+                    .map(|v| match *v {
+                        Value::Blob(ref b) => Some(b.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Returns the number of values in the column.
+    pub fn len(&self) -> usize {
+        match *self {
+            ArrayData::Int64(ref v) => v.len(),
+            ArrayData::Float64(ref v) => v.len(),
+            ArrayData::Utf8(ref v) => v.len(),
+            ArrayData::Binary(ref v) => v.len(),
+        }
+    }
+
+    /// Returns `true` if the column has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of `NULL` values in the column.
+    pub fn null_count(&self) -> usize {
+        (0..self.len()).filter(|&i| !self.is_valid(i)).count()
+    }
+
+    fn is_valid(&self, i: usize) -> bool {
+        match *self {
+            ArrayData::Int64(ref v) => v[i].is_some(),
+            ArrayData::Float64(ref v) => v[i].is_some(),
+            ArrayData::Utf8(ref v) => v[i].is_some(),
+            ArrayData::Binary(ref v) => v[i].is_some(),
+        }
+    }
+}
+
+/// A slice of a query result stored column by column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordBatch {
+    schema: Schema,
+    columns: Vec<ArrayData>,
+    num_rows: usize,
+}
+
+impl RecordBatch {
+    /// Returns the schema of the batch.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Returns the columns of the batch.
+    pub fn columns(&self) -> &[ArrayData] {
+        &self.columns
+    }
+
+    /// Returns the column at `idx`.
+    pub fn column(&self, idx: usize) -> &ArrayData {
+        &self.columns[idx]
+    }
+
+    /// Returns the number of rows in the batch.
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+}
+
+/// A value stored in a flatbuffer table slot.
+enum Fb {
+    Bool(bool),
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Str(String),
+    Table(Vec<Option<Fb>>),
+    Tables(Vec<Fb>),
+    /// A vector of structs made of two longs (`FieldNode` and `Buffer`).
+    Structs(Vec<[i64; 2]>),
+}
+
+impl Fb {
+    fn inline_size(&self) -> usize {
+        match *self {
+            Fb::Bool(_) | Fb::U8(_) => 1,
+            Fb::I16(_) => 2,
+            Fb::I32(_) => 4,
+            Fb::I64(_) => 8,
+            _ => 4,
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        match *self {
+            Fb::Bool(_) | Fb::U8(_) | Fb::I16(_) | Fb::I32(_) | Fb::I64(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A minimal flatbuffer serializer. Objects are written front to back, so
+/// every offset points forward and every vtable sits just before its table.
+struct FbWriter {
+    buf: Vec<u8>,
+}
+
+impl FbWriter {
+    fn finish(root: &Fb) -> Vec<u8> {
+        let mut writer = FbWriter { buf: vec![0; 4] };
+        let target = writer.write_object(root);
+        writer.patch(0, target);
+        writer.pad_to(8);
+        writer.buf
+    }
+
+    fn pad_to(&mut self, align: usize) {
+        while self.buf.len() % align != 0 {
+            self.buf.push(0);
+        }
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        let offset = (target - at) as u32;
+        self.buf[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    fn write_scalar(&mut self, at: usize, value: &Fb) {
+        let bytes = match *value {
+            Fb::Bool(b) => vec![b as u8],
+            Fb::U8(v) => vec![v],
+            Fb::I16(v) => v.to_le_bytes().to_vec(),
+            Fb::I32(v) => v.to_le_bytes().to_vec(),
+            Fb::I64(v) => v.to_le_bytes().to_vec(),
+            _ => unreachable!("offsets are patched once their target is written"),
+        };
+        self.buf[at..at + bytes.len()].copy_from_slice(&bytes);
+    }
+
+    /// Write an object referenced by offset and return its position.
+    fn write_object(&mut self, value: &Fb) -> usize {
+        match *value {
+            Fb::Str(ref s) => {
+                self.pad_to(4);
+                let pos = self.buf.len();
+                self.buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                self.buf.extend_from_slice(s.as_bytes());
+                self.buf.push(0);
+                pos
+            }
+            Fb::Table(ref slots) => self.write_table(slots),
+            Fb::Tables(ref tables) => {
+                self.pad_to(4);
+                let pos = self.buf.len();
+                self.buf
+                    .extend_from_slice(&(tables.len() as u32).to_le_bytes());
+                let first = self.buf.len();
+                self.buf.resize(first + 4 * tables.len(), 0);
+                for (i, table) in tables.iter().enumerate() {
+                    let target = self.write_object(table);
+                    self.patch(first + 4 * i, target);
+                }
+                pos
+            }
+            Fb::Structs(ref structs) => {
+                // The elements, which follow the length, must be 8-byte aligned.
+                while (self.buf.len() + 4) % 8 != 0 {
+                    self.buf.push(0);
+                }
+                let pos = self.buf.len();
+                self.buf
+                    .extend_from_slice(&(structs.len() as u32).to_le_bytes());
+                for s in structs {
+                    self.buf.extend_from_slice(&s[0].to_le_bytes());
+                    self.buf.extend_from_slice(&s[1].to_le_bytes());
+                }
+                pos
+            }
+            _ => unreachable!("scalars are stored inline"),
+        }
+    }
+
+    fn write_table(&mut self, slots: &[Option<Fb>]) -> usize {
+        // Place the largest fields first, right after the vtable offset, so
+        // that every field is aligned to its size.
+        let mut order: Vec<usize> = (0..slots.len()).filter(|&i| slots[i].is_some()).collect();
+        order.sort_by_key(|&i| Reverse(slots[i].as_ref().unwrap().inline_size()));
+        let mut field_offsets = vec![0u16; slots.len()];
+        let mut size = 4;
+        for &i in &order {
+            let field_size = slots[i].as_ref().unwrap().inline_size();
+            size = (size + field_size - 1) / field_size * field_size;
+            field_offsets[i] = size as u16;
+            size += field_size;
+        }
+
+        self.pad_to(2);
+        let vtable = self.buf.len();
+        self.buf
+            .extend_from_slice(&(4 + 2 * slots.len() as u16).to_le_bytes());
+        self.buf.extend_from_slice(&(size as u16).to_le_bytes());
+        for offset in &field_offsets {
+            self.buf.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        self.pad_to(8);
+        let table = self.buf.len();
+        self.buf
+            .extend_from_slice(&((table - vtable) as i32).to_le_bytes());
+        self.buf.resize(table + size, 0);
+        for &i in &order {
+            let value = slots[i].as_ref().unwrap();
+            if value.is_scalar() {
+                self.write_scalar(table + field_offsets[i] as usize, value);
+            }
+        }
+        for &i in &order {
+            let value = slots[i].as_ref().unwrap();
+            if !value.is_scalar() {
+                let target = self.write_object(value);
+                self.patch(table + field_offsets[i] as usize, target);
+            }
+        }
+        table
+    }
+}
+
+// Values from the Arrow flatbuffer schemas (Schema.fbs and Message.fbs).
+const METADATA_VERSION_V4: i16 = 3;
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_BINARY: u8 = 4;
+const TYPE_UTF8: u8 = 5;
+const PRECISION_DOUBLE: i16 = 2;
+const CONTINUATION_MARKER: u32 = 0xFFFF_FFFF;
+
+fn field_to_fb(field: &Field) -> Fb {
+    let (type_type, type_table) = match field.data_type {
+        DataType::Int64 => (
+            TYPE_INT,
+            Fb::Table(vec![Some(Fb::I32(64)), Some(Fb::Bool(true))]),
+        ),
+        DataType::Float64 => (
+            TYPE_FLOATING_POINT,
+            Fb::Table(vec![Some(Fb::I16(PRECISION_DOUBLE))]),
+        ),
+        DataType::Binary => (TYPE_BINARY, Fb::Table(vec![])),
+        DataType::Utf8 => (TYPE_UTF8, Fb::Table(vec![])),
+    };
+    Fb::Table(vec![
+        Some(Fb::Str(field.name.clone())),
+        Some(Fb::Bool(true)),
+        Some(Fb::U8(type_type)),
+        Some(type_table),
+        None,
+        Some(Fb::Tables(vec![])),
+    ])
+}
+
+fn message_to_fb(header_type: u8, header: Fb, body_length: usize) -> Fb {
+    Fb::Table(vec![
+        Some(Fb::I16(METADATA_VERSION_V4)),
+        Some(Fb::U8(header_type)),
+        Some(header),
+        Some(Fb::I64(body_length as i64)),
+    ])
+}
+
+/// The body of a record batch message and the location of its buffers.
+#[derive(Default)]
+struct Body {
+    bytes: Vec<u8>,
+    buffers: Vec<[i64; 2]>,
+}
+
+impl Body {
+    fn push(&mut self, data: &[u8]) {
+        let offset = self.bytes.len();
+        self.bytes.extend_from_slice(data);
+        while self.bytes.len() % 8 != 0 {
+            self.bytes.push(0);
+        }
+        self.buffers.push([offset as i64, data.len() as i64]);
+    }
+
+    fn push_validity(&mut self, column: &ArrayData) {
+        if column.null_count() == 0 {
+            self.push(&[]);
+            return;
+        }
+        let mut bitmap = vec![0u8; (column.len() + 7) / 8];
+        for i in 0..column.len() {
+            if column.is_valid(i) {
+                bitmap[i / 8] |= 1 << (i % 8);
+            }
+        }
+        self.push(&bitmap);
+    }
+
+    fn push_variable<'a, I>(&mut self, values: I)
+    where
+        I: Iterator<Item = Option<&'a [u8]>>,
+    {
+        let mut offsets = vec![0u8; 4];
+        let mut data = Vec::new();
+        for value in values {
+            data.extend_from_slice(value.unwrap_or(&[]));
+            offsets.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        }
+        self.push(&offsets);
+        self.push(&data);
+    }
+
+    fn push_column(&mut self, column: &ArrayData) {
+        self.push_validity(column);
+        match *column {
+            ArrayData::Int64(ref values) => {
+                let mut data = Vec::with_capacity(8 * values.len());
+                for v in values {
+                    data.extend_from_slice(&v.unwrap_or(0).to_le_bytes());
+                }
+                self.push(&data);
+            }
+            ArrayData::Float64(ref values) => {
+                let mut data = Vec::with_capacity(8 * values.len());
+                for v in values {
+                    data.extend_from_slice(&v.unwrap_or(0f64).to_bits().to_le_bytes());
+                }
+                self.push(&data);
+            }
+            ArrayData::Utf8(ref values) => {
+                self.push_variable(values.iter().map(|v| v.as_ref().map(|s| s.as_bytes())))
+            }
+            ArrayData::Binary(ref values) => {
+                self.push_variable(values.iter().map(|v| v.as_ref().map(|b| &b[..])))
+            }
+        }
+    }
+}
+
+fn write_message(out: &mut Vec<u8>, message: &Fb, body: &[u8]) {
+    let metadata = FbWriter::finish(message);
+    out.extend_from_slice(&CONTINUATION_MARKER.to_le_bytes());
+    out.extend_from_slice(&(metadata.len() as i32).to_le_bytes());
+    out.extend_from_slice(&metadata);
+    out.extend_from_slice(body);
+}
+
+/// Serialize `schema` and `batches` in the Arrow IPC streaming format.
+pub fn write_ipc_stream(schema: &Schema, batches: &[RecordBatch]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let fields = schema.fields.iter().map(field_to_fb).collect();
+    let schema = Fb::Table(vec![Some(Fb::I16(0)), Some(Fb::Tables(fields))]);
+    write_message(&mut out, &message_to_fb(HEADER_SCHEMA, schema, 0), &[]);
+
+    for batch in batches {
+        let mut body = Body::default();
+        let mut nodes = Vec::with_capacity(batch.columns.len());
+        for column in &batch.columns {
+            nodes.push([column.len() as i64, column.null_count() as i64]);
+            body.push_column(column);
+        }
+        let header = Fb::Table(vec![
+            Some(Fb::I64(batch.num_rows as i64)),
+            Some(Fb::Structs(nodes)),
+            Some(Fb::Structs(body.buffers)),
+        ]);
+        let message = message_to_fb(HEADER_RECORD_BATCH, header, body.bytes.len());
+        write_message(&mut out, &message, &body.bytes);
+    }
+
+    // End of stream.
+    out.extend_from_slice(&CONTINUATION_MARKER.to_le_bytes());
+    out.extend_from_slice(&0i32.to_le_bytes());
+    out
+}
+
+impl Statement<'_> {
+    /// Execute the prepared statement and return its result as Arrow record
+    /// batches of at most `batch_size` rows, along with their schema. A
+    /// `batch_size` of `0` returns a single batch.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails or the query fails.
+    pub fn query_arrow_with_schema<P>(
+        &mut self,
+        params: P,
+        batch_size: usize,
+    ) -> Result<(Schema, Vec<RecordBatch>)>
+    where
+        P: IntoIterator,
+        P::Item: ToSql,
+    {
+        let columns: Vec<(String, Option<DataType>)> = self
+            .columns()
+            .iter()
+            .map(|c| {
+                (
+                    c.name().to_owned(),
+                    c.decl_type().and_then(DataType::from_decl_type),
+                )
+            })
+            .collect();
+        let mut types: Vec<Option<DataType>> = vec![None; columns.len()];
+        let mut rows = Vec::new();
+        {
+            let mut result = self.query(params)?;
+            while let Some(row) = result.next()? {
+                let mut values = Vec::with_capacity(columns.len());
+                for (i, ty) in types.iter_mut().enumerate() {
+                    let value = row.get_raw(i);
+                    if let Some(value_type) = DataType::of(value) {
+                        *ty = Some(ty.map_or(value_type, |ty| ty.unify(value_type)));
+                    }
+                    values.push(Value::from(value));
+                }
+                rows.push(values);
+            }
+        }
+
+        let schema = Schema {
+            fields: columns
+                .into_iter()
+                .zip(types)
+                .map(|((name, decl_type), ty)| Field {
+                    name,
+                    data_type: ty.or(decl_type).unwrap_or(DataType::Utf8),
+                })
+                .collect(),
+        };
+        let batch_size = if batch_size == 0 {
+            rows.len().max(1)
+        } else {
+            batch_size
+        };
+        let batches = rows
+            .chunks(batch_size)
+            .map(|chunk| RecordBatch {
+                schema: schema.clone(),
+                columns: schema
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| ArrayData::from_column(field.data_type, chunk, i))
+                    .collect(),
+                num_rows: chunk.len(),
+            })
+            .collect();
+        Ok((schema, batches))
+    }
+
+    /// Execute the prepared statement and return its result as Arrow record
+    /// batches of at most `batch_size` rows.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails or the query fails.
+    pub fn query_arrow<P>(&mut self, params: P, batch_size: usize) -> Result<Vec<RecordBatch>>
+    where
+        P: IntoIterator,
+        P::Item: ToSql,
+    {
+        self.query_arrow_with_schema(params, batch_size)
+            .map(|(_, batches)| batches)
+    }
+
+    /// Execute the prepared statement and serialize its result in the Arrow
+    /// IPC streaming format.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if binding parameters fails or the query fails.
+    pub fn query_arrow_ipc<P>(&mut self, params: P, batch_size: usize) -> Result<Vec<u8>>
+    where
+        P: IntoIterator,
+        P::Item: ToSql,
+    {
+        let (schema, batches) = self.query_arrow_with_schema(params, batch_size)?;
+        Ok(write_ipc_stream(&schema, &batches))
+    }
+}
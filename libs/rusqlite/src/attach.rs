@@ -0,0 +1,279 @@
+//! Attach and detach databases, and list the schemas of a connection.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::ptr;
+
+use crate::ffi;
+use crate::{Connection, DatabaseName, Error, Result};
+
+/// Where `Connection::attach` reads the database from.
+#[derive(Clone, Copy, Debug)]
+pub enum AttachSource<'a> {
+    /// A file name, or a `file:` URI.
+    Path(&'a Path),
+    /// A new, empty in-memory database.
+    Memory,
+    /// An in-memory database holding a copy of a serialized database image,
+    /// such as the bytes of a database file or of `Connection::serialize`.
+    #[cfg(feature = "deserialize")]
+    Bytes(&'a [u8]),
+    /// The file `path` opened through the registered VFS `vfs`.
+    Vfs {
+        /// The file name.
+        path: &'a str,
+        /// The VFS name.
+        vfs: &'a str,
+    },
+}
+
+/// A schema of a connection, from `PRAGMA database_list`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatabaseInfo {
+    /// The schema name: `main`, `temp` or the name given to `ATTACH`.
+    pub name: String,
+    /// The database file, `None` for in-memory and temporary databases.
+    pub file: Option<String>,
+    /// The VFS the database was opened with, `None` if SQLite does not
+    /// report one, as for in-memory databases.
+    pub vfs: Option<String>,
+    /// Whether the database is read-only.
+    pub readonly: bool,
+}
+
+/// A database attached by `Connection::attach`. It is detached when the
+/// guard is dropped; use `detach` to see whether that fails.
+#[derive(Debug)]
+pub struct AttachedDb<'conn> {
+    conn: &'conn Connection,
+    name: String,
+    detached: bool,
+}
+
+impl AttachedDb<'_> {
+    /// The schema name, to pass to `blob_open`, the pragma helpers and
+    /// other APIs that take a `DatabaseName`.
+    pub fn name(&self) -> DatabaseName<'_> {
+        DatabaseName::Attached(&self.name)
+    }
+
+    /// Detach the database now.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the database is in use, for example by a
+    /// running statement or an open transaction.
+    pub fn detach(mut self) -> Result<()> {
+        self.detach_()
+    }
+
+    fn detach_(&mut self) -> Result<()> {
+        if self.detached {
+            return Ok(());
+        }
+        self.detached = true;
+        self.conn.detach(&self.name)
+    }
+}
+
+impl Drop for AttachedDb<'_> {
+    fn drop(&mut self) {
+        let _ = self.detach_();
+    }
+}
+
+/// `main` and `temp` always exist and cannot be attached or detached.
+fn check_attached_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.eq_ignore_ascii_case("main") || name.eq_ignore_ascii_case("temp") {
+        Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_MISUSE),
+            Some(format!("cannot attach or detach \"{}\"", name)),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Percent-encode the characters that end or escape a part of a `file:`
+/// URI.
+fn uri_encode(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '%' | '?' | '#' | '&' | '=' => out.push_str(&format!("%{:02X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+}
+
+impl Connection {
+    /// Attach `source` as the schema `name`, as `ATTACH DATABASE` does. The
+    /// database stays attached until the returned guard is dropped.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{AttachSource, Connection, Result, NO_PARAMS};
+    /// # use std::path::Path;
+    /// fn copy_archive(conn: &Connection) -> Result<usize> {
+    ///     let archive = conn.attach("archive", AttachSource::Path(Path::new("archive.db")))?;
+    ///     let copied = conn.execute("INSERT INTO log SELECT * FROM archive.log", NO_PARAMS)?;
+    ///     archive.detach()?;
+    ///     Ok(copied)
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `name` is `main` or `temp` or is already in
+    /// use, if the database cannot be opened, or if the VFS is not
+    /// registered.
+    pub fn attach(&self, name: &str, source: AttachSource<'_>) -> Result<AttachedDb<'_>> {
+        check_attached_name(name)?;
+        let file = match source {
+            AttachSource::Path(path) => path.to_string_lossy().into_owned(),
+            AttachSource::Memory => ":memory:".to_owned(),
+            #[cfg(feature = "deserialize")]
+            AttachSource::Bytes(_) => ":memory:".to_owned(),
+            AttachSource::Vfs { path, vfs } => {
+                let mut uri = String::from("file:");
+                uri_encode(path, &mut uri);
+                uri.push_str("?vfs=");
+                uri_encode(vfs, &mut uri);
+                uri
+            }
+        };
+        self.execute("ATTACH DATABASE ?1 AS ?2", &[file.as_str(), name])?;
+        let attached = AttachedDb {
+            conn: self,
+            name: name.to_owned(),
+            detached: false,
+        };
+        #[cfg(feature = "deserialize")]
+        {
+            if let AttachSource::Bytes(bytes) = source {
+                self.deserialize(attached.name(), bytes)?;
+            }
+        }
+        Ok(attached)
+    }
+
+    /// Detach the schema `name`, as `DETACH DATABASE` does.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `name` is `main` or `temp`, is not attached or
+    /// is in use.
+    pub fn detach(&self, name: &str) -> Result<()> {
+        check_attached_name(name)?;
+        self.execute("DETACH DATABASE ?1", &[name]).map(|_| ())
+    }
+
+    /// Returns the schemas of the connection: `main`, `temp` once it is
+    /// used, and the attached databases, in the order of
+    /// `PRAGMA database_list`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn databases(&self) -> Result<Vec<DatabaseInfo>> {
+        let mut list = Vec::new();
+        self.pragma_query(None, "database_list", |row| {
+            let file: String = row.get(2)?;
+            list.push((row.get::<_, String>(1)?, file));
+            Ok(())
+        })?;
+        let mut databases = Vec::with_capacity(list.len());
+        for (name, file) in list {
+            let c_name = DatabaseName::Attached(&name).to_cstring()?;
+            let db = self.db.borrow();
+            let mut z_vfs: *mut c_char = ptr::null_mut();
+            let (vfs, readonly) = unsafe {
+                let r = ffi::sqlite3_file_control(
+                    db.db(),
+                    c_name.as_ptr(),
+                    ffi::SQLITE_FCNTL_VFSNAME,
+                    &mut z_vfs as *mut *mut c_char as *mut c_void,
+                );
+                let vfs = if r == ffi::SQLITE_OK && !z_vfs.is_null() {
+                    let vfs = CStr::from_ptr(z_vfs).to_string_lossy().into_owned();
+                    ffi::sqlite3_free(z_vfs as *mut c_void);
+                    Some(vfs)
+                } else {
+                    None
+                };
+                (vfs, ffi::sqlite3_db_readonly(db.db(), c_name.as_ptr()) == 1)
+            };
+            databases.push(DatabaseInfo {
+                name,
+                file: if file.is_empty() { None } else { Some(file) },
+                vfs,
+                readonly,
+            });
+        }
+        Ok(databases)
+    }
+}
+
+#[cfg(feature = "deserialize")]
+impl Connection {
+    /// Returns the database `schema` as the bytes its database file would
+    /// hold, for example to store an in-memory database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if there is no such schema or memory runs out.
+    pub fn serialize(&self, schema: DatabaseName<'_>) -> Result<Vec<u8>> {
+        let c_schema = schema.to_cstring()?;
+        let db = self.db.borrow();
+        let mut size: ffi::sqlite3_int64 = 0;
+        unsafe {
+            let data = ffi::sqlite3_serialize(db.db(), c_schema.as_ptr(), &mut size, 0);
+            if data.is_null() {
+                return Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_ERROR),
+                    Some("cannot serialize database".to_owned()),
+                ));
+            }
+            let bytes = std::slice::from_raw_parts(data, size as usize).to_vec();
+            ffi::sqlite3_free(data as *mut c_void);
+            Ok(bytes)
+        }
+    }
+
+    /// Replace the database `schema` with an in-memory database holding a
+    /// copy of `bytes`, a serialized database image. The copy can grow; the
+    /// database is closed when `schema` is detached or the connection is
+    /// closed.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is in use or memory runs out. Invalid
+    /// images are only detected when the database is read.
+    pub fn deserialize(&self, schema: DatabaseName<'_>, bytes: &[u8]) -> Result<()> {
+        let c_schema = schema.to_cstring()?;
+        let mut db = self.db.borrow_mut();
+        unsafe {
+            // SQLite takes ownership of the copy, and frees it on failure
+            let data = ffi::sqlite3_malloc64(bytes.len() as u64) as *mut u8;
+            if data.is_null() && !bytes.is_empty() {
+                return Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_NOMEM),
+                    None,
+                ));
+            }
+            if !bytes.is_empty() {
+                ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+            }
+            let r = ffi::sqlite3_deserialize(
+                db.db(),
+                c_schema.as_ptr(),
+                data,
+                bytes.len() as ffi::sqlite3_int64,
+                bytes.len() as ffi::sqlite3_int64,
+                (ffi::SQLITE_DESERIALIZE_FREEONCLOSE | ffi::SQLITE_DESERIALIZE_RESIZEABLE) as u32,
+            );
+            db.decode_result(r)
+        }
+    }
+}
@@ -0,0 +1,510 @@
+//! A content-addressed store for large payloads, built on the incremental
+//! [`blob`](../blob/index.html) API.
+//!
+//! Payloads are split into fixed-size chunks stored once per distinct
+//! content (keyed by SHA-256), so storing the same file twice, or two files
+//! that share chunks, only costs the space of the distinct chunks. Chunks
+//! are reference counted; deleting a payload releases its references and
+//! `gc` removes the chunks nothing refers to any more.
+//!
+//! The store keeps its data in three tables, created on demand:
+//! `blobstore_chunk`, `blobstore_blob` and `blobstore_blob_chunk`.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! # use rusqlite::blobstore::BlobStore;
+//! # use std::io::Read;
+//! fn roundtrip(conn: &Connection, payload: &[u8]) -> Result<Vec<u8>> {
+//!     let store = BlobStore::new(conn)?;
+//!     let id = store.put(payload)?;
+//!     let mut data = Vec::new();
+//!     store.get(id)?.read_to_end(&mut data).unwrap();
+//!     store.delete(id)?;
+//!     store.gc()?;
+//!     Ok(data)
+//! }
+//! ```
+use std::cmp::min;
+use std::io;
+
+use crate::blob::{Blob, ZeroBlob};
+use crate::{params, Connection, DatabaseName, Error, OptionalExtension, Result, NO_PARAMS};
+
+/// Chunk size used by `BlobStore::new`: 256 KiB.
+pub const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+
+/// The id of a payload in a `BlobStore`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlobId(pub i64);
+
+/// A content-addressed, chunked store of payloads. See the module
+/// documentation.
+pub struct BlobStore<'conn> {
+    conn: &'conn Connection,
+    chunk_size: usize,
+}
+
+impl<'conn> BlobStore<'conn> {
+    /// Open the store on `conn`, creating its tables if needed, with chunks
+    /// of `DEFAULT_CHUNK_SIZE` bytes.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the tables cannot be created.
+    pub fn new(conn: &'conn Connection) -> Result<BlobStore<'conn>> {
+        BlobStore::with_chunk_size(conn, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Like `new`, with chunks of `chunk_size` bytes. The chunk size only
+    /// applies to payloads stored from now on; payloads stored with another
+    /// size stay readable but share no chunks with them.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the tables cannot be created or `chunk_size` is
+    /// 0 or larger than a blob can be.
+    pub fn with_chunk_size(conn: &'conn Connection, chunk_size: usize) -> Result<BlobStore<'conn>> {
+        if chunk_size == 0 || chunk_size > i32::max_value() as usize {
+            return Err(Error::SqliteFailure(
+                crate::ffi::Error::new(crate::ffi::SQLITE_MISUSE),
+                Some(format!("invalid chunk size {}", chunk_size)),
+            ));
+        }
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS blobstore_chunk (
+                 id INTEGER PRIMARY KEY,
+                 hash BLOB NOT NULL UNIQUE,
+                 refs INTEGER NOT NULL,
+                 data BLOB NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS blobstore_blob (
+                 id INTEGER PRIMARY KEY,
+                 size INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS blobstore_blob_chunk (
+                 blob_id INTEGER NOT NULL,
+                 seq INTEGER NOT NULL,
+                 chunk_id INTEGER NOT NULL,
+                 PRIMARY KEY (blob_id, seq)
+             ) WITHOUT ROWID;",
+        )?;
+        Ok(BlobStore { conn, chunk_size })
+    }
+
+    /// Store everything `reader` yields and return the id of the payload.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if reading fails (as `Error::ToSqlConversionFailure`)
+    /// or the underlying SQLite calls fail; nothing is stored then.
+    pub fn put<R: io::Read>(&self, mut reader: R) -> Result<BlobId> {
+        self.in_savepoint(|| {
+            self.conn
+                .execute("INSERT INTO blobstore_blob (size) VALUES (0)", NO_PARAMS)?;
+            let id = self.conn.last_insert_rowid();
+            let mut buf = vec![0; self.chunk_size];
+            let mut size = 0;
+            let mut seq = 0;
+            loop {
+                let n = read_full(&mut reader, &mut buf)
+                    .map_err(|err| Error::ToSqlConversionFailure(Box::new(err)))?;
+                if n == 0 {
+                    break;
+                }
+                let chunk_id = self.put_chunk(&buf[..n])?;
+                self.conn
+                    .prepare_cached(
+                        "INSERT INTO blobstore_blob_chunk (blob_id, seq, chunk_id) VALUES (?, ?, ?)",
+                    )?
+                    .execute(params![id, seq, chunk_id])?;
+                size += n as i64;
+                seq += 1;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            self.conn.execute(
+                "UPDATE blobstore_blob SET size = ? WHERE id = ?",
+                params![size, id],
+            )?;
+            Ok(BlobId(id))
+        })
+    }
+
+    /// Store one chunk, or add a reference to an identical one, and return
+    /// its row id.
+    fn put_chunk(&self, data: &[u8]) -> Result<i64> {
+        let hash = sha256(data);
+        let existing = self
+            .conn
+            .prepare_cached("SELECT id FROM blobstore_chunk WHERE hash = ?")?
+            .query_row(&[&hash[..]], |row| row.get(0))
+            .optional()?;
+        if let Some(chunk_id) = existing {
+            self.conn
+                .prepare_cached("UPDATE blobstore_chunk SET refs = refs + 1 WHERE id = ?")?
+                .execute(&[chunk_id])?;
+            return Ok(chunk_id);
+        }
+        self.conn
+            .prepare_cached("INSERT INTO blobstore_chunk (hash, refs, data) VALUES (?, 1, ?)")?
+            .execute(params![&hash[..], ZeroBlob(data.len() as i32)])?;
+        let chunk_id = self.conn.last_insert_rowid();
+        let mut blob = self.open_chunk(chunk_id, false)?;
+        io::Write::write_all(&mut blob, data)
+            .map_err(|err| Error::ToSqlConversionFailure(Box::new(err)))?;
+        blob.close()?;
+        Ok(chunk_id)
+    }
+
+    fn open_chunk(&self, chunk_id: i64, read_only: bool) -> Result<Blob<'conn>> {
+        self.conn.blob_open(
+            DatabaseName::Main,
+            "blobstore_chunk",
+            "data",
+            chunk_id,
+            read_only,
+        )
+    }
+
+    /// The size in bytes of the payload `id`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::QueryReturnedNoRows)` if there is no such
+    /// payload.
+    pub fn size(&self, id: BlobId) -> Result<u64> {
+        let size: i64 = self.conn.query_row(
+            "SELECT size FROM blobstore_blob WHERE id = ?",
+            &[id.0],
+            |row| row.get(0),
+        )?;
+        Ok(size as u64)
+    }
+
+    /// Open the payload `id` for reading.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::QueryReturnedNoRows)` if there is no such
+    /// payload.
+    pub fn get(&self, id: BlobId) -> Result<BlobReader<'conn>> {
+        let size = self.size(id)?;
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT bc.chunk_id, length(c.data) FROM blobstore_blob_chunk bc
+             JOIN blobstore_chunk c ON c.id = bc.chunk_id
+             WHERE bc.blob_id = ? ORDER BY bc.seq",
+        )?;
+        let mut start = 0;
+        let chunks = stmt
+            .query_map(&[id.0], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .map(|chunk| {
+                chunk.map(|(chunk_id, len)| {
+                    let chunk = Chunk {
+                        id: chunk_id,
+                        start,
+                        len: len as u64,
+                    };
+                    start += len as u64;
+                    chunk
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(BlobReader {
+            store: BlobStore {
+                conn: self.conn,
+                chunk_size: self.chunk_size,
+            },
+            chunks,
+            size,
+            pos: 0,
+            current: None,
+        })
+    }
+
+    /// Delete the payload `id`, releasing its chunks. Returns whether it
+    /// existed. The chunks themselves are removed by `gc`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn delete(&self, id: BlobId) -> Result<bool> {
+        self.in_savepoint(|| {
+            self.conn.execute(
+                "UPDATE blobstore_chunk SET refs = refs - (
+                     SELECT count(*) FROM blobstore_blob_chunk
+                     WHERE blob_id = ?1 AND chunk_id = blobstore_chunk.id
+                 )
+                 WHERE id IN (SELECT chunk_id FROM blobstore_blob_chunk WHERE blob_id = ?1)",
+                &[id.0],
+            )?;
+            self.conn.execute(
+                "DELETE FROM blobstore_blob_chunk WHERE blob_id = ?",
+                &[id.0],
+            )?;
+            Ok(self
+                .conn
+                .execute("DELETE FROM blobstore_blob WHERE id = ?", &[id.0])?
+                > 0)
+        })
+    }
+
+    /// Remove the chunks no payload refers to and return how many there
+    /// were.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn gc(&self) -> Result<usize> {
+        self.conn
+            .execute("DELETE FROM blobstore_chunk WHERE refs <= 0", NO_PARAMS)
+    }
+
+    /// Run `f` in a savepoint, rolled back if it fails. Savepoints work both
+    /// inside and outside a transaction, and only need `&Connection`.
+    fn in_savepoint<T, F: FnOnce() -> Result<T>>(&self, f: F) -> Result<T> {
+        self.conn.execute_batch("SAVEPOINT blobstore")?;
+        match f() {
+            Ok(value) => {
+                self.conn.execute_batch("RELEASE blobstore")?;
+                Ok(value)
+            }
+            Err(err) => {
+                let _ = self
+                    .conn
+                    .execute_batch("ROLLBACK TO blobstore; RELEASE blobstore");
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Fill `buf` from `reader`, stopping early only at the end of input.
+fn read_full<R: io::Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+struct Chunk {
+    id: i64,
+    start: u64,
+    len: u64,
+}
+
+/// A payload of a `BlobStore`, read chunk by chunk with a single blob
+/// handle.
+pub struct BlobReader<'conn> {
+    store: BlobStore<'conn>,
+    chunks: Vec<Chunk>,
+    size: u64,
+    pos: u64,
+    /// The index of the chunk `blob` is open on.
+    current: Option<(usize, Blob<'conn>)>,
+}
+
+impl BlobReader<'_> {
+    /// The size of the payload in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl io::Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.size {
+            return Ok(0);
+        }
+        let pos = self.pos;
+        let index = match self.chunks.binary_search_by(|chunk| {
+            if chunk.start + chunk.len <= pos {
+                std::cmp::Ordering::Less
+            } else if chunk.start > pos {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        }) {
+            Ok(index) => index,
+            Err(_) => return Ok(0),
+        };
+        let chunk = &self.chunks[index];
+        let to_io = |err| io::Error::new(io::ErrorKind::Other, err);
+        match self.current.take() {
+            Some((current, blob)) if current == index => self.current = Some((current, blob)),
+            Some((_, mut blob)) => {
+                blob.reopen(chunk.id).map_err(to_io)?;
+                self.current = Some((index, blob));
+            }
+            None => {
+                let blob = self.store.open_chunk(chunk.id, true).map_err(to_io)?;
+                self.current = Some((index, blob));
+            }
+        }
+        let blob = &mut self.current.as_mut().unwrap().1;
+        io::Seek::seek(blob, io::SeekFrom::Start(pos - chunk.start))?;
+        let n = min(buf.len() as u64, chunk.start + chunk.len - pos) as usize;
+        let n = io::Read::read(blob, &mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl io::Seek for BlobReader<'_> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(offset) => offset as i64,
+            io::SeekFrom::Current(offset) => self.pos as i64 + offset,
+            io::SeekFrom::End(offset) => self.size as i64 + offset,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to negative position",
+            ));
+        }
+        self.pos = pos as u64;
+        Ok(self.pos)
+    }
+}
+
+const K: [u32; 64] = [
+    0x428a_2f98,
+    0x7137_4491,
+    0xb5c0_fbcf,
+    0xe9b5_dba5,
+    0x3956_c25b,
+    0x59f1_11f1,
+    0x923f_82a4,
+    0xab1c_5ed5,
+    0xd807_aa98,
+    0x1283_5b01,
+    0x2431_85be,
+    0x550c_7dc3,
+    0x72be_5d74,
+    0x80de_b1fe,
+    0x9bdc_06a7,
+    0xc19b_f174,
+    0xe49b_69c1,
+    0xefbe_4786,
+    0x0fc1_9dc6,
+    0x240c_a1cc,
+    0x2de9_2c6f,
+    0x4a74_84aa,
+    0x5cb0_a9dc,
+    0x76f9_88da,
+    0x983e_5152,
+    0xa831_c66d,
+    0xb003_27c8,
+    0xbf59_7fc7,
+    0xc6e0_0bf3,
+    0xd5a7_9147,
+    0x06ca_6351,
+    0x1429_2967,
+    0x27b7_0a85,
+    0x2e1b_2138,
+    0x4d2c_6dfc,
+    0x5338_0d13,
+    0x650a_7354,
+    0x766a_0abb,
+    0x81c2_c92e,
+    0x9272_2c85,
+    0xa2bf_e8a1,
+    0xa81a_664b,
+    0xc24b_8b70,
+    0xc76c_51a3,
+    0xd192_e819,
+    0xd699_0624,
+    0xf40e_3585,
+    0x106a_a070,
+    0x19a4_c116,
+    0x1e37_6c08,
+    0x2748_774c,
+    0x34b0_bcb5,
+    0x391c_0cb3,
+    0x4ed8_aa4a,
+    0x5b9c_ca4f,
+    0x682e_6ff3,
+    0x748f_82ee,
+    0x78a5_636f,
+    0x84c8_7814,
+    0x8cc7_0208,
+    0x90be_fffa,
+    0xa450_6ceb,
+    0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+/// SHA-256 of `data`, the content address of a chunk.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09_e667,
+        0xbb67_ae85,
+        0x3c6e_f372,
+        0xa54f_f53a,
+        0x510e_527f,
+        0x9b05_688c,
+        0x1f83_d9ab,
+        0x5be0_cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (h, v) in h.iter_mut().zip(&[a, b, c, d, e, f, g, hh]) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
@@ -1,10 +1,14 @@
 //! Prepared statements cache for faster execution.
 
+use crate::clock::Stopwatch;
 use crate::raw_statement::RawStatement;
 use crate::{Connection, Result, Statement};
 use lru_cache::LruCache;
-use std::cell::RefCell;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
+use std::time::Duration;
 
 impl Connection {
     /// Prepare a SQL statement for execution, returning a previously prepared
@@ -46,16 +50,148 @@ impl Connection {
         self.cache.set_capacity(capacity)
     }
 
-    /// Remove/finalize all prepared statements currently in the cache.
+    /// Remove/finalize all prepared statements currently in the cache,
+    /// including pinned ones. Pinned SQL stays pinned.
     pub fn flush_prepared_statement_cache(&self) {
         self.cache.flush()
     }
+
+    /// Choose whether `prepare_cached` normalizes SQL before looking it up:
+    /// runs of whitespace outside literals and comments collapse to a single
+    /// space and trailing semicolons are dropped, so SQL that only differs
+    /// in layout shares one cached statement. Off by default.
+    pub fn set_prepared_statement_cache_normalization(&self, normalize: bool) {
+        self.cache.set_normalize(normalize)
+    }
+
+    /// Counters of the prepared statement cache since the connection was
+    /// opened or the counters were last reset.
+    pub fn prepared_statement_cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Reset the counters of `prepared_statement_cache_stats` to zero.
+    pub fn reset_prepared_statement_cache_stats(&self) {
+        self.cache.reset_stats()
+    }
+
+    /// Prepare `sql` and keep it in the cache until `unpin_prepared_statement`,
+    /// whatever the capacity of the cache. Later calls to `prepare_cached`
+    /// with the same SQL reuse it.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be converted to a C-compatible string
+    /// or if the underlying SQLite call fails.
+    pub fn pin_prepared_statement(&self, sql: &str) -> Result<()> {
+        self.cache.pin(self, sql)
+    }
+
+    /// Let the cached statement for `sql` be evicted again. Returns `false`
+    /// if it was not pinned.
+    pub fn unpin_prepared_statement(&self, sql: &str) -> bool {
+        self.cache.unpin(sql)
+    }
+}
+
+/// Counters of the prepared statement cache of a connection, returned by
+/// `Connection::prepared_statement_cache_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Calls to `prepare_cached` answered from the cache.
+    pub hits: u64,
+    /// Calls to `prepare_cached` that had to prepare the statement.
+    pub misses: u64,
+    /// Statements finalized to make room in the cache.
+    pub evictions: u64,
+    /// Total time spent preparing statements on misses and pins.
+    pub prepare_time: Duration,
+    /// Statements currently idle in the LRU part of the cache.
+    pub cached: usize,
+    /// Pinned SQL strings.
+    pub pinned: usize,
+}
+
+/// Collapse whitespace outside string literals, quoted identifiers and
+/// comments to a single space, and drop leading and trailing whitespace and
+/// trailing semicolons. A `--` comment keeps the newline that ends it.
+pub fn normalize_sql(sql: &str) -> Cow<'_, str> {
+    let trimmed = sql
+        .trim()
+        .trim_end_matches(|c: char| c == ';' || c.is_whitespace());
+    let mut out = String::with_capacity(trimmed.len());
+    let mut chars = trimmed.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    if c == close {
+                        // A doubled quote is an escaped quote.
+                        if close != ']' && chars.peek() == Some(&close) {
+                            out.push(chars.next().unwrap());
+                            continue;
+                        }
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                out.push(c);
+                for c in &mut chars {
+                    out.push(c);
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                out.push(c);
+                out.push(chars.next().unwrap());
+                let mut prev = ' ';
+                for c in &mut chars {
+                    out.push(c);
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            c if c.is_whitespace() => {
+                while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                    chars.next();
+                }
+                out.push(' ');
+            }
+            c => out.push(c),
+        }
+    }
+    if out == sql {
+        Cow::Borrowed(sql)
+    } else {
+        Cow::Owned(out)
+    }
 }
 
 /// Prepared statements LRU cache.
 /// made pub for testing
 #[derive(Debug)]
-pub struct StatementCache(pub RefCell<LruCache<String, RawStatement>>);
+pub struct StatementCache(pub RefCell<LruCache<String, RawStatement>>, CacheState);
+
+#[derive(Debug, Default)]
+struct CacheState {
+    // Idle pinned statements, out of reach of the LRU.
+    pinned: RefCell<HashMap<String, RawStatement>>,
+    // Keys of the pinned statements, whether idle or in use.
+    pinned_keys: RefCell<HashSet<String>>,
+    stats: Cell<CacheStats>,
+    normalize: Cell<bool>,
+}
 
 /// Cacheable statement.
 ///
@@ -63,6 +199,7 @@ pub struct StatementCache(pub RefCell<LruCache<String, RawStatement>>);
 /// If you want the statement to be discarded, call `discard()` on it.
 pub struct CachedStatement<'conn> {
     stmt: Option<Statement<'conn>>,
+    key: String,
     cache: &'conn StatementCache,
 }
 
@@ -84,15 +221,21 @@ impl Drop for CachedStatement<'_> {
     #[allow(unused_must_use)]
     fn drop(&mut self) {
         if let Some(stmt) = self.stmt.take() {
-            self.cache.cache_stmt(stmt.into());
+            let key = std::mem::replace(&mut self.key, String::new());
+            self.cache.cache_stmt(key, stmt.into());
         }
     }
 }
 
 impl CachedStatement<'_> {
-    fn new<'conn>(stmt: Statement<'conn>, cache: &'conn StatementCache) -> CachedStatement<'conn> {
+    fn new<'conn>(
+        stmt: Statement<'conn>,
+        key: String,
+        cache: &'conn StatementCache,
+    ) -> CachedStatement<'conn> {
         CachedStatement {
             stmt: Some(stmt),
+            key,
             cache,
         }
     }
@@ -107,11 +250,51 @@ impl CachedStatement<'_> {
 impl StatementCache {
     /// Create a statement cache.
     pub fn with_capacity(capacity: usize) -> StatementCache {
-        StatementCache(RefCell::new(LruCache::new(capacity)))
+        StatementCache(RefCell::new(LruCache::new(capacity)), CacheState::default())
     }
 
     fn set_capacity(&self, capacity: usize) {
-        self.0.borrow_mut().set_capacity(capacity)
+        let mut cache = self.0.borrow_mut();
+        let evicted = cache.len().saturating_sub(capacity);
+        cache.set_capacity(capacity);
+        self.count(|stats| stats.evictions += evicted as u64);
+    }
+
+    fn count<F: FnOnce(&mut CacheStats)>(&self, f: F) {
+        let mut stats = self.1.stats.get();
+        f(&mut stats);
+        self.1.stats.set(stats);
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            cached: self.0.borrow().len(),
+            pinned: self.1.pinned_keys.borrow().len(),
+            ..self.1.stats.get()
+        }
+    }
+
+    fn reset_stats(&self) {
+        self.1.stats.set(CacheStats::default())
+    }
+
+    fn set_normalize(&self, normalize: bool) {
+        self.1.normalize.set(normalize)
+    }
+
+    fn key(&self, sql: &str) -> String {
+        if self.1.normalize.get() {
+            normalize_sql(sql).into_owned()
+        } else {
+            sql.trim().to_owned()
+        }
+    }
+
+    fn prepare<'conn>(&self, conn: &'conn Connection, sql: &str) -> Result<Statement<'conn>> {
+        let stopwatch = Stopwatch::start();
+        let stmt = conn.prepare(sql);
+        self.count(|stats| stats.prepare_time += stopwatch.elapsed());
+        stmt
     }
 
     // Search the cache for a prepared-statement object that implements `sql`.
@@ -126,31 +309,71 @@ impl StatementCache {
         conn: &'conn Connection,
         sql: &str,
     ) -> Result<CachedStatement<'conn>> {
-        let mut cache = self.0.borrow_mut();
-        let stmt = match cache.remove(sql.trim()) {
-            Some(raw_stmt) => Ok(Statement::new(conn, raw_stmt)),
-            None => conn.prepare(sql),
+        let key = self.key(sql);
+        let cached = match self.1.pinned.borrow_mut().remove(&key) {
+            Some(raw_stmt) => Some(raw_stmt),
+            None => self.0.borrow_mut().remove(&key),
+        };
+        let stmt = match cached {
+            Some(raw_stmt) => {
+                self.count(|stats| stats.hits += 1);
+                Ok(Statement::new(conn, raw_stmt))
+            }
+            None => {
+                self.count(|stats| stats.misses += 1);
+                self.prepare(conn, sql)
+            }
         };
-        stmt.map(|stmt| CachedStatement::new(stmt, self))
+        stmt.map(|stmt| CachedStatement::new(stmt, key, self))
     }
 
     // Return a statement to the cache.
-    fn cache_stmt(&self, stmt: RawStatement) {
+    fn cache_stmt(&self, key: String, stmt: RawStatement) {
         if stmt.is_null() {
             return;
         }
-        let mut cache = self.0.borrow_mut();
         stmt.clear_bindings();
-        let sql = String::from_utf8_lossy(stmt.sql().unwrap().to_bytes())
-            .trim()
-            .to_string();
-        cache.insert(sql, stmt);
+        if self.1.pinned_keys.borrow().contains(&key) {
+            self.1.pinned.borrow_mut().insert(key, stmt);
+            return;
+        }
+        let mut cache = self.0.borrow_mut();
+        if cache.len() >= cache.capacity() && !cache.contains_key(&key) {
+            self.count(|stats| stats.evictions += 1);
+        }
+        cache.insert(key, stmt);
+    }
+
+    fn pin(&self, conn: &Connection, sql: &str) -> Result<()> {
+        let key = self.key(sql);
+        if self.1.pinned_keys.borrow().contains(&key) {
+            return Ok(());
+        }
+        let stmt = match self.0.borrow_mut().remove(&key) {
+            Some(raw_stmt) => raw_stmt,
+            None => self.prepare(conn, sql)?.into(),
+        };
+        self.1.pinned_keys.borrow_mut().insert(key.clone());
+        self.1.pinned.borrow_mut().insert(key, stmt);
+        Ok(())
+    }
+
+    fn unpin(&self, sql: &str) -> bool {
+        let key = self.key(sql);
+        if !self.1.pinned_keys.borrow_mut().remove(&key) {
+            return false;
+        }
+        // Still in use otherwise; `cache_stmt` will put it in the LRU.
+        let idle = self.1.pinned.borrow_mut().remove(&key);
+        if let Some(stmt) = idle {
+            self.cache_stmt(key, stmt);
+        }
+        true
     }
 
     fn flush(&self) {
+        self.1.pinned.borrow_mut().clear();
         let mut cache = self.0.borrow_mut();
         cache.clear()
     }
 }
-
-
@@ -0,0 +1,68 @@
+//! Timing that also works on `wasm32-unknown-unknown`, where
+//! `std::time::Instant` and `std::thread::sleep` are unsupported.
+//!
+//! There the JS clock is used when the `wasm` feature is enabled; without it
+//! elapsed times are always zero. The thread cannot sleep on `wasm32`, and
+//! spinning would freeze the page, so `CAN_SLEEP` is `false` there and
+//! callers should give up rather than wait.
+
+use std::time::Duration;
+
+/// Measures the time elapsed since it was started.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+    start: f64,
+}
+
+impl Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start() -> Stopwatch {
+        Stopwatch {
+            start: std::time::Instant::now(),
+        }
+    }
+
+    #[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+    pub fn start() -> Stopwatch {
+        Stopwatch {
+            start: js_sys::Date::now(),
+        }
+    }
+
+    #[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+    pub fn start() -> Stopwatch {
+        Stopwatch {}
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    #[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+    pub fn elapsed(&self) -> Duration {
+        let millis = (js_sys::Date::now() - self.start).max(0.0);
+        Duration::from_micros((millis * 1000.0) as u64)
+    }
+
+    #[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs(0)
+    }
+}
+
+/// Whether `sleep` blocks. It is `false` on `wasm32`, where `sleep` returns
+/// at once.
+pub(crate) const CAN_SLEEP: bool = cfg!(not(target_arch = "wasm32"));
+
+/// Block for `duration`, if `CAN_SLEEP`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn sleep(duration: Duration) {
+    std::thread::sleep(duration)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn sleep(_duration: Duration) {}
@@ -1,9 +1,12 @@
 //! Add, remove, or modify a collation
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::os::raw::{c_char, c_int, c_void};
-use std::panic::{catch_unwind, UnwindSafe};
+use std::panic::{catch_unwind, AssertUnwindSafe, UnwindSafe};
 use std::ptr;
 use std::slice;
+use std::sync::Arc;
 
 use crate::ffi;
 use crate::{str_to_cstring, Connection, InnerConnection, Result};
@@ -25,17 +28,123 @@ impl Connection {
     }
 
     /// Collation needed callback
-    pub fn collation_needed(
-        &self,
-        x_coll_needed: fn(&Connection, &str) -> Result<()>,
-    ) -> Result<()> {
-        self.db.borrow_mut().collation_needed(x_coll_needed)
+    ///
+    /// `x_coll_needed` is called when SQL references a collation that is
+    /// neither defined nor in the connection's `CollationRegistry`.
+    pub fn collation_needed<F>(&self, x_coll_needed: F) -> Result<()>
+    where
+        F: FnMut(&Connection, &str) -> Result<()> + Send + 'static,
+    {
+        *self.collations.fallback.borrow_mut() = Some(Box::new(x_coll_needed));
+        self.install_collation_needed()
     }
 
     /// Remove collation.
     pub fn remove_collation(&self, collation_name: &str) -> Result<()> {
         self.db.borrow_mut().remove_collation(collation_name)
     }
+
+    /// Add `x_compare` to the connection's `CollationRegistry` under
+    /// `collation_name`. The collation is only created in SQLite when a
+    /// statement first uses it. A collation of the same name that SQLite
+    /// already uses is replaced right away.
+    pub fn register_collation<C>(&self, collation_name: &str, x_compare: C) -> Result<()>
+    where
+        C: Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+    {
+        let key = CollationRegistry::key(collation_name);
+        self.collations
+            .collations
+            .borrow_mut()
+            .insert(key.clone(), Arc::new(x_compare));
+        if self.collations.installed.borrow().contains(&key) {
+            self.collations.install(self, collation_name)?;
+        }
+        self.install_collation_needed()
+    }
+
+    /// Remove `collation_name` from the connection's `CollationRegistry`,
+    /// and from SQLite if it has already been installed. Returns whether
+    /// the collation was registered.
+    pub fn unregister_collation(&self, collation_name: &str) -> Result<bool> {
+        let key = CollationRegistry::key(collation_name);
+        let removed = self.collations.collations.borrow_mut().remove(&key);
+        if self.collations.installed.borrow_mut().remove(&key) {
+            self.remove_collation(collation_name)?;
+        }
+        Ok(removed.is_some())
+    }
+
+    /// The collations registered with `register_collation`.
+    pub fn collations(&self) -> &CollationRegistry {
+        &self.collations
+    }
+
+    fn install_collation_needed(&self) -> Result<()> {
+        if self.collations.needed_installed.get() {
+            return Ok(());
+        }
+        self.db
+            .borrow_mut()
+            .collation_needed(&*self.collations as *const CollationRegistry)?;
+        self.collations.needed_installed.set(true);
+        Ok(())
+    }
+}
+
+type Comparator = Arc<dyn Fn(&str, &str) -> Ordering + Send + Sync>;
+type NeededCallback = Box<dyn FnMut(&Connection, &str) -> Result<()> + Send>;
+
+/// Named comparators owned by a `Connection` and installed through
+/// `sqlite3_collation_needed` the first time SQL references them.
+///
+/// Collation names are case-insensitive, as in SQLite.
+#[derive(Default)]
+pub struct CollationRegistry {
+    collations: RefCell<HashMap<String, Comparator>>,
+    // names already created in SQLite
+    installed: RefCell<HashSet<String>>,
+    fallback: RefCell<Option<NeededCallback>>,
+    needed_installed: Cell<bool>,
+}
+
+impl CollationRegistry {
+    fn key(collation_name: &str) -> String {
+        collation_name.to_ascii_lowercase()
+    }
+
+    /// Returns whether a collation named `collation_name` is registered.
+    pub fn contains(&self, collation_name: &str) -> bool {
+        self.collations
+            .borrow()
+            .contains_key(&CollationRegistry::key(collation_name))
+    }
+
+    /// Returns the (lowercased) names of the registered collations, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.collations.borrow().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Create `collation_name` on `conn` from the registry, or hand it to
+    /// the `collation_needed` callback if it is not registered.
+    fn install(&self, conn: &Connection, collation_name: &str) -> Result<()> {
+        let key = CollationRegistry::key(collation_name);
+        let compare = self.collations.borrow().get(&key).cloned();
+        match compare {
+            Some(compare) => {
+                let compare = AssertUnwindSafe(compare);
+                conn.create_collation(collation_name, move |a, b| (compare.0)(a, b))?;
+                self.installed.borrow_mut().insert(key);
+                Ok(())
+            }
+            None => match *self.fallback.borrow_mut() {
+                Some(ref mut fallback) => fallback(conn, collation_name),
+                None => Ok(()),
+            },
+        }
+    }
 }
 
 impl InnerConnection {
@@ -98,11 +207,7 @@ impl InnerConnection {
         self.decode_result(r)
     }
 
-    fn collation_needed(
-        &mut self,
-        x_coll_needed: fn(&Connection, &str) -> Result<()>,
-    ) -> Result<()> {
-        use std::mem;
+    fn collation_needed(&mut self, registry: *const CollationRegistry) -> Result<()> {
         unsafe extern "C" fn collation_needed_callback(
             arg1: *mut c_void,
             arg2: *mut ffi::sqlite3,
@@ -117,15 +222,15 @@ impl InnerConnection {
                 return;
             }
 
-            let callback: fn(&Connection, &str) -> Result<()> = mem::transmute(arg1);
-            if catch_unwind(|| {
+            let registry = &*(arg1 as *const CollationRegistry);
+            if catch_unwind(AssertUnwindSafe(|| {
                 let conn = Connection::from_handle(arg2).unwrap();
                 let collation_name = {
                     let c_slice = CStr::from_ptr(arg3).to_bytes();
                     str::from_utf8_unchecked(c_slice)
                 };
-                callback(&conn, collation_name)
-            })
+                registry.install(&conn, collation_name)
+            }))
             .is_err()
             {
                 return; // FIXME How ?
@@ -135,7 +240,7 @@ impl InnerConnection {
         let r = unsafe {
             ffi::sqlite3_collation_needed(
                 self.db(),
-                mem::transmute(x_coll_needed),
+                registry as *mut c_void,
                 Some(collation_needed_callback),
             )
         };
@@ -0,0 +1,117 @@
+//! Structured details of `SQLITE_CONSTRAINT` errors.
+use std::os::raw::c_int;
+
+use crate::ffi;
+use crate::Error;
+
+/// The kind of constraint that failed, from the extended result code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConstraintKind {
+    /// A `UNIQUE` constraint or unique index.
+    Unique,
+    /// A `PRIMARY KEY` constraint, including rowid conflicts.
+    PrimaryKey,
+    /// A `NOT NULL` constraint.
+    NotNull,
+    /// A `FOREIGN KEY` constraint.
+    ForeignKey,
+    /// A `CHECK` constraint.
+    Check,
+    /// A `RAISE(ABORT | FAIL | ROLLBACK, ...)` in a trigger.
+    Trigger,
+    /// Any other constraint, e.g. one raised by a virtual table.
+    Other,
+}
+
+/// A constraint violation, parsed from an `SQLITE_CONSTRAINT` error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstraintViolation {
+    /// What kind of constraint failed.
+    pub kind: ConstraintKind,
+    /// The table the constraint belongs to, when SQLite names it.
+    pub table: Option<String>,
+    /// The constrained columns, for `UNIQUE`, `PRIMARY KEY` and `NOT NULL`.
+    pub columns: Vec<String>,
+    /// The constraint name: the `CHECK` constraint name (or expression when
+    /// unnamed), or the index name for unique indexes on expressions.
+    pub name: Option<String>,
+}
+
+const PREFIX: &str = "constraint failed: ";
+
+impl ConstraintViolation {
+    fn parse(extended_code: c_int, msg: Option<&str>) -> ConstraintViolation {
+        let kind = match extended_code {
+            ffi::SQLITE_CONSTRAINT_UNIQUE => ConstraintKind::Unique,
+            ffi::SQLITE_CONSTRAINT_PRIMARYKEY | ffi::SQLITE_CONSTRAINT_ROWID => {
+                ConstraintKind::PrimaryKey
+            }
+            ffi::SQLITE_CONSTRAINT_NOTNULL => ConstraintKind::NotNull,
+            ffi::SQLITE_CONSTRAINT_FOREIGNKEY => ConstraintKind::ForeignKey,
+            ffi::SQLITE_CONSTRAINT_CHECK => ConstraintKind::Check,
+            ffi::SQLITE_CONSTRAINT_TRIGGER => ConstraintKind::Trigger,
+            _ => ConstraintKind::Other,
+        };
+        let mut violation = ConstraintViolation {
+            kind,
+            table: None,
+            columns: Vec::new(),
+            name: None,
+        };
+        // e.g. "UNIQUE constraint failed: person.first, person.last"
+        let msg = msg.unwrap_or("");
+        let detail = match msg.find(PREFIX) {
+            Some(i) => msg[i + PREFIX.len()..].trim(),
+            None => return violation,
+        };
+        match kind {
+            ConstraintKind::Unique | ConstraintKind::PrimaryKey | ConstraintKind::NotNull => {
+                if detail.starts_with("index '") && detail.ends_with('\'') {
+                    violation.name = Some(detail["index '".len()..detail.len() - 1].to_owned());
+                    return violation;
+                }
+                for column in detail.split(", ") {
+                    match column.find('.') {
+                        Some(dot) => {
+                            if violation.table.is_none() {
+                                violation.table = Some(column[..dot].to_owned());
+                            }
+                            violation.columns.push(column[dot + 1..].to_owned());
+                        }
+                        None => violation.columns.push(column.to_owned()),
+                    }
+                }
+            }
+            ConstraintKind::Check => violation.name = Some(detail.to_owned()),
+            // `migrations` reports "{table} row {rowid} references a missing
+            // {parent} row"
+            ConstraintKind::ForeignKey => {
+                violation.table = detail.split(' ').next().map(str::to_owned);
+            }
+            ConstraintKind::Trigger | ConstraintKind::Other => {}
+        }
+        violation
+    }
+}
+
+impl Error {
+    /// The constraint violation behind this error, if it is an
+    /// `SQLITE_CONSTRAINT` failure (or a migration step that failed with
+    /// one).
+    pub fn constraint(&self) -> Option<ConstraintViolation> {
+        match *self {
+            Error::SqliteFailure(ref err, ref msg)
+                if err.code == ffi::ErrorCode::ConstraintViolation =>
+            {
+                Some(ConstraintViolation::parse(
+                    err.extended_code,
+                    msg.as_ref().map(String::as_str),
+                ))
+            }
+            Error::MigrationFailed(_, ref err) | Error::ScriptFailed(_, ref err) => {
+                err.constraint()
+            }
+            _ => None,
+        }
+    }
+}
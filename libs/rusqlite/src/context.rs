@@ -9,6 +9,8 @@ use crate::ffi::sqlite3_context;
 
 use crate::str_for_sqlite;
 use crate::types::{ToSqlOutput, ValueRef};
+#[cfg(feature = "pointer")]
+use crate::types::pointer::free_pointer;
 #[cfg(feature = "array")]
 use crate::vtab::array::{free_array, ARRAY_TYPE};
 
@@ -30,6 +32,16 @@ pub(crate) unsafe fn set_result(ctx: *mut sqlite3_context, result: &ToSqlOutput<
                 Some(free_array),
             );
         }
+        #[cfg(feature = "pointer")]
+        ToSqlOutput::Pointer(ref p) => {
+            let type_name = p.type_name_ptr();
+            return ffi::sqlite3_result_pointer(
+                ctx,
+                p.clone().into_raw(),
+                type_name,
+                Some(free_pointer),
+            );
+        }
     };
 
     match value {
@@ -0,0 +1,540 @@
+//! Compare the main databases of two connections, like the `sqldiff` tool.
+//!
+//! The comparison lists the schema objects that were created, dropped or
+//! altered, and for each table the rows that were inserted, updated or
+//! deleted, matched by primary key, or by rowid for tables without one. It
+//! can also be rendered as a SQL script that transforms the first database
+//! into the second.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! # use rusqlite::diff::diff;
+//! fn sync(local: &Connection, remote: &Connection) -> Result<()> {
+//!     let changes = diff(local, remote)?;
+//!     if !changes.is_empty() {
+//!         local.execute_batch(&changes.to_sql()?)?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use std::cmp::Ordering;
+
+use crate::pragma::Sql;
+use crate::types::{Value, ValueRef};
+use crate::{Connection, Error, Result, Row, NO_PARAMS};
+
+/// The differences between two databases, as returned by `diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatabaseDiff {
+    /// Tables, indexes, views and triggers that differ, ordered by name.
+    pub schema: Vec<SchemaChange>,
+    /// The tables whose rows differ, ordered by name.
+    pub tables: Vec<TableDiff>,
+}
+
+/// A schema object that is only in one database, or whose `CREATE`
+/// statement differs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemaChange {
+    /// The object is only in the second database.
+    Created {
+        /// `"table"`, `"index"`, `"view"` or `"trigger"`.
+        kind: String,
+        /// The object name.
+        name: String,
+        /// The `CREATE` statement.
+        sql: String,
+    },
+    /// The object is only in the first database.
+    Dropped {
+        /// `"table"`, `"index"`, `"view"` or `"trigger"`.
+        kind: String,
+        /// The object name.
+        name: String,
+        /// The `CREATE` statement.
+        sql: String,
+    },
+    /// The `CREATE` statements differ. Indexes and triggers of an altered
+    /// table are also reported as altered, as they must be recreated with
+    /// it, even if their own statements are the same.
+    Altered {
+        /// `"table"`, `"index"`, `"view"` or `"trigger"`.
+        kind: String,
+        /// The object name.
+        name: String,
+        /// The `CREATE` statement in the first database.
+        old_sql: String,
+        /// The `CREATE` statement in the second database.
+        new_sql: String,
+    },
+}
+
+impl SchemaChange {
+    /// `"table"`, `"index"`, `"view"` or `"trigger"`.
+    pub fn kind(&self) -> &str {
+        match *self {
+            SchemaChange::Created { ref kind, .. }
+            | SchemaChange::Dropped { ref kind, .. }
+            | SchemaChange::Altered { ref kind, .. } => kind,
+        }
+    }
+
+    /// The object name.
+    pub fn name(&self) -> &str {
+        match *self {
+            SchemaChange::Created { ref name, .. }
+            | SchemaChange::Dropped { ref name, .. }
+            | SchemaChange::Altered { ref name, .. } => name,
+        }
+    }
+}
+
+/// The row changes of one table.
+///
+/// Tables that are created or altered are filled from scratch, so all their
+/// rows in the second database are listed as inserts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableDiff {
+    /// The table name.
+    pub name: String,
+    /// The primary key columns, or `["rowid"]`.
+    pub key: Vec<String>,
+    /// The table columns, in declaration order.
+    pub columns: Vec<String>,
+    /// The changes, ordered by key.
+    pub rows: Vec<RowChange>,
+}
+
+/// A changed row. Keys hold one value per `TableDiff::key` column and rows
+/// one value per `TableDiff::columns` column.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RowChange {
+    /// The row is only in the second database.
+    Insert { key: Vec<Value>, row: Vec<Value> },
+    /// Some values of the row differ.
+    Update {
+        key: Vec<Value>,
+        old: Vec<Value>,
+        new: Vec<Value>,
+    },
+    /// The row is only in the first database.
+    Delete { key: Vec<Value>, row: Vec<Value> },
+}
+
+impl DatabaseDiff {
+    /// Returns `true` if the databases have the same schema and rows.
+    pub fn is_empty(&self) -> bool {
+        self.schema.is_empty() && self.tables.is_empty()
+    }
+
+    /// A script that transforms the first database into the second: it
+    /// drops the objects that were dropped or altered, creates the tables,
+    /// applies the row changes and then creates the indexes, views and
+    /// triggers.
+    ///
+    /// The script does not disable triggers, so triggers that exist in both
+    /// databases fire while the row changes are applied.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a text value is not valid UTF-8.
+    pub fn to_sql(&self) -> Result<String> {
+        let mut script = String::new();
+        for &kind in &["trigger", "view", "index", "table"] {
+            for change in self.schema.iter().filter(|c| c.kind() == kind) {
+                if let SchemaChange::Created { .. } = *change {
+                    continue;
+                }
+                let mut sql = Sql::new();
+                sql.push_keyword("DROP")?;
+                sql.push_space();
+                sql.push_keyword(&kind.to_uppercase())?;
+                sql.push_space();
+                push_keywords(&mut sql, "IF EXISTS")?;
+                sql.push_space();
+                sql.push_quoted_identifier(change.name());
+                script.push_str(&sql);
+                script.push_str(";\n");
+            }
+        }
+        for &kind in &["table", "index", "view", "trigger"] {
+            for change in self.schema.iter().filter(|c| c.kind() == kind) {
+                let sql = match *change {
+                    SchemaChange::Created { ref sql, .. } => sql,
+                    SchemaChange::Altered { ref new_sql, .. } => new_sql,
+                    SchemaChange::Dropped { .. } => continue,
+                };
+                script.push_str(sql);
+                script.push_str(";\n");
+            }
+            if kind == "table" {
+                for table in &self.tables {
+                    table.push_sql(&mut script)?;
+                }
+            }
+        }
+        Ok(script)
+    }
+}
+
+impl TableDiff {
+    fn push_sql(&self, script: &mut String) -> Result<()> {
+        for change in &self.rows {
+            let mut sql = Sql::new();
+            match *change {
+                RowChange::Insert { ref key, ref row } => {
+                    push_keywords(&mut sql, "INSERT INTO")?;
+                    sql.push_space();
+                    sql.push_quoted_identifier(&self.name);
+                    sql.open_brace();
+                    let mut columns: Vec<&String> = self.columns.iter().collect();
+                    let mut values: Vec<&Value> = row.iter().collect();
+                    if self.is_rowid_key() {
+                        columns.insert(0, &self.key[0]);
+                        values.insert(0, &key[0]);
+                    }
+                    for (i, column) in columns.iter().enumerate() {
+                        if i > 0 {
+                            sql.push_comma();
+                        }
+                        sql.push_quoted_identifier(column);
+                    }
+                    sql.close_brace();
+                    sql.push_space();
+                    sql.push_keyword("VALUES")?;
+                    sql.open_brace();
+                    for (i, value) in values.into_iter().enumerate() {
+                        if i > 0 {
+                            sql.push_comma();
+                        }
+                        sql.push_literal(ValueRef::from(value))?;
+                    }
+                    sql.close_brace();
+                }
+                RowChange::Update {
+                    ref key,
+                    ref old,
+                    ref new,
+                } => {
+                    sql.push_keyword("UPDATE")?;
+                    sql.push_space();
+                    sql.push_quoted_identifier(&self.name);
+                    sql.push_space();
+                    sql.push_keyword("SET")?;
+                    sql.push_space();
+                    let changed = self
+                        .columns
+                        .iter()
+                        .zip(old.iter().zip(new))
+                        .filter(|&(_, (old, new))| old != new);
+                    for (i, (column, (_, new))) in changed.enumerate() {
+                        if i > 0 {
+                            sql.push_comma();
+                        }
+                        sql.push_quoted_identifier(column);
+                        sql.push_equal_sign();
+                        sql.push_literal(ValueRef::from(new))?;
+                    }
+                    self.push_where(&mut sql, key)?;
+                }
+                RowChange::Delete { ref key, .. } => {
+                    push_keywords(&mut sql, "DELETE FROM")?;
+                    sql.push_space();
+                    sql.push_quoted_identifier(&self.name);
+                    self.push_where(&mut sql, key)?;
+                }
+            }
+            script.push_str(&sql);
+            script.push_str(";\n");
+        }
+        Ok(())
+    }
+
+    fn push_where(&self, sql: &mut Sql, key: &[Value]) -> Result<()> {
+        sql.push_space();
+        sql.push_keyword("WHERE")?;
+        for (i, (column, value)) in self.key.iter().zip(key).enumerate() {
+            sql.push_space();
+            if i > 0 {
+                sql.push_keyword("AND")?;
+                sql.push_space();
+            }
+            sql.push_quoted_identifier(column);
+            if *value == Value::Null {
+                sql.push_space();
+                push_keywords(sql, "IS NULL")?;
+            } else {
+                sql.push_equal_sign();
+                sql.push_literal(ValueRef::from(value))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_rowid_key(&self) -> bool {
+        self.key.len() == 1 && !self.columns.contains(&self.key[0])
+    }
+}
+
+fn push_keywords(sql: &mut Sql, keywords: &str) -> Result<()> {
+    for (i, keyword) in keywords.split(' ').enumerate() {
+        if i > 0 {
+            sql.push_space();
+        }
+        sql.push_keyword(keyword)?;
+    }
+    Ok(())
+}
+
+/// A row of `sqlite_master`.
+struct Object {
+    kind: String,
+    name: String,
+    table: String,
+    sql: String,
+}
+
+impl Object {
+    fn is_virtual_table(&self) -> bool {
+        self.sql
+            .get(..20)
+            .map_or(false, |s| s.eq_ignore_ascii_case("CREATE VIRTUAL TABLE"))
+    }
+}
+
+fn objects(conn: &Connection) -> Result<Vec<Object>> {
+    let mut stmt = conn.prepare(
+        "SELECT type, name, tbl_name, sql FROM sqlite_master \
+         WHERE sql NOT NULL AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+         ORDER BY name",
+    )?;
+    let objects = stmt
+        .query_map(NO_PARAMS, |row| {
+            Ok(Object {
+                kind: row.get(0)?,
+                name: row.get(1)?,
+                table: row.get(2)?,
+                sql: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    Ok(objects)
+}
+
+fn find<'a>(objects: &'a [Object], kind: &str, name: &str) -> Option<&'a Object> {
+    objects.iter().find(|o| o.kind == kind && o.name == name)
+}
+
+/// Compares values the way SQLite orders them with the `BINARY` collation:
+/// `NULL`, then numbers, then text, then blobs.
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    fn class(value: &Value) -> u8 {
+        match *value {
+            Value::Null => 0,
+            Value::Integer(_) | Value::Real(_) => 1,
+            Value::Text(_) => 2,
+            Value::Blob(_) => 3,
+        }
+    }
+    let numbers = |a: f64, b: f64| a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+    match (a, b) {
+        (&Value::Integer(a), &Value::Integer(b)) => a.cmp(&b),
+        (&Value::Integer(a), &Value::Real(b)) => numbers(a as f64, b),
+        (&Value::Real(a), &Value::Integer(b)) => numbers(a, b as f64),
+        (&Value::Real(a), &Value::Real(b)) => numbers(a, b),
+        (&Value::Text(ref a), &Value::Text(ref b)) => a.as_bytes().cmp(b.as_bytes()),
+        (&Value::Blob(ref a), &Value::Blob(ref b)) => a.cmp(b),
+        _ => class(a).cmp(&class(b)),
+    }
+}
+
+fn compare_keys(a: &[Value], b: &[Value]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| compare_values(a, b))
+        .find(|&ordering| ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Splits a row selected by `select` into its key and its values.
+fn split(row: Option<&Row<'_>>, keys: usize) -> Result<Option<(Vec<Value>, Vec<Value>)>> {
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let mut values = (0..row.column_count())
+        .map(|i| row.get(i))
+        .collect::<Result<Vec<Value>>>()?;
+    let row = values.split_off(keys);
+    Ok(Some((values, row)))
+}
+
+/// The key and columns of `table`, and a query returning its keys followed
+/// by its columns, ordered by key.
+fn select(conn: &Connection, table: &str) -> Result<(Vec<String>, Vec<String>, String)> {
+    let info = conn.table_info(table)?.ok_or(Error::QueryReturnedNoRows)?;
+    let mut key: Vec<_> = info.columns.iter().filter(|c| c.primary_key > 0).collect();
+    key.sort_by_key(|c| c.primary_key);
+    let mut key: Vec<String> = key.into_iter().map(|c| c.name.clone()).collect();
+    if key.is_empty() {
+        key.push("rowid".to_owned());
+    }
+    let columns: Vec<String> = info.columns.into_iter().map(|c| c.name).collect();
+
+    let mut sql = Sql::new();
+    sql.push_keyword("SELECT")?;
+    for (i, column) in key.iter().chain(&columns).enumerate() {
+        if i > 0 {
+            sql.push_comma();
+        }
+        sql.push_space();
+        sql.push_quoted_identifier(column);
+    }
+    sql.push_space();
+    sql.push_keyword("FROM")?;
+    sql.push_space();
+    sql.push_quoted_identifier(table);
+    sql.push_space();
+    push_keywords(&mut sql, "ORDER BY")?;
+    for (i, column) in key.iter().enumerate() {
+        if i > 0 {
+            sql.push_comma();
+        }
+        sql.push_space();
+        sql.push_quoted_identifier(column);
+        sql.push_space();
+        push_keywords(&mut sql, "COLLATE BINARY")?;
+    }
+    Ok((key, columns, sql.as_str().to_owned()))
+}
+
+/// The row changes of `table`, which has the same definition in `a` and
+/// `b`, or, if `a` is `None`, all its rows in `b` as inserts.
+fn diff_rows(a: Option<&Connection>, b: &Connection, table: &str) -> Result<TableDiff> {
+    let (key, columns, sql) = select(b, table)?;
+    let keys = key.len();
+    let mut stmt_b = b.prepare(&sql)?;
+    let mut rows_b = stmt_b.query(NO_PARAMS)?;
+    let mut next_b = split(rows_b.next()?, keys)?;
+    let mut changes = Vec::new();
+    match a {
+        None => {
+            while let Some((key, row)) = next_b {
+                changes.push(RowChange::Insert { key, row });
+                next_b = split(rows_b.next()?, keys)?;
+            }
+        }
+        Some(a) => {
+            let mut stmt_a = a.prepare(&sql)?;
+            let mut rows_a = stmt_a.query(NO_PARAMS)?;
+            let mut next_a = split(rows_a.next()?, keys)?;
+            loop {
+                let ordering = match (&next_a, &next_b) {
+                    (&None, &None) => break,
+                    (&Some(_), &None) => Ordering::Less,
+                    (&None, &Some(_)) => Ordering::Greater,
+                    (&Some((ref key_a, _)), &Some((ref key_b, _))) => compare_keys(key_a, key_b),
+                };
+                if ordering != Ordering::Greater {
+                    let (key, old) = next_a.take().unwrap();
+                    if ordering == Ordering::Less {
+                        changes.push(RowChange::Delete { key, row: old });
+                    } else {
+                        let (_, new) = next_b.take().unwrap();
+                        if old != new {
+                            changes.push(RowChange::Update { key, old, new });
+                        }
+                        next_b = split(rows_b.next()?, keys)?;
+                    }
+                    next_a = split(rows_a.next()?, keys)?;
+                } else {
+                    let (key, row) = next_b.take().unwrap();
+                    changes.push(RowChange::Insert { key, row });
+                    next_b = split(rows_b.next()?, keys)?;
+                }
+            }
+        }
+    }
+    Ok(TableDiff {
+        name: table.to_owned(),
+        key,
+        columns,
+        rows: changes,
+    })
+}
+
+/// Compares the main databases of `a` and `b`.
+///
+/// Objects are matched by type and name and compared by their `CREATE`
+/// statements as stored in `sqlite_master`, so reformatting a statement
+/// counts as a change. Rows are compared only in tables whose definition is
+/// the same in both; virtual tables are compared by schema only.
+///
+/// # Failure
+///
+/// Will return `Err` if the underlying SQLite calls fail.
+pub fn diff(a: &Connection, b: &Connection) -> Result<DatabaseDiff> {
+    let old = objects(a)?;
+    let new = objects(b)?;
+
+    let mut schema = Vec::new();
+    let mut rebuilt = Vec::new();
+    for o in &old {
+        match find(&new, &o.kind, &o.name) {
+            None => schema.push(SchemaChange::Dropped {
+                kind: o.kind.clone(),
+                name: o.name.clone(),
+                sql: o.sql.clone(),
+            }),
+            Some(n) if n.sql != o.sql => {
+                if o.kind == "table" {
+                    rebuilt.push(o.name.as_str());
+                }
+                schema.push(SchemaChange::Altered {
+                    kind: o.kind.clone(),
+                    name: o.name.clone(),
+                    old_sql: o.sql.clone(),
+                    new_sql: n.sql.clone(),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    for n in &new {
+        match find(&old, &n.kind, &n.name) {
+            None => schema.push(SchemaChange::Created {
+                kind: n.kind.clone(),
+                name: n.name.clone(),
+                sql: n.sql.clone(),
+            }),
+            // dropped along with its table
+            Some(o)
+                if o.sql == n.sql
+                    && (n.kind == "index" || n.kind == "trigger")
+                    && rebuilt.contains(&n.table.as_str()) =>
+            {
+                schema.push(SchemaChange::Altered {
+                    kind: n.kind.clone(),
+                    name: n.name.clone(),
+                    old_sql: o.sql.clone(),
+                    new_sql: n.sql.clone(),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    schema.sort_by(|x, y| x.name().cmp(y.name()));
+
+    let mut tables = Vec::new();
+    for n in new
+        .iter()
+        .filter(|n| n.kind == "table" && !n.is_virtual_table())
+    {
+        let same = find(&old, "table", &n.name).map_or(false, |o| o.sql == n.sql);
+        let table = diff_rows(if same { Some(a) } else { None }, b, &n.name)?;
+        if !table.rows.is_empty() {
+            tables.push(table);
+        }
+    }
+    Ok(DatabaseDiff { schema, tables })
+}
@@ -0,0 +1,289 @@
+//! Export a database as SQL text and read it back, like the `.dump`
+//! command of the `sqlite3` command-line tool.
+
+use crate::pragma::Sql;
+use crate::script::is_complete;
+use crate::types::ValueRef;
+use crate::{Connection, Error, Result, NO_PARAMS};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// What `Connection::dump` writes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpOptions {
+    /// A `LIKE` pattern restricting the dump to matching tables, with their
+    /// indexes and triggers, and matching views. `None` dumps everything.
+    pub tables: Option<String>,
+    /// Write the `CREATE` statements.
+    pub schema: bool,
+    /// Write the rows, as `INSERT` statements.
+    pub data: bool,
+    /// Wrap the output in `PRAGMA foreign_keys=OFF;`, `BEGIN TRANSACTION;`
+    /// and `COMMIT;`.
+    pub transaction: bool,
+}
+
+impl Default for DumpOptions {
+    /// Everything, in a transaction.
+    fn default() -> DumpOptions {
+        DumpOptions {
+            tables: None,
+            schema: true,
+            data: true,
+            transaction: true,
+        }
+    }
+}
+
+/// A row of `sqlite_master`, with whether its name and table name match the
+/// `tables` pattern.
+struct Entry {
+    kind: String,
+    name: String,
+    sql: String,
+    name_matches: bool,
+    table_matches: bool,
+}
+
+impl Entry {
+    fn is_virtual(&self) -> bool {
+        self.sql
+            .get(..20)
+            .map_or(false, |s| s.eq_ignore_ascii_case("CREATE VIRTUAL TABLE"))
+    }
+}
+
+fn io_error(err: io::Error) -> Error {
+    Error::ToSqlConversionFailure(Box::new(err))
+}
+
+fn write_line<W: Write>(writer: &mut W, line: &str) -> Result<()> {
+    writeln!(writer, "{}", line).map_err(io_error)
+}
+
+fn string_literal(s: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_string_literal(s);
+    sql.as_str().to_owned()
+}
+
+fn literal(value: ValueRef<'_>) -> Result<String> {
+    let mut sql = Sql::new();
+    sql.push_literal(value)?;
+    Ok(sql.as_str().to_owned())
+}
+
+impl Connection {
+    /// Write the main database as SQL that recreates it: the tables, each
+    /// after the tables its foreign keys refer to, with their rows, then the
+    /// `sqlite_sequence` counters, indexes, views and triggers.
+    ///
+    /// Virtual tables are written into `sqlite_master` under
+    /// `PRAGMA writable_schema`, as the `sqlite3` tool does, so restoring
+    /// them does not run the module's `xCreate` again; their shadow tables
+    /// are dumped as ordinary tables. Values are written as literals of the
+    /// same storage class: reals keep a fractional part and blobs are
+    /// `X'..'`. Rowids are only kept where a column aliases them.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, DumpOptions, Result};
+    /// fn copy(from: &Connection, to: &Connection) -> Result<()> {
+    ///     let mut sql = Vec::new();
+    ///     from.dump(&mut sql, &DumpOptions::default())?;
+    ///     to.restore(&sql[..])
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail, or
+    /// `Error::ToSqlConversionFailure` with the `io::Error` if writing fails.
+    pub fn dump<W: Write>(&self, mut writer: W, options: &DumpOptions) -> Result<()> {
+        let pattern = options.tables.as_ref().map_or("%", String::as_str);
+        let mut stmt = self.prepare(
+            "SELECT type, name, sql, name LIKE ?1, tbl_name LIKE ?1 \
+             FROM sqlite_master WHERE sql NOT NULL ORDER BY rowid",
+        )?;
+        let entries = stmt
+            .query_map(&[pattern], |row| {
+                Ok(Entry {
+                    kind: row.get(0)?,
+                    name: row.get(1)?,
+                    sql: row.get(2)?,
+                    name_matches: row.get(3)?,
+                    table_matches: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let tables = self.parents_first(
+            entries
+                .iter()
+                .filter(|e| e.kind == "table" && e.name_matches && !e.name.starts_with("sqlite_"))
+                .collect(),
+        )?;
+
+        if options.transaction {
+            write_line(&mut writer, "PRAGMA foreign_keys=OFF;")?;
+            write_line(&mut writer, "BEGIN TRANSACTION;")?;
+        }
+        let mut writable_schema = false;
+        for table in &tables {
+            if options.schema && table.is_virtual() {
+                if !writable_schema {
+                    write_line(&mut writer, "PRAGMA writable_schema=ON;")?;
+                    writable_schema = true;
+                }
+                let name = string_literal(&table.name);
+                write_line(
+                    &mut writer,
+                    &format!(
+                        "INSERT INTO sqlite_master(type,name,tbl_name,rootpage,sql)\
+                         VALUES('table',{},{},0,{});",
+                        name,
+                        name,
+                        string_literal(&table.sql)
+                    ),
+                )?;
+            } else if options.schema {
+                write_line(&mut writer, &format!("{};", table.sql))?;
+            }
+            if options.data && !table.is_virtual() {
+                self.dump_rows(&mut writer, &table.name)?;
+            }
+        }
+        if writable_schema {
+            write_line(&mut writer, "PRAGMA writable_schema=OFF;")?;
+        }
+
+        if options.data && entries.iter().any(|e| e.name == "sqlite_sequence") {
+            let mut stmt = self.prepare("SELECT name, seq FROM sqlite_sequence ORDER BY rowid")?;
+            let mut rows = stmt.query(NO_PARAMS)?;
+            while let Some(row) = rows.next()? {
+                let name: String = row.get(0)?;
+                if tables.iter().any(|t| t.name == name) {
+                    let name = string_literal(&name);
+                    write_line(
+                        &mut writer,
+                        &format!("DELETE FROM sqlite_sequence WHERE name={};", name),
+                    )?;
+                    write_line(
+                        &mut writer,
+                        &format!(
+                            "INSERT INTO sqlite_sequence VALUES({},{});",
+                            name,
+                            literal(row.get_raw(1))?
+                        ),
+                    )?;
+                }
+            }
+        }
+
+        if options.schema {
+            for &kind in &["index", "view", "trigger"] {
+                for entry in entries.iter().filter(|e| e.kind == kind) {
+                    let selected = if kind == "view" {
+                        entry.name_matches
+                    } else {
+                        entry.table_matches
+                    };
+                    if selected {
+                        write_line(&mut writer, &format!("{};", entry.sql))?;
+                    }
+                }
+            }
+        }
+        if options.transaction {
+            write_line(&mut writer, "COMMIT;")?;
+        }
+        writer.flush().map_err(io_error)
+    }
+
+    /// Run the SQL read from `reader`, typically the output of `dump`, one
+    /// statement at a time, so the whole script is never held in memory.
+    /// The schema is reloaded afterwards, so that restored virtual tables
+    /// can be queried at once; their modules must be loaded first.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a statement fails, or
+    /// `Error::ToSqlConversionFailure` with the `io::Error` if reading
+    /// fails. A transaction opened by the script is rolled back.
+    pub fn restore<R: Read>(&self, reader: R) -> Result<()> {
+        let was_autocommit = self.is_autocommit();
+        let result = self.restore_(reader);
+        if result.is_err() && was_autocommit && !self.is_autocommit() {
+            let _ = self.execute_batch("ROLLBACK");
+        }
+        result
+    }
+
+    fn restore_<R: Read>(&self, reader: R) -> Result<()> {
+        let mut reader = BufReader::new(reader);
+        let mut sql = String::new();
+        while reader.read_line(&mut sql).map_err(io_error)? > 0 {
+            if is_complete(&sql) {
+                self.execute_batch(&sql)?;
+                sql.clear();
+            }
+        }
+        if !sql.trim().is_empty() {
+            self.execute_batch(&sql)?;
+        }
+        // Virtual tables were written into sqlite_master directly, which
+        // SQLite only reads when it loads the schema. Changing the schema
+        // version makes it reload the schema before the next statement.
+        let version: i64 = self.pragma_query_value(None, "schema_version", |row| row.get(0))?;
+        self.pragma_update(None, "schema_version", &(version + 1))
+    }
+
+    /// Write `INSERT` statements for the rows of `table`.
+    fn dump_rows<W: Write>(&self, writer: &mut W, table: &str) -> Result<()> {
+        let mut name = Sql::new();
+        name.push_quoted_identifier(table);
+        let mut stmt = self.prepare(&format!("SELECT * FROM {}", name.as_str()))?;
+        let columns = stmt.column_count();
+        let mut rows = stmt.query(NO_PARAMS)?;
+        while let Some(row) = rows.next()? {
+            let values = (0..columns)
+                .map(|i| literal(row.get_raw(i)))
+                .collect::<Result<Vec<_>>>()?;
+            write_line(
+                writer,
+                &format!(
+                    "INSERT INTO {} VALUES({});",
+                    name.as_str(),
+                    values.join(",")
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Order `tables` so that each comes after the tables its foreign keys
+    /// refer to, keeping the given order otherwise and for cycles.
+    fn parents_first<'a>(&self, tables: Vec<&'a Entry>) -> Result<Vec<&'a Entry>> {
+        let mut pending = Vec::with_capacity(tables.len());
+        for table in tables {
+            let mut parents = Vec::new();
+            self.pragma(None, "foreign_key_list", &table.name, |row| {
+                parents.push(row.get::<_, String>(2)?);
+                Ok(())
+            })?;
+            pending.push((table, parents));
+        }
+        let mut sorted = Vec::with_capacity(pending.len());
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|&(table, ref parents)| {
+                parents.iter().all(|parent| {
+                    parent.eq_ignore_ascii_case(&table.name)
+                        || !pending
+                            .iter()
+                            .any(|&(other, _)| other.name.eq_ignore_ascii_case(parent))
+                })
+            });
+            sorted.push(pending.remove(ready.unwrap_or(0)).0);
+        }
+        Ok(sorted)
+    }
+}
@@ -1,10 +1,12 @@
 use crate::types::FromSqlError;
 use crate::types::Type;
-use crate::{errmsg_to_string, ffi};
+use crate::{errmsg_to_string, ffi, str_for_sqlite};
 use std::error;
+use std::ffi::CString;
 use std::fmt;
 use std::os::raw::c_int;
 use std::path::PathBuf;
+use std::ptr;
 use std::str;
 
 /// Enum listing possible errors from rusqlite.
@@ -39,6 +41,11 @@ pub enum Error {
     /// present in the SQL.
     InvalidParameterName(String),
 
+    /// Error when the number of parameters bound to a statement is not the
+    /// number it takes. The first `usize` is the number given, the second
+    /// the number expected.
+    InvalidParameterCount(usize, usize),
+
     /// Error converting a file path to a string.
     InvalidPath(PathBuf),
 
@@ -103,6 +110,33 @@ pub enum Error {
 
     /// Error when the SQL contains multiple statements.
     MultipleStatement,
+
+    /// Error when a `migrations::Migrations` step fails. The associated
+    /// `usize` is the `user_version` the step migrates to (or from, when
+    /// migrating down), and the boxed error is what went wrong.
+    MigrationFailed(usize, Box<Error>),
+
+    /// Error when a statement run by `Connection::execute_script` fails. The
+    /// associated `usize` is the index of the statement in the script, and
+    /// the boxed error is what went wrong.
+    ScriptFailed(usize, Box<Error>),
+
+    /// A syntax error in SQL passed to `prepare` or `execute_batch`, with the
+    /// byte offset in `sql` where SQLite stopped parsing.
+    ///
+    /// The bundled SQLite predates `sqlite3_error_offset`, so the offset is
+    /// recovered by re-preparing `sql` up to each occurrence of the token
+    /// SQLite quotes in `msg`.
+    SqlInputError {
+        /// The underlying SQLite error, usually `SQLITE_ERROR`.
+        error: ffi::Error,
+        /// The SQLite error message.
+        msg: String,
+        /// The SQL that failed to parse.
+        sql: String,
+        /// The byte offset of the error in `sql`.
+        offset: usize,
+    },
 }
 
 impl PartialEq for Error {
@@ -116,6 +150,9 @@ impl PartialEq for Error {
             (Error::Utf8Error(e1), Error::Utf8Error(e2)) => e1 == e2,
             (Error::NulError(e1), Error::NulError(e2)) => e1 == e2,
             (Error::InvalidParameterName(n1), Error::InvalidParameterName(n2)) => n1 == n2,
+            (Error::InvalidParameterCount(g1, e1), Error::InvalidParameterCount(g2, e2)) => {
+                g1 == g2 && e1 == e2
+            }
             (Error::InvalidPath(p1), Error::InvalidPath(p2)) => p1 == p2,
             (Error::ExecuteReturnedResults, Error::ExecuteReturnedResults) => true,
             (Error::QueryReturnedNoRows, Error::QueryReturnedNoRows) => true,
@@ -136,6 +173,24 @@ impl PartialEq for Error {
                 Error::InvalidFilterParameterType(i2, t2),
             ) => i1 == i2 && t1 == t2,
             (Error::InvalidQuery, Error::InvalidQuery) => true,
+            (Error::MigrationFailed(v1, e1), Error::MigrationFailed(v2, e2)) => {
+                v1 == v2 && e1 == e2
+            }
+            (Error::ScriptFailed(i1, e1), Error::ScriptFailed(i2, e2)) => i1 == i2 && e1 == e2,
+            (
+                Error::SqlInputError {
+                    error: e1,
+                    msg: m1,
+                    sql: s1,
+                    offset: o1,
+                },
+                Error::SqlInputError {
+                    error: e2,
+                    msg: m2,
+                    sql: s2,
+                    offset: o2,
+                },
+            ) => e1 == e2 && m1 == m2 && s1 == s2 && o1 == o2,
             #[cfg(feature = "vtab")]
             (Error::ModuleError(s1), Error::ModuleError(s2)) => s1 == s2,
             #[cfg(feature = "functions")]
@@ -159,7 +214,7 @@ impl From<::std::ffi::NulError> for Error {
     }
 }
 
-const UNKNOWN_COLUMN: usize = std::usize::MAX;
+pub(crate) const UNKNOWN_COLUMN: usize = std::usize::MAX;
 
 /// The conversion isn't precise, but it's convenient to have it
 /// to allow use of `get_raw(…).as_…()?` in callbacks that take `Error`.
@@ -215,6 +270,11 @@ impl fmt::Display for Error {
             Error::Utf8Error(ref err) => err.fmt(f),
             Error::NulError(ref err) => err.fmt(f),
             Error::InvalidParameterName(ref name) => write!(f, "Invalid parameter name: {}", name),
+            Error::InvalidParameterCount(given, expected) => write!(
+                f,
+                "Wrong number of parameters passed to query. Got {}, needed {}",
+                given, expected
+            ),
             Error::InvalidPath(ref p) => write!(f, "Invalid path: {}", p.to_string_lossy()),
             Error::ExecuteReturnedResults => {
                 write!(f, "Execute returned results - did you mean to call query?")
@@ -248,6 +308,15 @@ impl fmt::Display for Error {
             #[cfg(feature = "functions")]
             Error::GetAuxWrongType => write!(f, "get_aux called with wrong type"),
             Error::MultipleStatement => write!(f, "Multiple statements provided"),
+            Error::MigrationFailed(version, ref err) => {
+                write!(f, "Migration step {} failed: {}", version, err)
+            }
+            Error::ScriptFailed(index, ref err) => {
+                write!(f, "Statement {} of the script failed: {}", index, err)
+            }
+            Error::SqlInputError {
+                ref msg, offset, ..
+            } => write!(f, "{} at offset {}", msg, offset),
         }
     }
 }
@@ -265,6 +334,7 @@ impl error::Error for Error {
             Error::IntegralValueOutOfRange(..) => "integral value out of range of requested type",
             Error::Utf8Error(ref err) => err.description(),
             Error::InvalidParameterName(_) => "invalid parameter name",
+            Error::InvalidParameterCount(..) => "wrong number of parameters",
             Error::NulError(ref err) => err.description(),
             Error::InvalidPath(_) => "invalid path",
             Error::ExecuteReturnedResults => {
@@ -291,6 +361,9 @@ impl error::Error for Error {
             #[cfg(feature = "functions")]
             Error::GetAuxWrongType => "get_aux called with wrong type",
             Error::MultipleStatement => "multiple statements provided",
+            Error::MigrationFailed(..) => "migration failed",
+            Error::ScriptFailed(..) => "script failed",
+            Error::SqlInputError { ref msg, .. } => msg,
         }
     }
 
@@ -303,6 +376,7 @@ impl error::Error for Error {
             Error::IntegralValueOutOfRange(..)
             | Error::SqliteSingleThreadedMode
             | Error::InvalidParameterName(_)
+            | Error::InvalidParameterCount(..)
             | Error::ExecuteReturnedResults
             | Error::QueryReturnedNoRows
             | Error::InvalidColumnIndex(_)
@@ -324,6 +398,10 @@ impl error::Error for Error {
             Error::FromSqlConversionFailure(_, _, ref err)
             | Error::ToSqlConversionFailure(ref err) => Some(&**err),
 
+            Error::MigrationFailed(_, ref err) => Some(&**err),
+            Error::ScriptFailed(_, ref err) => Some(&**err),
+            Error::SqlInputError { ref error, .. } => Some(error),
+
             #[cfg(feature = "vtab")]
             Error::ModuleError(_) => None,
 
@@ -351,6 +429,81 @@ pub fn error_from_handle(db: *mut ffi::sqlite3, code: c_int) -> Error {
     error_from_sqlite_code(code, message)
 }
 
+/// Like `error_from_handle`, but turns syntax errors in `sql` into
+/// `Error::SqlInputError`.
+pub fn error_from_handle_with_sql(db: *mut ffi::sqlite3, code: c_int, sql: &str) -> Error {
+    match error_from_handle(db, code) {
+        Error::SqliteFailure(error, Some(msg)) if code == ffi::SQLITE_ERROR => {
+            match unsafe { syntax_error_offset(db, sql, &msg) } {
+                Some(offset) => Error::SqlInputError {
+                    error,
+                    msg,
+                    sql: sql.to_owned(),
+                    offset,
+                },
+                None => Error::SqliteFailure(error, Some(msg)),
+            }
+        }
+        err => err,
+    }
+}
+
+/// Recover the offset `sqlite3_error_offset` would report, which the bundled
+/// SQLite predates. SQLite quotes the offending token (`near "TOKEN": syntax
+/// error`, `unrecognized token: "TOKEN"`) and parses left to right, so the
+/// error is at the first occurrence of the token where the statement, cut
+/// just after it, fails to prepare with the same message.
+unsafe fn syntax_error_offset(db: *mut ffi::sqlite3, sql: &str, msg: &str) -> Option<usize> {
+    if msg == "incomplete input" {
+        return Some(sql.trim_end().len());
+    }
+    let token = if msg.starts_with("near \"") && msg.ends_with("\": syntax error") {
+        &msg["near \"".len()..msg.len() - "\": syntax error".len()]
+    } else if msg.starts_with("unrecognized token: \"") && msg.ends_with('"') {
+        &msg["unrecognized token: \"".len()..msg.len() - 1]
+    } else {
+        return None;
+    };
+    if token.is_empty() {
+        return None;
+    }
+    // start of the statement containing the candidate, and how far `sql`
+    // has been scanned for statement boundaries
+    let mut start = 0;
+    let mut scanned = 0;
+    for (i, _) in sql.match_indices(token) {
+        for (p, _) in sql[scanned..i].match_indices(';') {
+            let end = scanned + p + 1;
+            let prefix = CString::new(&sql[..end]).ok()?;
+            if ffi::sqlite3_complete(prefix.as_ptr()) != 0 {
+                start = end;
+            }
+        }
+        scanned = i;
+        if prepare_error(db, &sql[start..i + token.len()])
+            .as_ref()
+            .map(String::as_str)
+            == Some(msg)
+        {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// The error message preparing `sql` fails with, if any.
+unsafe fn prepare_error(db: *mut ffi::sqlite3, sql: &str) -> Option<String> {
+    let (c_sql, len, _) = str_for_sqlite(sql.as_bytes()).ok()?;
+    let mut stmt = ptr::null_mut();
+    let rc = ffi::sqlite3_prepare_v2(db, c_sql, len, &mut stmt, ptr::null_mut());
+    if rc == ffi::SQLITE_OK {
+        ffi::sqlite3_finalize(stmt);
+        None
+    } else {
+        Some(errmsg_to_string(ffi::sqlite3_errmsg(db)))
+    }
+}
+
 macro_rules! check {
     ($funcall:expr) => {{
         let rc = $funcall;
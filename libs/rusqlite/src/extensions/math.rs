@@ -0,0 +1,72 @@
+//! Math functions matching those SQLite 3.35 added behind
+//! `SQLITE_ENABLE_MATH_FUNCTIONS`.
+//!
+//! Arguments are converted to reals. `NULL` arguments, and results that are
+//! not a number (e.g. `sqrt(-1)`), yield `NULL`.
+use std::f64::consts::PI;
+
+use crate::functions::FunctionFlags;
+use crate::{Connection, Result};
+
+fn flags() -> FunctionFlags {
+    FunctionFlags::SQLITE_UTF8
+        | FunctionFlags::SQLITE_DETERMINISTIC
+        | FunctionFlags::SQLITE_INNOCUOUS
+}
+
+fn number(x: f64) -> Option<f64> {
+    if x.is_nan() {
+        None
+    } else {
+        Some(x)
+    }
+}
+
+fn unary(conn: &Connection, name: &str, f: fn(f64) -> f64) -> Result<()> {
+    conn.create_scalar_function(name, 1, flags(), move |ctx| {
+        Ok(ctx.get::<Option<f64>>(0)?.and_then(|x| number(f(x))))
+    })
+}
+
+fn binary(conn: &Connection, name: &str, f: fn(f64, f64) -> f64) -> Result<()> {
+    conn.create_scalar_function(name, 2, flags(), move |ctx| {
+        let x = ctx.get::<Option<f64>>(0)?;
+        let y = ctx.get::<Option<f64>>(1)?;
+        Ok(match (x, y) {
+            (Some(x), Some(y)) => number(f(x, y)),
+            _ => None,
+        })
+    })
+}
+
+/// Register `sqrt`, `pow`/`power`, `exp`, `ln`, `log` (base 10, or
+/// `log(B, X)`), `log10`, `log2`, `sin`, `cos`, `tan`, `asin`, `acos`,
+/// `atan`, `atan2`, `sinh`, `cosh`, `tanh`, `degrees`, `radians` and `pi`.
+///
+/// # Failure
+///
+/// Will return Err if a function could not be registered.
+pub fn register(conn: &Connection) -> Result<()> {
+    unary(conn, "sqrt", f64::sqrt)?;
+    binary(conn, "pow", f64::powf)?;
+    binary(conn, "power", f64::powf)?;
+    unary(conn, "exp", f64::exp)?;
+    unary(conn, "ln", f64::ln)?;
+    unary(conn, "log", f64::log10)?;
+    binary(conn, "log", |b, x| x.log(b))?;
+    unary(conn, "log10", f64::log10)?;
+    unary(conn, "log2", f64::log2)?;
+    unary(conn, "sin", f64::sin)?;
+    unary(conn, "cos", f64::cos)?;
+    unary(conn, "tan", f64::tan)?;
+    unary(conn, "asin", f64::asin)?;
+    unary(conn, "acos", f64::acos)?;
+    unary(conn, "atan", f64::atan)?;
+    binary(conn, "atan2", f64::atan2)?;
+    unary(conn, "sinh", f64::sinh)?;
+    unary(conn, "cosh", f64::cosh)?;
+    unary(conn, "tanh", f64::tanh)?;
+    unary(conn, "degrees", f64::to_degrees)?;
+    unary(conn, "radians", f64::to_radians)?;
+    conn.create_scalar_function("pi", 0, flags(), |_| Ok(PI))
+}
@@ -0,0 +1,49 @@
+//! SQL functions and collations missing from SQLite's default build.
+//!
+//! Each group lives behind its own feature:
+//!
+//! * `regexp`: the `REGEXP` operator, see `regexp::register`.
+//! * `uuid_functions`: `uuid4()`, `uuid_str(X)` and `uuid_blob(X)`, see
+//!   `uuid::register`.
+//! * `math_functions`: `sqrt`, `pow`, `log`, trigonometric functions, ...
+//!   see `math::register`.
+//! * `unicase_collation`: the `unicase` collation, see `unicase::register`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! fn open() -> Result<Connection> {
+//!     let conn = Connection::open_in_memory()?;
+//!     rusqlite::extensions::register_all(&conn)?;
+//!     conn.query_row("SELECT 'abc' REGEXP '^a'", NO_PARAMS, |r| r.get::<_, bool>(0))?;
+//!     Ok(conn)
+//! }
+//! ```
+use crate::{Connection, Result};
+
+#[cfg(feature = "math_functions")]
+pub mod math;
+#[cfg(feature = "regexp")]
+pub mod regexp;
+#[cfg(feature = "unicase_collation")]
+pub mod unicase;
+#[cfg(feature = "uuid_functions")]
+pub mod uuid;
+
+/// Register every extension enabled at compile time.
+///
+/// # Failure
+///
+/// Will return Err if a function or collation could not be registered.
+pub fn register_all(conn: &Connection) -> Result<()> {
+    #[cfg(feature = "math_functions")]
+    math::register(conn)?;
+    #[cfg(feature = "regexp")]
+    regexp::register(conn)?;
+    #[cfg(feature = "unicase_collation")]
+    unicase::register(conn)?;
+    #[cfg(feature = "uuid_functions")]
+    uuid::register(conn)?;
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! The `REGEXP` operator, backed by the `regex` crate.
+//!
+//! `X REGEXP Y` calls `regexp(Y, X)`. Compiled patterns are cached with
+//! `Context::set_aux` for as long as the pattern argument is constant.
+use regex::Regex;
+
+use crate::functions::FunctionFlags;
+use crate::{Connection, Error, Result};
+
+/// Register the `regexp(pattern, text)` function. It returns `NULL` when
+/// either argument is `NULL`.
+///
+/// # Failure
+///
+/// Will return Err if the function could not be registered.
+pub fn register(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8
+            | FunctionFlags::SQLITE_DETERMINISTIC
+            | FunctionFlags::SQLITE_INNOCUOUS,
+        |ctx| {
+            let text = match ctx.get::<Option<String>>(1)? {
+                Some(text) => text,
+                None => return Ok(None),
+            };
+            if let Some(re) = ctx.get_aux::<Regex>(0)? {
+                return Ok(Some(re.is_match(&text)));
+            }
+            let pattern = match ctx.get::<Option<String>>(0)? {
+                Some(pattern) => pattern,
+                None => return Ok(None),
+            };
+            let re = Regex::new(&pattern).map_err(|err| Error::UserFunctionError(Box::new(err)))?;
+            let is_match = re.is_match(&text);
+            ctx.set_aux(0, re);
+            Ok(Some(is_match))
+        },
+    )
+}
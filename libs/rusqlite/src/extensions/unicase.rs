@@ -0,0 +1,14 @@
+//! The `unicase` collation, which compares text using Unicode case folding.
+use unicase::UniCase;
+
+use crate::{Connection, Result};
+
+/// Register the `unicase` collation, e.g.
+/// `SELECT * FROM t ORDER BY name COLLATE unicase`.
+///
+/// # Failure
+///
+/// Will return Err if the collation could not be registered.
+pub fn register(conn: &Connection) -> Result<()> {
+    conn.create_collation("unicase", |s1, s2| UniCase::new(s1).cmp(&UniCase::new(s2)))
+}
@@ -0,0 +1,47 @@
+//! UUID functions, modeled after SQLite's `ext/misc/uuid.c`.
+//!
+//! * `uuid4()` returns a random version 4 UUID as text.
+//! * `uuid_str(X)` converts a UUID given as text or as a 16 byte blob into
+//!   its canonical text form.
+//! * `uuid_blob(X)` converts a UUID given as text or as a 16 byte blob into a
+//!   16 byte blob.
+//!
+//! Both conversions return `NULL` when `X` is not a valid UUID.
+use uuid::Uuid;
+
+use crate::functions::{Context, FunctionFlags};
+use crate::types::ValueRef;
+use crate::{Connection, Result};
+
+fn uuid_arg(ctx: &Context<'_>) -> Option<Uuid> {
+    match ctx.get_raw(0) {
+        ValueRef::Blob(b) => Uuid::from_slice(b).ok(),
+        ValueRef::Text(s) => std::str::from_utf8(s)
+            .ok()
+            .and_then(|s| Uuid::parse_str(s.trim_matches(|c| c == '{' || c == '}')).ok()),
+        _ => None,
+    }
+}
+
+/// Register the `uuid4`, `uuid_str` and `uuid_blob` functions.
+///
+/// # Failure
+///
+/// Will return Err if a function could not be registered.
+pub fn register(conn: &Connection) -> Result<()> {
+    let deterministic = FunctionFlags::SQLITE_UTF8
+        | FunctionFlags::SQLITE_DETERMINISTIC
+        | FunctionFlags::SQLITE_INNOCUOUS;
+    conn.create_scalar_function(
+        "uuid4",
+        0,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_INNOCUOUS,
+        |_| Ok(Uuid::new_v4().to_hyphenated().to_string()),
+    )?;
+    conn.create_scalar_function("uuid_str", 1, deterministic, |ctx| {
+        Ok(uuid_arg(ctx).map(|uuid| uuid.to_hyphenated().to_string()))
+    })?;
+    conn.create_scalar_function("uuid_blob", 1, deterministic, |ctx| {
+        Ok(uuid_arg(ctx).map(|uuid| uuid.as_bytes().to_vec()))
+    })
+}
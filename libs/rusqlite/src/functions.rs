@@ -67,7 +67,8 @@
 //!     Ok(())
 //! }
 //! ```
-use std::os::raw::{c_int, c_void};
+use std::cell::Cell;
+use std::os::raw::{c_int, c_uint, c_void};
 use std::panic::{catch_unwind, RefUnwindSafe, UnwindSafe};
 use std::ptr;
 use std::slice;
@@ -77,6 +78,8 @@ use crate::ffi::sqlite3_context;
 use crate::ffi::sqlite3_value;
 
 use crate::context::set_result;
+#[cfg(feature = "pointer")]
+use crate::types::pointer::pointer_value;
 use crate::types::{FromSql, FromSqlError, ToSql, ValueRef};
 
 use crate::{str_to_cstring, Connection, Error, InnerConnection, Result};
@@ -115,6 +118,7 @@ unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
 pub struct Context<'a> {
     ctx: *mut sqlite3_context,
     args: &'a [*mut sqlite3_value],
+    result_subtype: Cell<Option<c_uint>>,
 }
 
 impl Context<'_> {
@@ -168,6 +172,42 @@ impl Context<'_> {
         unsafe { ValueRef::from_value(arg) }
     }
 
+    /// Returns the subtype of the `idx`th argument, `0` if it has none. See
+    /// https://www.sqlite.org/c3ref/value_subtype.html.
+    ///
+    /// Functions reading subtypes should be created with
+    /// `FunctionFlags::SQLITE_SUBTYPE`.
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to `self.len()`.
+    pub fn get_subtype(&self, idx: usize) -> c_uint {
+        let arg = self.args[idx];
+        unsafe { ffi::sqlite3_value_subtype(arg) }
+    }
+
+    /// Sets the subtype of the result of a scalar function. Only the lower 8
+    /// bits are kept. It is applied once the function returns, after its
+    /// result has been set, and is ignored for aggregates.
+    pub fn set_result_subtype(&self, subtype: c_uint) {
+        self.result_subtype.set(Some(subtype));
+    }
+
+    /// Returns the value of the `idx`th argument if it is a `Pointer<T>`
+    /// tagged with `type_name`, `None` otherwise.
+    ///
+    /// # Failure
+    ///
+    /// Will panic if `idx` is greater than or equal to `self.len()`.
+    ///
+    /// Will return Err if `type_name` contains a nul byte.
+    #[cfg(feature = "pointer")]
+    pub fn get_pointer<T: 'static>(&self, idx: usize, type_name: &str) -> Result<Option<&T>> {
+        let arg = self.args[idx];
+        let c_name = str_to_cstring(type_name)?;
+        Ok(unsafe { pointer_value(ffi::sqlite3_value_pointer(arg, c_name.as_ptr())) })
+    }
+
     /// Sets the auxilliary data associated with a particular parameter. See
     /// https://www.sqlite.org/c3ref/get_auxdata.html for a discussion of
     /// this feature, or the unit tests of this module for an example.
@@ -396,22 +436,31 @@ impl InnerConnection {
                 let ctx = Context {
                     ctx,
                     args: slice::from_raw_parts(argv, argc as usize),
+                    result_subtype: Cell::new(None),
                 };
-                (*boxed_f)(&ctx)
+                (*boxed_f)(&ctx).map(|t| (t, ctx.result_subtype.get()))
             });
-            let t = match r {
+            let (t, subtype) = match r {
                 Err(_) => {
                     report_error(ctx, &Error::UnwindingPanic);
                     return;
                 }
-                Ok(r) => r,
+                Ok(Err(err)) => {
+                    report_error(ctx, &err);
+                    return;
+                }
+                Ok(Ok(r)) => r,
             };
-            let t = t.as_ref().map(|t| ToSql::to_sql(t));
 
-            match t {
-                Ok(Ok(ref value)) => set_result(ctx, value),
-                Ok(Err(err)) => report_error(ctx, &err),
-                Err(err) => report_error(ctx, err),
+            match t.to_sql() {
+                Ok(ref value) => {
+                    set_result(ctx, value);
+                    // Setting the result clears the subtype, so it goes last.
+                    if let Some(subtype) = subtype {
+                        ffi::sqlite3_result_subtype(ctx, subtype);
+                    }
+                }
+                Err(err) => report_error(ctx, &err),
             }
         }
 
@@ -551,6 +600,7 @@ unsafe extern "C" fn call_boxed_step<A, D, T>(
         let mut ctx = Context {
             ctx,
             args: slice::from_raw_parts(argv, argc as usize),
+            result_subtype: Cell::new(None),
         };
         (*boxed_aggr).step(&mut ctx, &mut **pac)
     });
@@ -594,6 +644,7 @@ unsafe extern "C" fn call_boxed_inverse<A, W, T>(
         let mut ctx = Context {
             ctx,
             args: slice::from_raw_parts(argv, argc as usize),
+            result_subtype: Cell::new(None),
         };
         (*boxed_aggr).inverse(&mut ctx, &mut **pac)
     });
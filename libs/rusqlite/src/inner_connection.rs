@@ -11,7 +11,7 @@ use std::sync::{Arc, Mutex};
 use super::ffi;
 use super::{str_for_sqlite, str_to_cstring};
 use super::{Connection, InterruptHandle, OpenFlags, Result};
-use crate::error::{error_from_handle, error_from_sqlite_code, Error};
+use crate::error::{error_from_handle, error_from_handle_with_sql, error_from_sqlite_code, Error};
 use crate::raw_statement::RawStatement;
 use crate::statement::Statement;
 use crate::unlock_notify;
@@ -188,7 +188,11 @@ impl InnerConnection {
                 ptr::null_mut(),
                 ptr::null_mut(),
             );
-            self.decode_result(r)
+            if r == ffi::SQLITE_OK {
+                Ok(())
+            } else {
+                Err(error_from_handle_with_sql(self.db(), r, sql))
+            }
         }
     }
 
@@ -235,6 +239,16 @@ impl InnerConnection {
     }
 
     pub fn prepare<'a>(&mut self, conn: &'a Connection, sql: &str) -> Result<Statement<'a>> {
+        self.prepare_with_tail(conn, sql).map(|(stmt, _)| stmt)
+    }
+
+    /// Like `prepare`, also returning the byte offset in `sql` where the
+    /// first statement ends.
+    pub fn prepare_with_tail<'a>(
+        &mut self,
+        conn: &'a Connection,
+        sql: &str,
+    ) -> Result<(Statement<'a>, usize)> {
         let mut c_stmt = MaybeUninit::uninit();
         let (c_sql, len, _) = str_for_sqlite(sql.as_bytes())?;
         let mut c_tail = MaybeUninit::uninit();
@@ -269,20 +283,31 @@ impl InnerConnection {
             }
         };
         // If there is an error, *ppStmt is set to NULL.
-        self.decode_result(r)?;
+        if r != ffi::SQLITE_OK {
+            return Err(error_from_handle_with_sql(self.db(), r, sql));
+        }
         // If the input text contains no SQL (if the input is an empty string or a
         // comment) then *ppStmt is set to NULL.
         let c_stmt: *mut ffi::sqlite3_stmt = unsafe { c_stmt.assume_init() };
         let c_tail: *const c_char = unsafe { c_tail.assume_init() };
         // TODO ignore spaces, comments, ... at the end
         let tail = !c_tail.is_null() && unsafe { c_tail != c_sql.offset(len as isize) };
-        Ok(Statement::new(conn, RawStatement::new(c_stmt, tail)))
+        let end = if c_tail.is_null() {
+            sql.len()
+        } else {
+            c_tail as usize - c_sql as usize
+        };
+        Ok((Statement::new(conn, RawStatement::new(c_stmt, tail)), end))
     }
 
     pub fn changes(&mut self) -> usize {
         unsafe { ffi::sqlite3_changes(self.db()) as usize }
     }
 
+    pub fn total_changes(&self) -> usize {
+        unsafe { ffi::sqlite3_total_changes(self.db()) as usize }
+    }
+
     pub fn is_autocommit(&self) -> bool {
         unsafe { ffi::sqlite3_get_autocommit(self.db()) != 0 }
     }
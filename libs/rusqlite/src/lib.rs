@@ -70,38 +70,69 @@ use std::str;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
 
+pub use crate::attach::{AttachSource, AttachedDb, DatabaseInfo};
 pub use crate::cache::StatementCache;
 use crate::inner_connection::{InnerConnection, BYPASS_SQLITE_INIT};
 use crate::raw_statement::RawStatement;
 use crate::types::ValueRef;
 
-pub use crate::cache::CachedStatement;
+pub use crate::cache::{normalize_sql, CacheStats, CachedStatement};
+#[cfg(feature = "collation")]
+pub use crate::collation::CollationRegistry;
 pub use crate::column::Column;
+pub use crate::constraint::{ConstraintKind, ConstraintViolation};
+pub use crate::dump::DumpOptions;
 pub use crate::error::Error;
 pub use crate::ffi::ErrorCode;
 #[cfg(feature = "hooks")]
 pub use crate::hooks::Action;
 #[cfg(feature = "load_extension")]
 pub use crate::load_extension_guard::LoadExtensionGuard;
+pub use crate::query_plan::QueryPlan;
 pub use crate::row::{AndThenRows, MappedRows, Row, RowIndex, Rows};
+pub use crate::script::{is_complete, StatementResult};
+#[cfg(feature = "serde")]
+pub use crate::serde_impl::{to_named_params, to_params, NamedParams};
 pub use crate::statement::{Statement, StatementStatus};
-pub use crate::transaction::{DropBehavior, Savepoint, Transaction, TransactionBehavior};
+#[cfg(feature = "derive")]
+pub use crate::table::{Table, ToParams};
+pub use crate::transaction::{
+    DropBehavior, RetryPolicy, Savepoint, SavepointName, Transaction, TransactionBehavior,
+};
 pub use crate::types::ToSql;
 pub use crate::version::*;
+#[cfg(feature = "derive")]
+pub use rusqlite_derive::{FromRow, Table, ToParams};
 
 #[macro_use]
 mod error;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(any(
+    feature = "math_functions",
+    feature = "regexp",
+    feature = "unicase_collation",
+    feature = "uuid_functions"
+))]
+pub mod extensions;
 
 #[cfg(feature = "blob")]
 pub mod blob;
+mod attach;
+#[cfg(feature = "blobstore")]
+pub mod blobstore;
 mod busy;
 mod cache;
+mod clock;
 #[cfg(feature = "collation")]
 mod collation;
 mod column;
+mod constraint;
 pub mod config;
 #[cfg(any(feature = "functions", feature = "vtab"))]
 mod context;
+pub mod diff;
+mod dump;
 #[cfg(feature = "functions")]
 pub mod functions;
 #[cfg(feature = "hooks")]
@@ -111,12 +142,24 @@ mod inner_connection;
 pub mod limits;
 #[cfg(feature = "load_extension")]
 mod load_extension_guard;
+#[cfg(feature = "memvfs")]
+pub mod memvfs;
+pub mod migrations;
 // public for tests only
 pub mod pragma;
+pub mod query_plan;
 mod raw_statement;
 mod row;
+pub mod schema;
+mod script;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
 
 mod statement;
+#[cfg(feature = "derive")]
+mod table;
 #[cfg(feature = "trace")]
 pub mod trace;
 mod transaction;
@@ -125,6 +168,8 @@ mod unlock_notify;
 mod version;
 #[cfg(feature = "vtab")]
 pub mod vtab;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 // Number of cached prepared statements we'll hold on to.
 const STATEMENT_CACHE_DEFAULT_CAPACITY: usize = 16;
@@ -308,6 +353,10 @@ pub struct Connection {
     // made public for tests
     pub cache: StatementCache,
     path: Option<PathBuf>,
+    // boxed so that its address, handed to `sqlite3_collation_needed`, is
+    // stable; dropped after `db` is closed.
+    #[cfg(feature = "collation")]
+    collations: Box<CollationRegistry>,
 }
 
 unsafe impl Send for Connection {}
@@ -379,6 +428,8 @@ impl Connection {
             db: RefCell::new(db),
             cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
             path: Some(path.as_ref().to_path_buf()),
+            #[cfg(feature = "collation")]
+            collations: Box::default(),
         })
     }
 
@@ -403,6 +454,8 @@ impl Connection {
             db: RefCell::new(db),
             cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
             path: Some(path.as_ref().to_path_buf()),
+            #[cfg(feature = "collation")]
+            collations: Box::default(),
         })
     }
 
@@ -764,6 +817,8 @@ impl Connection {
             db: RefCell::new(db),
             cache: StatementCache::with_capacity(STATEMENT_CACHE_DEFAULT_CAPACITY),
             path: db_path,
+            #[cfg(feature = "collation")]
+            collations: Box::default(),
         })
     }
 
@@ -784,6 +839,12 @@ impl Connection {
         self.db.borrow_mut().changes()
     }
 
+    /// Return the number of rows modified, inserted or deleted since the
+    /// connection was opened, including by triggers.
+    fn total_changes(&self) -> usize {
+        self.db.borrow().total_changes()
+    }
+
     /// Test for auto-commit mode.
     /// Autocommit mode is on by default.
     pub fn is_autocommit(&self) -> bool {
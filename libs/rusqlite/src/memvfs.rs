@@ -0,0 +1,576 @@
+//! A VFS keeping database files on the heap, so that databases can be in
+//! WAL mode where there is no filesystem, as in the wasm build.
+//!
+//! Files are named, and every connection of the thread that opens the same
+//! name through this VFS sees the same file. The WAL index, which the unix
+//! VFS keeps in a memory-mapped `-shm` file, lives in heap regions shared
+//! by those connections, and locks are counted instead of taken from the OS.
+//! A file lives until it is deleted, so a database outlives its connections
+//! until the end of the thread.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{memvfs, Connection, OpenFlags, Result, NO_PARAMS};
+//! fn open_wal(name: &str) -> Result<Connection> {
+//!     memvfs::register()?;
+//!     let conn = Connection::open_with_flags_and_vfs(name, OpenFlags::default(), memvfs::NAME)?;
+//!     conn.query_row("PRAGMA journal_mode = WAL", NO_PARAMS, |_| Ok(()))?;
+//!     Ok(conn)
+//! }
+//! ```
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::rc::Rc;
+use std::slice;
+
+use crate::ffi;
+use crate::{Error, Result};
+
+/// The name the VFS is registered under, to pass to
+/// `Connection::open_with_flags_and_vfs` or as the `vfs` URI parameter.
+pub const NAME: &str = "memvfs";
+
+const MAX_PATHNAME: c_int = 512;
+const SECTOR_SIZE: c_int = 4096;
+
+thread_local! {
+    static FILES: RefCell<HashMap<Vec<u8>, Rc<Node>>> = RefCell::new(HashMap::new());
+}
+
+/// Register the VFS, without making it the default. Registering it again
+/// does nothing.
+///
+/// # Failure
+///
+/// Will return `Err` if there is no default VFS to take the randomness and
+/// the time from, or if SQLite fails to register the VFS.
+pub fn register() -> Result<()> {
+    let name = CString::new(NAME).unwrap();
+    unsafe {
+        if !ffi::sqlite3_vfs_find(name.as_ptr()).is_null() {
+            return Ok(());
+        }
+        let parent = ffi::sqlite3_vfs_find(ptr::null());
+        if parent.is_null() {
+            return Err(Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ERROR),
+                Some("no default VFS".to_owned()),
+            ));
+        }
+        let vfs = Box::leak(Box::new(ffi::sqlite3_vfs {
+            iVersion: 2,
+            szOsFile: mem::size_of::<File>() as c_int,
+            mxPathname: MAX_PATHNAME,
+            pNext: ptr::null_mut(),
+            zName: Box::leak(name.into_boxed_c_str()).as_ptr(),
+            pAppData: parent as *mut c_void,
+            xOpen: Some(x_open),
+            xDelete: Some(x_delete),
+            xAccess: Some(x_access),
+            xFullPathname: Some(x_full_pathname),
+            xDlOpen: None,
+            xDlError: None,
+            xDlSym: None,
+            xDlClose: None,
+            xRandomness: Some(x_randomness),
+            xSleep: Some(x_sleep),
+            xCurrentTime: Some(x_current_time),
+            xGetLastError: Some(x_get_last_error),
+            xCurrentTimeInt64: Some(x_current_time_int64),
+            xSetSystemCall: None,
+            xGetSystemCall: None,
+            xNextSystemCall: None,
+        }));
+        let r = ffi::sqlite3_vfs_register(vfs, 0);
+        if r == ffi::SQLITE_OK {
+            Ok(())
+        } else {
+            Err(Error::SqliteFailure(ffi::Error::new(r), None))
+        }
+    }
+}
+
+/// The contents and the locks of a file, shared by its open handles.
+#[derive(Default)]
+struct Node {
+    data: RefCell<Vec<u8>>,
+    /// Number of handles holding at least a `SHARED` lock.
+    shared: Cell<u32>,
+    /// Whether a handle holds at least a `RESERVED` lock.
+    reserved: Cell<bool>,
+    /// Whether a handle waits for the others to release their `SHARED`
+    /// locks, which keeps new readers out.
+    pending: Cell<bool>,
+    exclusive: Cell<bool>,
+    shm: RefCell<Shm>,
+}
+
+/// The WAL index of a database file.
+#[derive(Default)]
+struct Shm {
+    /// `u64`s keep the regions 8-byte aligned, as SQLite expects.
+    regions: Vec<Box<[u64]>>,
+    /// For each lock slot, the number of shared holders, or -1 when held
+    /// exclusively.
+    locks: [i32; ffi::SQLITE_SHM_NLOCK as usize],
+    /// Number of handles that have mapped the regions.
+    mapped: u32,
+}
+
+/// An open handle, allocated by SQLite with the size given in `szOsFile`.
+#[repr(C)]
+struct File {
+    base: ffi::sqlite3_file,
+    /// From `Rc::into_raw`, released in `x_close`.
+    node: *const Node,
+    /// The name to unlink on close, for `SQLITE_OPEN_DELETEONCLOSE`.
+    delete_on_close: Option<Vec<u8>>,
+    lock: c_int,
+    shm_mapped: bool,
+    /// Bit `i` is set when the handle holds lock slot `i` of the WAL index.
+    shm_shared: u8,
+    shm_exclusive: u8,
+}
+
+impl File {
+    unsafe fn from_raw<'a>(file: *mut ffi::sqlite3_file) -> &'a mut File {
+        &mut *(file as *mut File)
+    }
+
+    fn node(&self) -> &Node {
+        unsafe { &*self.node }
+    }
+}
+
+static IO_METHODS: ffi::sqlite3_io_methods = ffi::sqlite3_io_methods {
+    iVersion: 2,
+    xClose: Some(x_close),
+    xRead: Some(x_read),
+    xWrite: Some(x_write),
+    xTruncate: Some(x_truncate),
+    xSync: Some(x_sync),
+    xFileSize: Some(x_file_size),
+    xLock: Some(x_lock),
+    xUnlock: Some(x_unlock),
+    xCheckReservedLock: Some(x_check_reserved_lock),
+    xFileControl: Some(x_file_control),
+    xSectorSize: Some(x_sector_size),
+    xDeviceCharacteristics: Some(x_device_characteristics),
+    xShmMap: Some(x_shm_map),
+    xShmLock: Some(x_shm_lock),
+    xShmBarrier: Some(x_shm_barrier),
+    xShmUnmap: Some(x_shm_unmap),
+    xFetch: None,
+    xUnfetch: None,
+};
+
+unsafe fn parent<'a>(vfs: *mut ffi::sqlite3_vfs) -> &'a mut ffi::sqlite3_vfs {
+    &mut *((*vfs).pAppData as *mut ffi::sqlite3_vfs)
+}
+
+unsafe extern "C" fn x_open(
+    _vfs: *mut ffi::sqlite3_vfs,
+    z_name: *const c_char,
+    file: *mut ffi::sqlite3_file,
+    flags: c_int,
+    p_out_flags: *mut c_int,
+) -> c_int {
+    // SQLite only calls xClose if pMethods is set.
+    (*file).pMethods = ptr::null();
+    let (node, delete_on_close) = if z_name.is_null() {
+        // a temporary file, which nothing else can open
+        (Rc::new(Node::default()), None)
+    } else {
+        let name = CStr::from_ptr(z_name).to_bytes().to_vec();
+        let node = FILES.with(|files| {
+            let mut files = files.borrow_mut();
+            match files.get(&name) {
+                Some(node) => Some(Rc::clone(node)),
+                None if flags & ffi::SQLITE_OPEN_CREATE != 0 => {
+                    let node = Rc::new(Node::default());
+                    files.insert(name.clone(), Rc::clone(&node));
+                    Some(node)
+                }
+                None => None,
+            }
+        });
+        let node = match node {
+            Some(node) => node,
+            None => return ffi::SQLITE_CANTOPEN,
+        };
+        let delete_on_close = if flags & ffi::SQLITE_OPEN_DELETEONCLOSE != 0 {
+            Some(name)
+        } else {
+            None
+        };
+        (node, delete_on_close)
+    };
+    ptr::write(
+        file as *mut File,
+        File {
+            base: ffi::sqlite3_file {
+                pMethods: &IO_METHODS,
+            },
+            node: Rc::into_raw(node),
+            delete_on_close,
+            lock: ffi::SQLITE_LOCK_NONE,
+            shm_mapped: false,
+            shm_shared: 0,
+            shm_exclusive: 0,
+        },
+    );
+    if !p_out_flags.is_null() {
+        *p_out_flags = flags;
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_delete(
+    _vfs: *mut ffi::sqlite3_vfs,
+    z_name: *const c_char,
+    _sync_dir: c_int,
+) -> c_int {
+    let name = CStr::from_ptr(z_name).to_bytes();
+    // open handles keep the contents until they close
+    match FILES.with(|files| files.borrow_mut().remove(name)) {
+        Some(_) => ffi::SQLITE_OK,
+        None => ffi::SQLITE_IOERR_DELETE_NOENT,
+    }
+}
+
+unsafe extern "C" fn x_access(
+    _vfs: *mut ffi::sqlite3_vfs,
+    z_name: *const c_char,
+    flags: c_int,
+    p_res_out: *mut c_int,
+) -> c_int {
+    let name = CStr::from_ptr(z_name).to_bytes();
+    let exists = FILES.with(|files| match files.borrow().get(name) {
+        // like the unix VFS, an empty file does not exist
+        Some(node) => flags != ffi::SQLITE_ACCESS_EXISTS || !node.data.borrow().is_empty(),
+        None => false,
+    });
+    *p_res_out = exists as c_int;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_full_pathname(
+    _vfs: *mut ffi::sqlite3_vfs,
+    z_name: *const c_char,
+    n_out: c_int,
+    z_out: *mut c_char,
+) -> c_int {
+    let name = CStr::from_ptr(z_name).to_bytes_with_nul();
+    if name.len() > n_out as usize {
+        return ffi::SQLITE_CANTOPEN;
+    }
+    ptr::copy_nonoverlapping(name.as_ptr() as *const c_char, z_out, name.len());
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_randomness(
+    vfs: *mut ffi::sqlite3_vfs,
+    n_byte: c_int,
+    z_out: *mut c_char,
+) -> c_int {
+    let parent = parent(vfs);
+    parent.xRandomness.unwrap()(parent, n_byte, z_out)
+}
+
+unsafe extern "C" fn x_sleep(vfs: *mut ffi::sqlite3_vfs, microseconds: c_int) -> c_int {
+    let parent = parent(vfs);
+    parent.xSleep.unwrap()(parent, microseconds)
+}
+
+unsafe extern "C" fn x_current_time(vfs: *mut ffi::sqlite3_vfs, p_time: *mut f64) -> c_int {
+    let parent = parent(vfs);
+    parent.xCurrentTime.unwrap()(parent, p_time)
+}
+
+unsafe extern "C" fn x_get_last_error(
+    vfs: *mut ffi::sqlite3_vfs,
+    n_byte: c_int,
+    z_out: *mut c_char,
+) -> c_int {
+    let parent = parent(vfs);
+    match parent.xGetLastError {
+        Some(get_last_error) => get_last_error(parent, n_byte, z_out),
+        None => 0,
+    }
+}
+
+unsafe extern "C" fn x_current_time_int64(
+    vfs: *mut ffi::sqlite3_vfs,
+    p_time: *mut ffi::sqlite3_int64,
+) -> c_int {
+    let parent = parent(vfs);
+    match parent.xCurrentTimeInt64 {
+        Some(current_time) if parent.iVersion >= 2 => current_time(parent, p_time),
+        _ => {
+            let mut days = 0.0;
+            let r = parent.xCurrentTime.unwrap()(parent, &mut days);
+            *p_time = (days * 86_400_000.0) as ffi::sqlite3_int64;
+            r
+        }
+    }
+}
+
+unsafe extern "C" fn x_close(file: *mut ffi::sqlite3_file) -> c_int {
+    x_shm_unmap(file, 0);
+    x_unlock(file, ffi::SQLITE_LOCK_NONE);
+    let file = ptr::read(file as *mut File);
+    let node = Rc::from_raw(file.node);
+    if let Some(name) = file.delete_on_close {
+        FILES.with(|files| {
+            let mut files = files.borrow_mut();
+            if files.get(&name).map_or(false, |n| Rc::ptr_eq(n, &node)) {
+                files.remove(&name);
+            }
+        });
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_read(
+    file: *mut ffi::sqlite3_file,
+    buf: *mut c_void,
+    i_amt: c_int,
+    i_ofst: ffi::sqlite3_int64,
+) -> c_int {
+    let file = File::from_raw(file);
+    let data = file.node().data.borrow();
+    let buf = slice::from_raw_parts_mut(buf as *mut u8, i_amt as usize);
+    let start = (i_ofst as usize).min(data.len());
+    let end = (i_ofst as usize + buf.len()).min(data.len());
+    let n = end - start;
+    buf[..n].copy_from_slice(&data[start..end]);
+    if n < buf.len() {
+        // SQLite expects the rest of the buffer zeroed on a short read
+        for b in &mut buf[n..] {
+            *b = 0;
+        }
+        return ffi::SQLITE_IOERR_SHORT_READ;
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_write(
+    file: *mut ffi::sqlite3_file,
+    buf: *const c_void,
+    i_amt: c_int,
+    i_ofst: ffi::sqlite3_int64,
+) -> c_int {
+    let file = File::from_raw(file);
+    let mut data = file.node().data.borrow_mut();
+    let buf = slice::from_raw_parts(buf as *const u8, i_amt as usize);
+    let start = i_ofst as usize;
+    let end = start + buf.len();
+    if data.len() < end {
+        data.resize(end, 0);
+    }
+    data[start..end].copy_from_slice(buf);
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_truncate(file: *mut ffi::sqlite3_file, size: ffi::sqlite3_int64) -> c_int {
+    let file = File::from_raw(file);
+    file.node().data.borrow_mut().truncate(size as usize);
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_sync(_file: *mut ffi::sqlite3_file, _flags: c_int) -> c_int {
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_file_size(
+    file: *mut ffi::sqlite3_file,
+    p_size: *mut ffi::sqlite3_int64,
+) -> c_int {
+    let file = File::from_raw(file);
+    *p_size = file.node().data.borrow().len() as ffi::sqlite3_int64;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_lock(file: *mut ffi::sqlite3_file, level: c_int) -> c_int {
+    let file = File::from_raw(file);
+    if file.lock >= level {
+        return ffi::SQLITE_OK;
+    }
+    let node = &*file.node;
+    match level {
+        ffi::SQLITE_LOCK_SHARED => {
+            if node.pending.get() || node.exclusive.get() {
+                return ffi::SQLITE_BUSY;
+            }
+            node.shared.set(node.shared.get() + 1);
+        }
+        ffi::SQLITE_LOCK_RESERVED => {
+            if node.reserved.get() {
+                return ffi::SQLITE_BUSY;
+            }
+            node.reserved.set(true);
+        }
+        _ => {
+            // EXCLUSIVE, through PENDING until the other readers are gone
+            if file.lock < ffi::SQLITE_LOCK_RESERVED {
+                if node.reserved.get() {
+                    return ffi::SQLITE_BUSY;
+                }
+                node.reserved.set(true);
+            }
+            node.pending.set(true);
+            file.lock = ffi::SQLITE_LOCK_PENDING;
+            if node.shared.get() > 1 {
+                return ffi::SQLITE_BUSY;
+            }
+            node.exclusive.set(true);
+        }
+    }
+    file.lock = level;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_unlock(file: *mut ffi::sqlite3_file, level: c_int) -> c_int {
+    let file = File::from_raw(file);
+    if file.lock <= level {
+        return ffi::SQLITE_OK;
+    }
+    let node = &*file.node;
+    if file.lock > ffi::SQLITE_LOCK_SHARED {
+        node.reserved.set(false);
+        node.pending.set(false);
+        node.exclusive.set(false);
+    }
+    if level == ffi::SQLITE_LOCK_NONE {
+        node.shared.set(node.shared.get() - 1);
+    }
+    file.lock = level;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_check_reserved_lock(
+    file: *mut ffi::sqlite3_file,
+    p_res_out: *mut c_int,
+) -> c_int {
+    let file = File::from_raw(file);
+    *p_res_out = file.node().reserved.get() as c_int;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_file_control(
+    _file: *mut ffi::sqlite3_file,
+    _op: c_int,
+    _p_arg: *mut c_void,
+) -> c_int {
+    ffi::SQLITE_NOTFOUND
+}
+
+unsafe extern "C" fn x_sector_size(_file: *mut ffi::sqlite3_file) -> c_int {
+    SECTOR_SIZE
+}
+
+unsafe extern "C" fn x_device_characteristics(_file: *mut ffi::sqlite3_file) -> c_int {
+    0
+}
+
+unsafe extern "C" fn x_shm_map(
+    file: *mut ffi::sqlite3_file,
+    i_pg: c_int,
+    pgsz: c_int,
+    b_extend: c_int,
+    pp: *mut *mut c_void,
+) -> c_int {
+    let file = File::from_raw(file);
+    let mut shm = (*file.node).shm.borrow_mut();
+    if !file.shm_mapped {
+        file.shm_mapped = true;
+        shm.mapped += 1;
+    }
+    let i_pg = i_pg as usize;
+    if shm.regions.len() <= i_pg {
+        if b_extend == 0 {
+            *pp = ptr::null_mut();
+            return ffi::SQLITE_OK;
+        }
+        let words = (pgsz as usize + 7) / 8;
+        while shm.regions.len() <= i_pg {
+            shm.regions.push(vec![0; words].into_boxed_slice());
+        }
+    }
+    // boxed regions do not move when `regions` grows
+    *pp = shm.regions[i_pg].as_mut_ptr() as *mut c_void;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_shm_lock(
+    file: *mut ffi::sqlite3_file,
+    offset: c_int,
+    n: c_int,
+    flags: c_int,
+) -> c_int {
+    let file = File::from_raw(file);
+    let mut shm = (*file.node).shm.borrow_mut();
+    let slots = offset as usize..(offset + n) as usize;
+    let mask = slots.clone().fold(0u8, |mask, i| mask | 1 << i);
+    if flags & ffi::SQLITE_SHM_UNLOCK != 0 {
+        for i in slots {
+            if file.shm_shared & 1 << i != 0 {
+                shm.locks[i] -= 1;
+            } else if file.shm_exclusive & 1 << i != 0 {
+                shm.locks[i] = 0;
+            }
+        }
+        file.shm_shared &= !mask;
+        file.shm_exclusive &= !mask;
+    } else if flags & ffi::SQLITE_SHM_SHARED != 0 {
+        // SQLite takes shared locks one slot at a time
+        if file.shm_shared & mask == mask {
+            return ffi::SQLITE_OK;
+        }
+        if slots.clone().any(|i| shm.locks[i] < 0) {
+            return ffi::SQLITE_BUSY;
+        }
+        for i in slots {
+            shm.locks[i] += 1;
+        }
+        file.shm_shared |= mask;
+    } else {
+        if slots
+            .clone()
+            .any(|i| shm.locks[i] != 0 && file.shm_exclusive & 1 << i == 0)
+        {
+            return ffi::SQLITE_BUSY;
+        }
+        for i in slots {
+            shm.locks[i] = -1;
+        }
+        file.shm_exclusive |= mask;
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_shm_barrier(_file: *mut ffi::sqlite3_file) {
+    // the connections sharing the regions all run on this thread
+}
+
+unsafe extern "C" fn x_shm_unmap(file: *mut ffi::sqlite3_file, _delete_flag: c_int) -> c_int {
+    let unlock = ffi::SQLITE_SHM_UNLOCK | ffi::SQLITE_SHM_EXCLUSIVE;
+    x_shm_lock(file, 0, ffi::SQLITE_SHM_NLOCK, unlock);
+    let file = File::from_raw(file);
+    if !file.shm_mapped {
+        return ffi::SQLITE_OK;
+    }
+    file.shm_mapped = false;
+    let mut shm = (*file.node).shm.borrow_mut();
+    shm.mapped -= 1;
+    if shm.mapped == 0 {
+        // the next connection rebuilds the index from the WAL file
+        shm.regions.clear();
+    }
+    ffi::SQLITE_OK
+}
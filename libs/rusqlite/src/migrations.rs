@@ -0,0 +1,236 @@
+//! Ordered schema migrations tracked with `PRAGMA user_version`.
+//!
+//! Step `n` (counting from 1) migrates the database from `user_version`
+//! `n - 1` to `n`, and its optional down step back again. Each step runs in
+//! its own transaction together with the `user_version` update and a
+//! `PRAGMA foreign_key_check`, so a failing step leaves the database at the
+//! version of the last step that succeeded.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! # use rusqlite::migrations::{Migrations, M};
+//! fn open() -> Result<Connection> {
+//!     let mut conn = Connection::open("app.db")?;
+//!     let migrations = Migrations::new(vec![
+//!         M::up("CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT);")
+//!             .down("DROP TABLE person;"),
+//!         M::up_with(|conn| {
+//!             conn.execute_batch("ALTER TABLE person ADD COLUMN email TEXT;")
+//!         }),
+//!     ]);
+//!     migrations.to_latest(&mut conn)?;
+//!     Ok(conn)
+//! }
+//! ```
+use std::fmt;
+use std::os::raw::c_int;
+
+use crate::ffi;
+use crate::{Connection, Error, Result, NO_PARAMS};
+
+enum Step<'a> {
+    Sql(&'a str),
+    Func(Box<dyn Fn(&Connection) -> Result<()> + 'a>),
+}
+
+impl Step<'_> {
+    fn run(&self, conn: &Connection) -> Result<()> {
+        match *self {
+            Step::Sql(sql) => conn.execute_batch(sql),
+            Step::Func(ref f) => f(conn),
+        }
+    }
+}
+
+impl fmt::Debug for Step<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Step::Sql(sql) => f.debug_tuple("Sql").field(&sql).finish(),
+            Step::Func(_) => f.write_str("Func"),
+        }
+    }
+}
+
+/// A migration step: SQL or a closure to migrate up, and optionally one to
+/// migrate back down.
+#[derive(Debug)]
+pub struct M<'a> {
+    up: Step<'a>,
+    down: Option<Step<'a>>,
+}
+
+impl<'a> M<'a> {
+    /// A step that runs `sql` with `execute_batch`.
+    pub fn up(sql: &'a str) -> M<'a> {
+        M {
+            up: Step::Sql(sql),
+            down: None,
+        }
+    }
+
+    /// A step that calls `f` with the connection, inside the step's
+    /// transaction.
+    pub fn up_with<F>(f: F) -> M<'a>
+    where
+        F: Fn(&Connection) -> Result<()> + 'a,
+    {
+        M {
+            up: Step::Func(Box::new(f)),
+            down: None,
+        }
+    }
+
+    /// Undo the step by running `sql`.
+    pub fn down(mut self, sql: &'a str) -> M<'a> {
+        self.down = Some(Step::Sql(sql));
+        self
+    }
+
+    /// Undo the step by calling `f`.
+    pub fn down_with<F>(mut self, f: F) -> M<'a>
+    where
+        F: Fn(&Connection) -> Result<()> + 'a,
+    {
+        self.down = Some(Step::Func(Box::new(f)));
+        self
+    }
+}
+
+/// An ordered list of migration steps.
+#[derive(Debug)]
+pub struct Migrations<'a> {
+    steps: Vec<M<'a>>,
+}
+
+fn failure(code: c_int, msg: String) -> Error {
+    Error::SqliteFailure(ffi::Error::new(code), Some(msg))
+}
+
+impl<'a> Migrations<'a> {
+    /// Create the migrations from `steps`, oldest first.
+    pub fn new(steps: Vec<M<'a>>) -> Migrations<'a> {
+        Migrations { steps }
+    }
+
+    /// The version the last step migrates to.
+    pub fn latest_version(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// The `user_version` of the main database.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails or
+    /// `user_version` is negative, which no migration sets.
+    pub fn current_version(&self, conn: &Connection) -> Result<usize> {
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version < 0 {
+            return Err(failure(
+                ffi::SQLITE_ERROR,
+                format!("user_version {} is negative", version),
+            ));
+        }
+        Ok(version as usize)
+    }
+
+    /// Apply all the steps that have not been applied yet.
+    ///
+    /// # Failure
+    ///
+    /// See `to_version`.
+    pub fn to_latest(&self, conn: &mut Connection) -> Result<()> {
+        self.to_version(conn, self.latest_version())
+    }
+
+    /// Migrate up or down to `version`.
+    ///
+    /// Foreign key enforcement is turned off while migrating (it cannot be
+    /// changed inside a transaction) and restored afterwards; each step is
+    /// instead checked with `PRAGMA foreign_key_check` before it commits.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::MigrationFailed(n, err))` if step `n` fails,
+    /// leaves foreign key violations behind, or has no down step when
+    /// migrating down. Steps before it stay applied. Will also return `Err`
+    /// if `version` or the current version is beyond the last step.
+    pub fn to_version(&self, conn: &mut Connection, version: usize) -> Result<()> {
+        let current = self.current_version(conn)?;
+        for &v in &[current, version] {
+            if v > self.latest_version() {
+                return Err(failure(
+                    ffi::SQLITE_MISUSE,
+                    format!(
+                        "version {} is beyond the last migration ({})",
+                        v,
+                        self.latest_version()
+                    ),
+                ));
+            }
+        }
+        if current == version {
+            return Ok(());
+        }
+
+        let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |row| row.get(0))?;
+        if foreign_keys {
+            conn.pragma_update(None, "foreign_keys", &false)?;
+        }
+        let result = self.migrate(conn, current, version);
+        if foreign_keys {
+            // keep the migration error if restoring fails too
+            let restored = conn.pragma_update(None, "foreign_keys", &true);
+            return result.and(restored);
+        }
+        result
+    }
+
+    fn migrate(&self, conn: &mut Connection, current: usize, version: usize) -> Result<()> {
+        if current < version {
+            for n in current + 1..=version {
+                apply(conn, n, n, Some(&self.steps[n - 1].up))?;
+            }
+        } else {
+            for n in (version + 1..=current).rev() {
+                apply(conn, n, n - 1, self.steps[n - 1].down.as_ref())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run `step` (step number `n`) and set `user_version` to `version` in one
+/// transaction.
+fn apply(conn: &mut Connection, n: usize, version: usize, step: Option<&Step<'_>>) -> Result<()> {
+    let run = |conn: &mut Connection| -> Result<()> {
+        let step =
+            step.ok_or_else(|| failure(ffi::SQLITE_MISUSE, "no down migration".to_owned()))?;
+        let tx = conn.transaction()?;
+        step.run(&tx)?;
+        tx.pragma_update(None, "user_version", &(version as i64))?;
+        foreign_key_check(&tx)?;
+        tx.commit()
+    };
+    run(conn).map_err(|err| Error::MigrationFailed(n, Box::new(err)))
+}
+
+/// Fail with the first row reported by `PRAGMA foreign_key_check`.
+fn foreign_key_check(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let mut rows = stmt.query(NO_PARAMS)?;
+    if let Some(row) = rows.next()? {
+        let table: String = row.get(0)?;
+        let rowid: Option<i64> = row.get(1)?;
+        let parent: String = row.get(2)?;
+        let rowid = rowid.map_or_else(|| "?".to_owned(), |rowid| rowid.to_string());
+        return Err(failure(
+            ffi::SQLITE_CONSTRAINT_FOREIGNKEY,
+            format!(
+                "FOREIGN KEY constraint failed: {} row {} references a missing {} row",
+                table, rowid, parent
+            ),
+        ));
+    }
+    Ok(())
+}
@@ -58,6 +58,12 @@ impl Sql {
         }
     }
 
+    /// Push `s` in double quotes, even if it is a plain identifier, so that
+    /// keywords such as `order` can be used as names.
+    pub fn push_quoted_identifier(&mut self, s: &str) {
+        self.wrap_and_escape(s, '"');
+    }
+
     pub fn push_value(&mut self, value: &dyn ToSql) -> Result<()> {
         let value = value.to_sql()?;
         let value = match value {
@@ -77,6 +83,13 @@ impl Sql {
                     Some(format!("Unsupported value \"{:?}\"", value)),
                 ));
             }
+            #[cfg(feature = "pointer")]
+            ToSqlOutput::Pointer(_) => {
+                return Err(Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_MISUSE),
+                    Some(format!("Unsupported value \"{:?}\"", value)),
+                ));
+            }
         };
         match value {
             ValueRef::Integer(i) => {
@@ -99,16 +112,50 @@ impl Sql {
         Ok(())
     }
 
+    /// Push `value` as a literal of the same type, including `NULL` and
+    /// blobs, which `push_value` rejects.
+    pub fn push_literal(&mut self, value: ValueRef<'_>) -> Result<()> {
+        match value {
+            ValueRef::Null => self.buf.push_str("NULL"),
+            ValueRef::Integer(i) => self.push_int(i),
+            ValueRef::Real(r) => self.push_real(r),
+            ValueRef::Text(s) => self.push_string_literal(std::str::from_utf8(s)?),
+            ValueRef::Blob(b) => self.push_blob_literal(b),
+        }
+        Ok(())
+    }
+
     pub fn push_string_literal(&mut self, s: &str) {
         self.wrap_and_escape(s, '\'');
     }
 
+    pub fn push_blob_literal(&mut self, b: &[u8]) {
+        self.buf.push_str("X'");
+        for byte in b {
+            self.buf.push_str(&format!("{:02X}", byte));
+        }
+        self.buf.push('\'');
+    }
+
     pub fn push_int(&mut self, i: i64) {
         self.buf.push_str(&i.to_string());
     }
 
+    /// Push `f` so that it reads back as a real: `1.0`, not `1`. Infinities
+    /// overflow to `1e999` and NaN, which SQLite stores as `NULL`, is
+    /// `NULL`.
     pub fn push_real(&mut self, f: f64) {
-        self.buf.push_str(&f.to_string());
+        if f.is_nan() {
+            self.buf.push_str("NULL");
+        } else if f.is_infinite() {
+            self.buf.push_str(if f > 0.0 { "1e999" } else { "-1e999" });
+        } else {
+            let s = format!("{:?}", f);
+            self.buf.push_str(&s);
+            if !s.contains(|c| c == '.' || c == 'e') {
+                self.buf.push_str(".0");
+            }
+        }
     }
 
     pub fn push_space(&mut self) {
@@ -119,6 +166,10 @@ impl Sql {
         self.buf.push('.');
     }
 
+    pub fn push_comma(&mut self) {
+        self.buf.push(',');
+    }
+
     pub fn push_equal_sign(&mut self) {
         self.buf.push('=');
     }
@@ -309,4 +360,3 @@ pub fn is_identifier_continue(c: char) -> bool {
         || (c >= 'a' && c <= 'z')
         || c > '\x7F'
 }
-
@@ -0,0 +1,266 @@
+//! Inspect query plans produced by `EXPLAIN QUERY PLAN`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! fn show_plan(conn: &Connection) -> Result<()> {
+//!     let plan = conn.explain_query_plan("SELECT * FROM foo WHERE x > 1 ORDER BY y")?;
+//!     if plan.has_full_scan() {
+//!         println!("{}", plan);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use std::fmt;
+use std::str;
+
+use crate::types::Null;
+use crate::{Connection, Result, Statement, StatementStatus};
+
+/// The kind of operation a query plan step performs, derived from its detail
+/// text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlanOp {
+    /// A full scan of a table, subquery or covering index (`SCAN ...`).
+    Scan,
+    /// A lookup using an index or rowid (`SEARCH ...`).
+    Search,
+    /// A temporary b-tree built for ORDER BY, GROUP BY or DISTINCT
+    /// (`USE TEMP B-TREE ...`).
+    UseTempBTree,
+    /// Anything else (`COMPOUND QUERY`, `SCALAR SUBQUERY`, `CO-ROUTINE`, ...).
+    Other,
+}
+
+impl PlanOp {
+    fn from_detail(detail: &str) -> PlanOp {
+        if detail.starts_with("SCAN ") {
+            PlanOp::Scan
+        } else if detail.starts_with("SEARCH ") {
+            PlanOp::Search
+        } else if detail.starts_with("USE TEMP B-TREE") {
+            PlanOp::UseTempBTree
+        } else {
+            PlanOp::Other
+        }
+    }
+}
+
+/// A single step of a query plan and the steps nested under it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryPlanNode {
+    id: i32,
+    parent: i32,
+    detail: String,
+    children: Vec<QueryPlanNode>,
+}
+
+impl QueryPlanNode {
+    /// Returns the id SQLite assigned to this step.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Returns the id of the parent step, `0` for top level steps.
+    pub fn parent(&self) -> i32 {
+        self.parent
+    }
+
+    /// Returns the human readable description of this step.
+    pub fn detail(&self) -> &str {
+        &self.detail
+    }
+
+    /// Returns the steps nested under this one.
+    pub fn children(&self) -> &[QueryPlanNode] {
+        &self.children
+    }
+
+    /// Returns the kind of operation performed by this step.
+    pub fn op(&self) -> PlanOp {
+        PlanOp::from_detail(&self.detail)
+    }
+
+    /// Returns the table (or subquery) name for `SCAN` and `SEARCH` steps.
+    pub fn table(&self) -> Option<&str> {
+        let rest = match self.op() {
+            PlanOp::Scan => &self.detail["SCAN ".len()..],
+            PlanOp::Search => &self.detail["SEARCH ".len()..],
+            _ => return None,
+        };
+        // SQLite 3.36.0 dropped the "TABLE" keyword from the detail text.
+        let rest = if rest.starts_with("TABLE ") {
+            &rest["TABLE ".len()..]
+        } else {
+            rest
+        };
+        rest.split_whitespace().next()
+    }
+
+    /// Returns the name of the index used by this step, if any.
+    pub fn index(&self) -> Option<&str> {
+        let pos = self.detail.find(" INDEX ")?;
+        self.detail[pos + " INDEX ".len()..]
+            .split_whitespace()
+            .next()
+    }
+
+    fn walk<'a>(&'a self, nodes: &mut Vec<&'a QueryPlanNode>) {
+        nodes.push(self);
+        for child in &self.children {
+            child.walk(nodes);
+        }
+    }
+}
+
+/// Statement status counters captured alongside a query plan.
+///
+/// The counters reflect the executions of the statement that happened before
+/// the plan was captured, so they are all zero for a freshly prepared
+/// statement.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryPlanStatus {
+    /// Equivalent to `StatementStatus::FullscanStep`
+    pub fullscan_steps: i32,
+    /// Equivalent to `StatementStatus::Sort`
+    pub sorts: i32,
+    /// Equivalent to `StatementStatus::AutoIndex`
+    pub auto_indexes: i32,
+}
+
+impl QueryPlanStatus {
+    fn from_statement(stmt: &Statement<'_>) -> QueryPlanStatus {
+        QueryPlanStatus {
+            fullscan_steps: stmt.get_status(StatementStatus::FullscanStep),
+            sorts: stmt.get_status(StatementStatus::Sort),
+            auto_indexes: stmt.get_status(StatementStatus::AutoIndex),
+        }
+    }
+}
+
+/// The tree of steps returned by `EXPLAIN QUERY PLAN`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryPlan {
+    roots: Vec<QueryPlanNode>,
+    status: Option<QueryPlanStatus>,
+}
+
+impl QueryPlan {
+    fn from_rows(rows: Vec<(i32, i32, String)>) -> QueryPlan {
+        fn children_of(rows: &[(i32, i32, String)], parent: i32) -> Vec<QueryPlanNode> {
+            rows.iter()
+                .filter(|row| row.1 == parent && row.0 != parent)
+                .map(|row| QueryPlanNode {
+                    id: row.0,
+                    parent: row.1,
+                    detail: row.2.clone(),
+                    children: children_of(rows, row.0),
+                })
+                .collect()
+        }
+        QueryPlan {
+            roots: children_of(&rows, 0),
+            status: None,
+        }
+    }
+
+    /// Returns the top level steps of the plan.
+    pub fn roots(&self) -> &[QueryPlanNode] {
+        &self.roots
+    }
+
+    /// Returns every step of the plan in depth-first order.
+    pub fn nodes(&self) -> Vec<&QueryPlanNode> {
+        let mut nodes = Vec::new();
+        for root in &self.roots {
+            root.walk(&mut nodes);
+        }
+        nodes
+    }
+
+    /// Returns the status counters captured with the plan, if any.
+    pub fn status(&self) -> Option<QueryPlanStatus> {
+        self.status
+    }
+
+    /// Capture the `FullscanStep`, `Sort` and `AutoIndex` counters of `stmt`.
+    ///
+    /// Call this after the statement has been executed to see how the plan
+    /// behaved at run time.
+    pub fn attach_status(&mut self, stmt: &Statement<'_>) {
+        self.status = Some(QueryPlanStatus::from_statement(stmt));
+    }
+
+    /// Returns `true` if any step scans a whole table or subquery.
+    pub fn has_full_scan(&self) -> bool {
+        self.nodes().iter().any(|n| n.op() == PlanOp::Scan)
+    }
+
+    /// Returns `true` if any step builds a temporary b-tree.
+    pub fn uses_temp_b_tree(&self) -> bool {
+        self.nodes().iter().any(|n| n.op() == PlanOp::UseTempBTree)
+    }
+
+    fn fmt_nodes(f: &mut fmt::Formatter<'_>, nodes: &[QueryPlanNode], prefix: &str) -> fmt::Result {
+        for (i, node) in nodes.iter().enumerate() {
+            let last = i == nodes.len() - 1;
+            writeln!(
+                f,
+                "{}{}{}",
+                prefix,
+                if last { "`--" } else { "|--" },
+                node.detail
+            )?;
+            let prefix = format!("{}{}", prefix, if last { "   " } else { "|  " });
+            QueryPlan::fmt_nodes(f, &node.children, &prefix)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the plan the same way the `sqlite3` shell does.
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "QUERY PLAN")?;
+        QueryPlan::fmt_nodes(f, &self.roots, "")
+    }
+}
+
+impl Connection {
+    /// Run `EXPLAIN QUERY PLAN` on `sql` and return the resulting plan.
+    ///
+    /// Parameters in `sql` do not need to be bound; they are planned as
+    /// `NULL`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `sql` cannot be prepared.
+    pub fn explain_query_plan(&self, sql: &str) -> Result<QueryPlan> {
+        let mut stmt = self.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+        let params = vec![Null; stmt.stmt.bind_parameter_count()];
+        let rows = stmt
+            .query_map(&params, |row| Ok((row.get(0)?, row.get(1)?, row.get(3)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(QueryPlan::from_rows(rows))
+    }
+}
+
+impl Statement<'_> {
+    /// Return the query plan of this statement, with its current
+    /// `FullscanStep`, `Sort` and `AutoIndex` counters attached.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the `EXPLAIN QUERY PLAN` statement cannot be
+    /// prepared.
+    pub fn query_plan(&self) -> Result<QueryPlan> {
+        let sql = match self.stmt.sql() {
+            Some(sql) => str::from_utf8(sql.to_bytes())?,
+            None => "",
+        };
+        let mut plan = self.conn.explain_query_plan(sql)?;
+        plan.attach_status(self);
+        Ok(plan)
+    }
+}
@@ -0,0 +1,326 @@
+//! Inspect the tables, views, indexes, foreign keys and triggers of a
+//! database.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result};
+//! fn describe(conn: &Connection) -> Result<()> {
+//!     for table in conn.schema()?.tables {
+//!         println!("{}", table.name);
+//!         for column in &table.columns {
+//!             println!("  {} {}", column.name, column.decl_type.as_deref().unwrap_or(""));
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use crate::ffi;
+use crate::{str_to_cstring, Connection, DatabaseName, Result, NO_PARAMS};
+
+/// The schema of the main database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    /// Tables, ordered by name.
+    pub tables: Vec<TableInfo>,
+    /// Views, ordered by name. Views have no indexes or foreign keys.
+    pub views: Vec<TableInfo>,
+    /// Triggers, ordered by name.
+    pub triggers: Vec<TriggerInfo>,
+}
+
+/// A table or view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableInfo {
+    /// The table name.
+    pub name: String,
+    /// The `CREATE` statement, as stored in `sqlite_master`.
+    pub sql: Option<String>,
+    /// The columns, in declaration order.
+    pub columns: Vec<ColumnInfo>,
+    /// The indexes, including those created for `UNIQUE` and `PRIMARY KEY`
+    /// constraints.
+    pub indexes: Vec<IndexInfo>,
+    /// The foreign keys, one per constraint.
+    pub foreign_keys: Vec<ForeignKeyInfo>,
+}
+
+/// A column, from `PRAGMA table_info` and
+/// `sqlite3_table_column_metadata`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnInfo {
+    /// The column name.
+    pub name: String,
+    /// The declared type, if any.
+    pub decl_type: Option<String>,
+    /// Whether the column is `NOT NULL`.
+    pub not_null: bool,
+    /// The default value, as SQL text.
+    pub default: Option<String>,
+    /// The 1-based position of the column in the primary key, or 0.
+    pub primary_key: u32,
+    /// The collation, for table columns.
+    pub collation: Option<String>,
+    /// Whether the column is an `AUTOINCREMENT` rowid alias.
+    pub auto_increment: bool,
+}
+
+/// An index, from `PRAGMA index_list` and `PRAGMA index_info`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexInfo {
+    /// The index name.
+    pub name: String,
+    /// Whether the index is `UNIQUE`.
+    pub unique: bool,
+    /// How the index was created: `"c"` by `CREATE INDEX`, `"u"` by a
+    /// `UNIQUE` constraint or `"pk"` by a `PRIMARY KEY` constraint.
+    pub origin: String,
+    /// Whether the index has a `WHERE` clause.
+    pub partial: bool,
+    /// The indexed columns, `None` for expressions.
+    pub columns: Vec<Option<String>>,
+}
+
+/// A foreign key constraint, from `PRAGMA foreign_key_list`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForeignKeyInfo {
+    /// The referenced table.
+    pub table: String,
+    /// The referencing columns.
+    pub from: Vec<String>,
+    /// The referenced columns, `None` when the constraint refers to the
+    /// parent's primary key implicitly.
+    pub to: Vec<Option<String>>,
+    /// The `ON UPDATE` action.
+    pub on_update: String,
+    /// The `ON DELETE` action.
+    pub on_delete: String,
+}
+
+/// A trigger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriggerInfo {
+    /// The trigger name.
+    pub name: String,
+    /// The table or view the trigger fires on.
+    pub table: String,
+    /// The `CREATE TRIGGER` statement.
+    pub sql: Option<String>,
+}
+
+/// What `sqlite3_table_column_metadata` knows about a column that
+/// `PRAGMA table_info` does not.
+struct ColumnMetadata {
+    collation: Option<String>,
+    auto_increment: bool,
+}
+
+unsafe fn optional_cstr(p: *const c_char) -> Option<String> {
+    if p.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(p).to_string_lossy().into_owned())
+    }
+}
+
+impl Connection {
+    /// Returns the tables, views and triggers of the main database,
+    /// excluding SQLite's internal `sqlite_` tables.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn schema(&self) -> Result<Schema> {
+        let mut stmt = self.prepare(
+            "SELECT type, name, tbl_name, sql FROM sqlite_master \
+             WHERE type IN ('table', 'view', 'trigger') AND name NOT LIKE 'sqlite\\_%' ESCAPE '\\' \
+             ORDER BY name",
+        )?;
+        let entries = stmt
+            .query_map(NO_PARAMS, |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut schema = Schema {
+            tables: Vec::new(),
+            views: Vec::new(),
+            triggers: Vec::new(),
+        };
+        for (kind, name, table, sql) in entries {
+            match kind.as_str() {
+                "table" => schema
+                    .tables
+                    .push(self.table_info_with_sql(name, sql, true)?),
+                "view" => schema
+                    .views
+                    .push(self.table_info_with_sql(name, sql, false)?),
+                _ => schema.triggers.push(TriggerInfo { name, table, sql }),
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Returns the table or view `name` of the main database, or `None` if
+    /// there is none.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite calls fail.
+    pub fn table_info(&self, name: &str) -> Result<Option<TableInfo>> {
+        let mut stmt = self.prepare(
+            "SELECT type, name, sql FROM sqlite_master \
+             WHERE type IN ('table', 'view') AND name = ?",
+        )?;
+        let mut rows = stmt.query(&[name])?;
+        let entry = match rows.next()? {
+            Some(row) => Some((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            )),
+            None => None,
+        };
+        match entry {
+            Some((kind, name, sql)) => self
+                .table_info_with_sql(name, sql, kind == "table")
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn table_info_with_sql(
+        &self,
+        name: String,
+        sql: Option<String>,
+        is_table: bool,
+    ) -> Result<TableInfo> {
+        let mut columns = Vec::new();
+        self.pragma(None, "table_info", &name, |row| {
+            let column_name: String = row.get(1)?;
+            let decl_type: String = row.get(2)?;
+            // virtual tables and views have no metadata
+            let metadata = if is_table {
+                self.column_metadata(&name, &column_name).ok()
+            } else {
+                None
+            };
+            columns.push(ColumnInfo {
+                name: column_name,
+                decl_type: if decl_type.is_empty() {
+                    None
+                } else {
+                    Some(decl_type)
+                },
+                not_null: row.get(3)?,
+                default: row.get(4)?,
+                primary_key: row.get(5)?,
+                collation: metadata.as_ref().and_then(|m| m.collation.clone()),
+                auto_increment: metadata.map_or(false, |m| m.auto_increment),
+            });
+            Ok(())
+        })?;
+
+        let mut indexes = Vec::new();
+        let mut foreign_keys: Vec<(i64, ForeignKeyInfo)> = Vec::new();
+        if is_table {
+            let mut list = Vec::new();
+            self.pragma(None, "index_list", &name, |row| {
+                list.push((
+                    row.get::<_, String>(1)?,
+                    row.get::<_, bool>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, bool>(4)?,
+                ));
+                Ok(())
+            })?;
+            for (index_name, unique, origin, partial) in list {
+                let mut index_columns = Vec::new();
+                self.pragma(None, "index_info", &index_name, |row| {
+                    index_columns.push(row.get(2)?);
+                    Ok(())
+                })?;
+                indexes.push(IndexInfo {
+                    name: index_name,
+                    unique,
+                    origin,
+                    partial,
+                    columns: index_columns,
+                });
+            }
+            indexes.sort_by(|a, b| a.name.cmp(&b.name));
+
+            // one row per column, grouped by constraint id
+            self.pragma(None, "foreign_key_list", &name, |row| {
+                let id: i64 = row.get(0)?;
+                let from: String = row.get(3)?;
+                let to: Option<String> = row.get(4)?;
+                if let Some(&mut (last, ref mut fk)) = foreign_keys.last_mut() {
+                    if last == id {
+                        fk.from.push(from);
+                        fk.to.push(to);
+                        return Ok(());
+                    }
+                }
+                foreign_keys.push((
+                    id,
+                    ForeignKeyInfo {
+                        table: row.get(2)?,
+                        from: vec![from],
+                        to: vec![to],
+                        on_update: row.get(5)?,
+                        on_delete: row.get(6)?,
+                    },
+                ));
+                Ok(())
+            })?;
+        }
+
+        Ok(TableInfo {
+            name,
+            sql,
+            columns,
+            indexes,
+            foreign_keys: foreign_keys.into_iter().map(|(_, fk)| fk).collect(),
+        })
+    }
+
+    fn column_metadata(&self, table: &str, column: &str) -> Result<ColumnMetadata> {
+        let db_name = DatabaseName::Main.to_cstring()?;
+        let table = str_to_cstring(table)?;
+        let column = str_to_cstring(column)?;
+        let mut data_type: *const c_char = ptr::null();
+        let mut collation: *const c_char = ptr::null();
+        let mut not_null: c_int = 0;
+        let mut primary_key: c_int = 0;
+        let mut auto_increment: c_int = 0;
+        let mut db = self.db.borrow_mut();
+        let r = unsafe {
+            ffi::sqlite3_table_column_metadata(
+                db.db(),
+                db_name.as_ptr(),
+                table.as_ptr(),
+                column.as_ptr(),
+                &mut data_type,
+                &mut collation,
+                &mut not_null,
+                &mut primary_key,
+                &mut auto_increment,
+            )
+        };
+        db.decode_result(r)?;
+        Ok(ColumnMetadata {
+            collation: unsafe { optional_cstr(collation) },
+            auto_increment: auto_increment != 0,
+        })
+    }
+}
@@ -0,0 +1,120 @@
+//! Run a script of several statements, keeping the result of each one.
+
+use crate::ffi;
+use crate::types::Value;
+use crate::{Connection, Error, Result};
+use std::ffi::CString;
+use std::ops::Range;
+
+/// The outcome of one statement run by `Connection::execute_script`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatementResult {
+    /// Byte range of the statement in the script, from its first
+    /// non-whitespace character through its terminating `;`, if any.
+    pub span: Range<usize>,
+    /// Names of the result columns; empty for statements that return no
+    /// rows, such as `INSERT` or `CREATE TABLE`.
+    pub columns: Vec<String>,
+    /// The rows returned by the statement.
+    pub rows: Vec<Vec<Value>>,
+    /// Number of rows inserted, updated or deleted, including by triggers;
+    /// 0 for statements of other kinds.
+    pub changes: usize,
+    /// `last_insert_rowid()` after the statement ran.
+    pub last_insert_rowid: i64,
+}
+
+/// Returns `true` if `sql` ends with a complete statement, as decided by
+/// `sqlite3_complete`: a terminating `;` outside literals, comments and
+/// trigger bodies. SQL with an embedded nul is never complete.
+pub fn is_complete(sql: &str) -> bool {
+    match CString::new(sql) {
+        Ok(sql) => unsafe { ffi::sqlite3_complete(sql.as_ptr()) != 0 },
+        Err(_) => false,
+    }
+}
+
+/// Make the offset of a syntax error relative to the whole script.
+fn in_script(err: Error, script: &str, start: usize) -> Error {
+    match err {
+        Error::SqlInputError {
+            error, msg, offset, ..
+        } => Error::SqlInputError {
+            error,
+            msg,
+            sql: script.to_owned(),
+            offset: start + offset,
+        },
+        err => err,
+    }
+}
+
+impl Connection {
+    /// Run each statement of `sql` in turn and return their results, the way
+    /// a SQL console shows them. Whitespace and comments between statements
+    /// are skipped.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result};
+    /// fn show(conn: &Connection) -> Result<()> {
+    ///     let script = "INSERT INTO t VALUES (1); SELECT * FROM t;";
+    ///     for result in conn.execute_script(script)? {
+    ///         println!("{}: {:?}", &script[result.span], result.rows);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Stops at the first statement that fails and returns
+    /// `Error::ScriptFailed` with its index. Statements before it stay
+    /// applied unless the script opened a transaction. Offsets of syntax
+    /// errors are relative to `sql`.
+    pub fn execute_script(&self, sql: &str) -> Result<Vec<StatementResult>> {
+        let mut results = Vec::new();
+        let mut start = 0;
+        while start < sql.len() {
+            let index = results.len();
+            let fail = |err| Error::ScriptFailed(index, Box::new(in_script(err, sql, start)));
+            let (stmt, len) = self
+                .db
+                .borrow_mut()
+                .prepare_with_tail(self, &sql[start..])
+                .map_err(fail)?;
+            let end = start + len;
+            if stmt.is_empty() {
+                start = end;
+                continue;
+            }
+
+            let columns: Vec<String> = stmt.column_names().into_iter().map(str::to_owned).collect();
+            let mut rows = Vec::new();
+            // sqlite3_changes() keeps the count of the last INSERT, UPDATE
+            // or DELETE, even after statements of other kinds
+            let total_changes = self.total_changes();
+            while stmt.step().map_err(fail)? {
+                rows.push(
+                    (0..columns.len())
+                        .map(|i| Value::from(stmt.value_ref(i)))
+                        .collect(),
+                );
+            }
+            let changes = self.total_changes() - total_changes;
+            stmt.finalize().map_err(fail)?;
+
+            let skipped = sql[start..end].len() - sql[start..end].trim_start().len();
+            results.push(StatementResult {
+                span: start + skipped..end,
+                columns,
+                rows,
+                changes,
+                last_insert_rowid: self.last_insert_rowid(),
+            });
+            start = end;
+        }
+        Ok(results)
+    }
+}
@@ -0,0 +1,841 @@
+//! Serde support: deserialize rows with `Row::deserialize` and
+//! `Statement::query_as`, and turn `Serialize` values into parameters with
+//! `to_params` and `to_named_params`.
+//!
+//! Columns are matched to struct fields by name. Values are converted the
+//! same way `FromSql` converts them: integers can be read as `bool` or
+//! floats, and blobs as `Vec<u8>`.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{params, to_named_params, Connection, Result};
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Serialize, Deserialize)]
+//! struct Person {
+//!     id: i64,
+//!     name: String,
+//! }
+//!
+//! fn roundtrip(conn: &Connection, person: &Person) -> Result<Vec<Person>> {
+//!     let mut insert = conn.prepare("INSERT INTO person (id, name) VALUES (:id, :name)")?;
+//!     let params = to_named_params(person)?;
+//!     insert.execute_named(&params.for_statement(&insert)?)?;
+//!
+//!     let mut stmt = conn.prepare("SELECT id, name FROM person")?;
+//!     let rows = stmt.query_as::<Person, _>(params![])?;
+//!     rows.collect()
+//! }
+//! ```
+use std::fmt;
+
+use serde::de::value::{SeqDeserializer, StrDeserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Impossible, Serialize};
+
+use crate::error::UNKNOWN_COLUMN;
+use crate::types::{ToSql, Type, Value, ValueRef};
+use crate::{Error, MappedRows, Result, Row, Statement};
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::FromSqlConversionFailure(UNKNOWN_COLUMN, Type::Null, msg.to_string().into())
+    }
+
+    fn missing_field(field: &'static str) -> Error {
+        Error::InvalidColumnName(field.to_owned())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::ToSqlConversionFailure(msg.to_string().into())
+    }
+}
+
+/// Attach the column to an error raised while deserializing its value.
+fn at_column(err: Error, idx: usize, value: ValueRef<'_>) -> Error {
+    match err {
+        Error::FromSqlConversionFailure(UNKNOWN_COLUMN, _, err) => {
+            Error::FromSqlConversionFailure(idx, value.data_type(), err)
+        }
+        Error::IntegralValueOutOfRange(UNKNOWN_COLUMN, i) => Error::IntegralValueOutOfRange(idx, i),
+        err => err,
+    }
+}
+
+impl Row<'_> {
+    /// Deserialize the row into `T`.
+    ///
+    /// Structs and maps are filled from the columns by name, tuples and
+    /// sequences by position, and other types from the only column.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err::InvalidColumnName` if a struct field has no
+    /// matching column, or a conversion error if a value cannot be
+    /// deserialized into its field. As usual with serde, `Option` fields
+    /// without a column are `None` and `#[serde(default)]` fields get their
+    /// default.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        T::deserialize(RowDeserializer { row: self })
+    }
+}
+
+fn deserialize_row<T: DeserializeOwned>(row: &Row<'_>) -> Result<T> {
+    row.deserialize()
+}
+
+impl Statement<'_> {
+    /// Execute the prepared statement, returning an iterator over its rows
+    /// deserialized into `T` with `Row::deserialize`.
+    ///
+    /// ## Failure
+    ///
+    /// Will return `Err` if binding parameters fails.
+    #[allow(clippy::type_complexity)]
+    pub fn query_as<T, P>(&mut self, params: P) -> Result<MappedRows<'_, fn(&Row<'_>) -> Result<T>>>
+    where
+        T: DeserializeOwned,
+        P: IntoIterator,
+        P::Item: ToSql,
+    {
+        self.query_map(params, deserialize_row::<T> as fn(&Row<'_>) -> Result<T>)
+    }
+}
+
+struct RowDeserializer<'a, 'stmt> {
+    row: &'a Row<'stmt>,
+}
+
+impl RowDeserializer<'_, '_> {
+    fn single_column(&self) -> Result<ValueRef<'_>> {
+        match self.row.column_count() {
+            1 => Ok(self.row.get_raw(0)),
+            n => Err(de::Error::custom(format_args!(
+                "expected a single column, got {}",
+                n
+            ))),
+        }
+    }
+}
+
+macro_rules! forward_to_column {
+    ($($method:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            let value = self.single_column()?;
+            ValueDeserializer { value }
+                .$method(visitor)
+                .map_err(|err| at_column(err, 0, value))
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'_, '_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(ColumnAccess {
+            row: self.row,
+            idx: 0,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(ColumnAccess {
+            row: self.row,
+            idx: 0,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let value = self.single_column()?;
+        ValueDeserializer { value }
+            .deserialize_enum(name, variants, visitor)
+            .map_err(|err| at_column(err, 0, value))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_column! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_option deserialize_unit
+        deserialize_identifier
+    }
+}
+
+/// Visits the columns of a row, as a map keyed by column name or as a
+/// sequence.
+struct ColumnAccess<'a, 'stmt> {
+    row: &'a Row<'stmt>,
+    idx: usize,
+}
+
+impl ColumnAccess<'_, '_> {
+    fn next_value<'de, T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value> {
+        let idx = self.idx;
+        self.idx += 1;
+        let value = self.row.get_raw(idx);
+        seed.deserialize(ValueDeserializer { value })
+            .map_err(|err| at_column(err, idx, value))
+    }
+}
+
+impl<'de> de::MapAccess<'de> for ColumnAccess<'_, '_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.idx >= self.row.column_count() {
+            return Ok(None);
+        }
+        let name: StrDeserializer<'_, Error> = self.row.column_name(self.idx)?.into_deserializer();
+        seed.deserialize(name).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        self.next_value(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.row.column_count() - self.idx)
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for ColumnAccess<'_, '_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.idx >= self.row.column_count() {
+            return Ok(None);
+        }
+        self.next_value(seed).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.row.column_count() - self.idx)
+    }
+}
+
+/// Deserializes a single SQLite value.
+struct ValueDeserializer<'a> {
+    value: ValueRef<'a>,
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            ValueRef::Null => visitor.visit_unit(),
+            ValueRef::Integer(i) => visitor.visit_i64(i),
+            ValueRef::Real(f) => visitor.visit_f64(f),
+            ValueRef::Text(_) => visitor.visit_str(self.value.as_str()?),
+            ValueRef::Blob(b) => visitor.visit_bytes(b),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            ValueRef::Integer(i) => visitor.visit_bool(i != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            ValueRef::Integer(i) => visitor.visit_f64(i as f64),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            ValueRef::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.value {
+            // `Vec<u8>` deserializes from a sequence
+            ValueRef::Blob(b) => {
+                visitor.visit_seq(SeqDeserializer::<_, Error>::new(b.iter().cloned()))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.value {
+            ValueRef::Text(_) => {
+                let variant: StrDeserializer<'_, Error> = self.value.as_str()?.into_deserializer();
+                visitor.visit_enum(variant)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 u8 u16 u32 u64 char str string bytes byte_buf unit unit_struct
+        tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Serialize `value` into positional parameters: the fields of a struct in
+/// declaration order, the elements of a tuple or sequence, or a single
+/// value.
+///
+/// ```rust,no_run
+/// # use rusqlite::{to_params, Connection, Result};
+/// fn insert(conn: &Connection) -> Result<usize> {
+///     let params = to_params(&(1, "one"))?;
+///     conn.execute("INSERT INTO foo (id, name) VALUES (?, ?)", &params)
+/// }
+/// ```
+///
+/// # Failure
+///
+/// Will return `Err` if a field is not a scalar, `Option`, unit enum
+/// variant or byte sequence.
+pub fn to_params<T: Serialize + ?Sized>(value: &T) -> Result<Vec<Value>> {
+    Ok(value
+        .serialize(ParamsSerializer)?
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect())
+}
+
+/// Serialize the fields of a struct or the entries of a map into named
+/// parameters. Names are prefixed with `:` unless they already start with
+/// `:`, `@` or `$`.
+///
+/// # Failure
+///
+/// Will return `Err` if `value` is not a struct or a map, or under the same
+/// conditions as `to_params`.
+pub fn to_named_params<T: Serialize + ?Sized>(value: &T) -> Result<NamedParams> {
+    let params = value
+        .serialize(ParamsSerializer)?
+        .into_iter()
+        .map(|(name, value)| match name {
+            Some(name) => Ok((parameter_name(name), value)),
+            None => Err(ser::Error::custom(
+                "named parameters need a struct or a map",
+            )),
+        })
+        .collect::<Result<_>>()?;
+    Ok(NamedParams(params))
+}
+
+fn parameter_name(name: String) -> String {
+    if name.starts_with(|c: char| c == ':' || c == '@' || c == '$') {
+        name
+    } else {
+        format!(":{}", name)
+    }
+}
+
+/// Named parameters built by `to_named_params`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedParams(Vec<(String, Value)>);
+
+impl NamedParams {
+    /// Returns the parameters as `(name, value)` pairs.
+    pub fn as_slice(&self) -> &[(String, Value)] {
+        &self.0
+    }
+
+    /// Returns the parameters that `stmt` binds, according to
+    /// `Statement::parameter_index`, in the form `execute_named` and
+    /// `query_named` take.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a name is not a valid C string.
+    pub fn for_statement(&self, stmt: &Statement<'_>) -> Result<Vec<(&str, &dyn ToSql)>> {
+        let mut params = Vec::with_capacity(self.0.len());
+        for (name, value) in &self.0 {
+            if stmt.parameter_index(name)?.is_some() {
+                params.push((name.as_str(), value as &dyn ToSql));
+            }
+        }
+        Ok(params)
+    }
+}
+
+fn unsupported(what: &str) -> Error {
+    ser::Error::custom(format_args!("cannot bind {} as a parameter", what))
+}
+
+/// Serializes a single parameter value.
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = BlobSerializer;
+    type SerializeTuple = Impossible<Value, Error>;
+    type SerializeTupleStruct = Impossible<Value, Error>;
+    type SerializeTupleVariant = Impossible<Value, Error>;
+    type SerializeMap = Impossible<Value, Error>;
+    type SerializeStruct = Impossible<Value, Error>;
+    type SerializeStructVariant = Impossible<Value, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        if v > i64::max_value() as u64 {
+            return Err(ser::Error::custom(format_args!(
+                "{} is out of range for a SQLite integer",
+                v
+            )));
+        }
+        Ok(Value::Integer(v as i64))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        Ok(Value::Real(f64::from(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(Value::Real(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        Ok(Value::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::Text(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(Value::Blob(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        Ok(Value::Text(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Value> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<BlobSerializer> {
+        Ok(BlobSerializer {
+            bytes: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(unsupported("a tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(unsupported("a tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(unsupported("a map"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(unsupported("a struct"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(unsupported("an enum variant with data"))
+    }
+}
+
+/// Binds a sequence of bytes, such as a `Vec<u8>`, as a blob.
+struct BlobSerializer {
+    bytes: Vec<u8>,
+}
+
+impl ser::SerializeSeq for BlobSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        match value.serialize(ValueSerializer)? {
+            Value::Integer(i) if (0..=255).contains(&i) => {
+                self.bytes.push(i as u8);
+                Ok(())
+            }
+            _ => Err(unsupported("a sequence of anything but bytes")),
+        }
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Blob(self.bytes))
+    }
+}
+
+/// A parameter and its name, if it came from a struct field or map key.
+type Param = (Option<String>, Value);
+
+/// Serializes a value into a list of parameters.
+struct ParamsSerializer;
+
+macro_rules! serialize_single {
+    ($($method:ident($ty:ty))*) => {$(
+        fn $method(self, v: $ty) -> Result<Vec<Param>> {
+            Ok(vec![(None, ValueSerializer.$method(v)?)])
+        }
+    )*};
+}
+
+impl ser::Serializer for ParamsSerializer {
+    type Ok = Vec<Param>;
+    type Error = Error;
+    type SerializeSeq = ParamsCollector;
+    type SerializeTuple = ParamsCollector;
+    type SerializeTupleStruct = ParamsCollector;
+    type SerializeTupleVariant = Impossible<Vec<Param>, Error>;
+    type SerializeMap = ParamsCollector;
+    type SerializeStruct = ParamsCollector;
+    type SerializeStructVariant = Impossible<Vec<Param>, Error>;
+
+    serialize_single! {
+        serialize_bool(bool) serialize_i8(i8) serialize_i16(i16) serialize_i32(i32)
+        serialize_i64(i64) serialize_u8(u8) serialize_u16(u16) serialize_u32(u32)
+        serialize_u64(u64) serialize_f32(f32) serialize_f64(f64) serialize_char(char)
+        serialize_str(&str) serialize_bytes(&[u8])
+    }
+
+    fn serialize_none(self) -> Result<Vec<Param>> {
+        Ok(vec![(None, Value::Null)])
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<Param>> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Vec<Param>> {
+        Ok(Vec::new())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Vec<Param>> {
+        Ok(Vec::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Vec<Param>> {
+        let value = ValueSerializer.serialize_unit_variant(name, variant_index, variant)?;
+        Ok(vec![(None, value)])
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Vec<Param>> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Vec<Param>> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ParamsCollector> {
+        Ok(ParamsCollector::with_capacity(len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ParamsCollector> {
+        Ok(ParamsCollector::with_capacity(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ParamsCollector> {
+        Ok(ParamsCollector::with_capacity(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<ParamsCollector> {
+        Ok(ParamsCollector::with_capacity(len.unwrap_or(0)))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<ParamsCollector> {
+        Ok(ParamsCollector::with_capacity(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(unsupported("an enum variant with data"))
+    }
+}
+
+struct ParamsCollector {
+    params: Vec<Param>,
+    key: Option<String>,
+}
+
+impl ParamsCollector {
+    fn with_capacity(len: usize) -> ParamsCollector {
+        ParamsCollector {
+            params: Vec::with_capacity(len),
+            key: None,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, name: Option<String>, value: &T) -> Result<()> {
+        let value = value.serialize(ValueSerializer)?;
+        self.params.push((name, value));
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for ParamsCollector {
+    type Ok = Vec<Param>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(None, value)
+    }
+
+    fn end(self) -> Result<Vec<Param>> {
+        Ok(self.params)
+    }
+}
+
+impl ser::SerializeTuple for ParamsCollector {
+    type Ok = Vec<Param>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(None, value)
+    }
+
+    fn end(self) -> Result<Vec<Param>> {
+        Ok(self.params)
+    }
+}
+
+impl ser::SerializeTupleStruct for ParamsCollector {
+    type Ok = Vec<Param>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(None, value)
+    }
+
+    fn end(self) -> Result<Vec<Param>> {
+        Ok(self.params)
+    }
+}
+
+impl ser::SerializeMap for ParamsCollector {
+    type Ok = Vec<Param>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        match key.serialize(ValueSerializer)? {
+            Value::Text(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(unsupported("a map with non-string keys")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take();
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Vec<Param>> {
+        Ok(self.params)
+    }
+}
+
+impl ser::SerializeStruct for ParamsCollector {
+    type Ok = Vec<Param>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.push(Some(key.to_owned()), value)
+    }
+
+    fn end(self) -> Result<Vec<Param>> {
+        Ok(self.params)
+    }
+}
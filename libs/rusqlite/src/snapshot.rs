@@ -0,0 +1,135 @@
+//! Consistent reads of a WAL database at a recorded point in time.
+//!
+//! A `Snapshot` records the state of a database as seen by a read
+//! transaction. Another connection to the same database can later start a
+//! read transaction on that state, while writers keep committing, as long
+//! as the WAL has not been checkpointed past it.
+//!
+//! Snapshots need the database in WAL mode, which needs a VFS that can
+//! share the WAL index between connections. The wasm build has no file
+//! VFS, so there databases must be opened through `memvfs`; in-memory
+//! databases never support snapshots.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, Result, NO_PARAMS};
+//! fn count_at_start(writer: &Connection, reader: &mut Connection) -> Result<i64> {
+//!     let start = reader.current_snapshot()?;
+//!     writer.execute("INSERT INTO log VALUES ('written later')", NO_PARAMS)?;
+//!     let tx = reader.read_snapshot(&start)?;
+//!     tx.query_row("SELECT count(*) FROM log", NO_PARAMS, |row| row.get(0))
+//! }
+//! ```
+use std::cmp::Ordering;
+use std::ptr;
+
+use crate::ffi;
+use crate::{Connection, DatabaseName, Result, Transaction, TransactionBehavior, NO_PARAMS};
+
+/// A recorded state of a WAL database, from `Connection::snapshot`.
+///
+/// Snapshots of the same database file are ordered by age; comparing
+/// snapshots of different databases gives a meaningless result.
+#[derive(Debug)]
+pub struct Snapshot {
+    snapshot: *mut ffi::sqlite3_snapshot,
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_snapshot_free(self.snapshot) };
+    }
+}
+
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Snapshot) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Snapshot {
+    /// Older snapshots are less than newer ones.
+    fn partial_cmp(&self, other: &Snapshot) -> Option<Ordering> {
+        let cmp = unsafe { ffi::sqlite3_snapshot_cmp(self.snapshot, other.snapshot) };
+        Some(cmp.cmp(&0))
+    }
+}
+
+impl Connection {
+    /// Record the state seen by the read transaction open on `schema`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not in WAL mode, if no read
+    /// transaction is open on it or if the transaction has written to it.
+    pub fn snapshot(&self, schema: DatabaseName<'_>) -> Result<Snapshot> {
+        let c_schema = schema.to_cstring()?;
+        let mut db = self.db.borrow_mut();
+        let mut snapshot = ptr::null_mut();
+        let r = unsafe { ffi::sqlite3_snapshot_get(db.db(), c_schema.as_ptr(), &mut snapshot) };
+        db.decode_result(r)?;
+        Ok(Snapshot { snapshot })
+    }
+
+    /// Record the current state of the main database, in a read
+    /// transaction of its own.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a transaction is already open or the database
+    /// is not in WAL mode.
+    pub fn current_snapshot(&mut self) -> Result<Snapshot> {
+        let tx = self.transaction_with_behavior(TransactionBehavior::Deferred)?;
+        // start the read transaction
+        tx.query_row("PRAGMA schema_version", NO_PARAMS, |_| Ok(()))?;
+        let snapshot = tx.snapshot(DatabaseName::Main)?;
+        tx.commit()?;
+        Ok(snapshot)
+    }
+
+    /// Make the next read transaction on `schema` see `snapshot`, which
+    /// must be of the same database. A transaction must have been begun
+    /// with `BEGIN` but must not have read `schema` yet.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` with `SQLITE_ERROR_SNAPSHOT` if the WAL has been
+    /// checkpointed past `snapshot`, or `Err` if there is no suitable open
+    /// transaction.
+    pub fn open_snapshot(&self, schema: DatabaseName<'_>, snapshot: &Snapshot) -> Result<()> {
+        let c_schema = schema.to_cstring()?;
+        let mut db = self.db.borrow_mut();
+        let r =
+            unsafe { ffi::sqlite3_snapshot_open(db.db(), c_schema.as_ptr(), snapshot.snapshot) };
+        db.decode_result(r)
+    }
+
+    /// Begin a read transaction that sees the main database as it was when
+    /// `snapshot` was taken. The transaction rolls back when dropped.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if a transaction is already open or the snapshot
+    /// can no longer be opened.
+    pub fn read_snapshot(&mut self, snapshot: &Snapshot) -> Result<Transaction<'_>> {
+        let tx = self.transaction_with_behavior(TransactionBehavior::Deferred)?;
+        tx.open_snapshot(DatabaseName::Main, snapshot)?;
+        Ok(tx)
+    }
+
+    /// Rebuild the list of snapshots that can be opened on `schema` after
+    /// the database was reopened, so that snapshots taken by earlier
+    /// connections become usable again.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `schema` is not in WAL mode or a read
+    /// transaction is open on it.
+    pub fn recover_snapshots(&self, schema: DatabaseName<'_>) -> Result<()> {
+        let c_schema = schema.to_cstring()?;
+        let mut db = self.db.borrow_mut();
+        let r = unsafe { ffi::sqlite3_snapshot_recover(db.db(), c_schema.as_ptr()) };
+        db.decode_result(r)
+    }
+}
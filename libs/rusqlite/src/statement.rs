@@ -11,13 +11,15 @@ use super::{len_as_c_int, str_for_sqlite, str_to_cstring};
 use super::{
     AndThenRows, Connection, Error, MappedRows, RawStatement, Result, Row, Rows, ValueRef,
 };
+#[cfg(feature = "pointer")]
+use crate::types::pointer::free_pointer;
 use crate::types::{ToSql, ToSqlOutput};
 #[cfg(feature = "array")]
 use crate::vtab::array::{free_array, ARRAY_TYPE};
 
 /// A prepared statement.
 pub struct Statement<'conn> {
-    conn: &'conn Connection,
+    pub(crate) conn: &'conn Connection,
     pub(crate) stmt: RawStatement,
 }
 
@@ -424,7 +426,14 @@ impl Statement<'_> {
         Ok(self.stmt.bind_parameter_index(&c_name))
     }
 
-    fn bind_parameters<P>(&mut self, params: P) -> Result<()>
+    /// Bind `params` to the parameters of the statement, in order.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err(Error::InvalidParameterCount(given, expected))` if
+    /// `params` does not have one value per parameter, or `Err` if a value
+    /// cannot be converted or bound.
+    pub fn bind_parameters<P>(&mut self, params: P) -> Result<()>
     where
         P: IntoIterator,
         P::Item: ToSql,
@@ -433,16 +442,13 @@ impl Statement<'_> {
         let mut index = 0;
         for p in params.into_iter() {
             index += 1; // The leftmost SQL parameter has an index of 1.
-            if index > expected {
-                break;
+            if index <= expected {
+                self.bind_parameter(&p, index)?;
             }
-            self.bind_parameter(&p, index)?;
         }
-        assert_eq!(
-            index, expected,
-            "incorrect number of parameters: expected {}, got {}",
-            expected, index
-        );
+        if index != expected {
+            return Err(Error::InvalidParameterCount(index, expected));
+        }
 
         Ok(())
     }
@@ -484,6 +490,19 @@ impl Statement<'_> {
                     )
                 });
             }
+            #[cfg(feature = "pointer")]
+            ToSqlOutput::Pointer(p) => {
+                let type_name = p.type_name_ptr();
+                return self.conn.decode_result(unsafe {
+                    ffi::sqlite3_bind_pointer(
+                        ptr,
+                        col as c_int,
+                        p.into_raw(),
+                        type_name,
+                        Some(free_pointer),
+                    )
+                });
+            }
         };
         self.conn.decode_result(match value {
             ValueRef::Null => unsafe { ffi::sqlite3_bind_null(ptr, col as c_int) },
@@ -595,6 +614,12 @@ impl Statement<'_> {
     pub(crate) fn check_no_tail(&self) -> Result<()> {
         Ok(())
     }
+
+    /// Returns `true` if the SQL held no statement, only whitespace or
+    /// comments.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.stmt.is_null()
+    }
 }
 
 impl Into<RawStatement> for Statement<'_> {
@@ -632,7 +657,9 @@ impl Statement<'_> {
         Statement { conn, stmt }
     }
 
-    pub(crate) fn value_ref(&self, col: usize) -> ValueRef<'_> {
+    /// Returns the value of column `col` of the current row. Only valid after
+    /// `step` has returned `Ok(true)`.
+    pub fn value_ref(&self, col: usize) -> ValueRef<'_> {
         let raw = unsafe { self.stmt.ptr() };
 
         match self.stmt.column_type(col) {
@@ -0,0 +1,53 @@
+//! Traits implemented by `#[derive(ToParams)]` and `#[derive(Table)]`.
+//!
+//! ```rust,no_run
+//! # use rusqlite::{Connection, FromRow, Result, Table, ToParams, NO_PARAMS};
+//! # use std::convert::TryFrom;
+//! #[derive(FromRow, ToParams, Table)]
+//! struct Person {
+//!     #[rusqlite(primary_key)]
+//!     id: i64,
+//!     name: String,
+//!     data: Option<Vec<u8>>,
+//! }
+//!
+//! fn people(conn: &Connection, me: &Person) -> Result<Vec<Person>> {
+//!     // CREATE TABLE "person" ("id" INTEGER PRIMARY KEY NOT NULL, "name" TEXT NOT NULL,
+//!     //     "data" BLOB)
+//!     conn.execute_batch(&Person::create_table_sql())?;
+//!     conn.execute_named(
+//!         "INSERT INTO person VALUES (:id, :name, :data)",
+//!         &me.to_named_params(),
+//!     )?;
+//!     let mut stmt = conn.prepare("SELECT * FROM person")?;
+//!     let rows = stmt.query_map(NO_PARAMS, |row| Person::try_from(row))?;
+//!     rows.collect()
+//! }
+//! ```
+use crate::types::ToSql;
+
+/// A struct whose fields can be bound as parameters.
+pub trait ToParams {
+    /// The fields, in declaration order.
+    fn to_params(&self) -> Vec<&dyn ToSql>;
+
+    /// The fields, named `:field` (or `:name` with
+    /// `#[rusqlite(rename = "name")]`).
+    fn to_named_params(&self) -> Vec<(&'static str, &dyn ToSql)>;
+}
+
+/// A struct that maps to a table.
+pub trait Table {
+    /// The table name: the struct name in snake case, or the name given with
+    /// `#[rusqlite(table = "name")]`.
+    const NAME: &'static str;
+
+    /// The column names, in declaration order.
+    const COLUMNS: &'static [&'static str];
+
+    /// The `CREATE TABLE` statement for the table, with the table and
+    /// column names quoted. Each column is declared with its field's
+    /// `types::ColumnType`, and is `NOT NULL` unless the field is an
+    /// `Option`.
+    fn create_table_sql() -> String;
+}
@@ -1,9 +1,12 @@
-use crate::{Connection, Result};
+use crate::clock;
+use crate::{Connection, Error, ErrorCode, Result};
+use std::fmt;
 use std::ops::Deref;
+use std::time::Duration;
 
 /// Options for transaction behavior. See [BEGIN
 /// TRANSACTION](http://www.sqlite.org/lang_transaction.html) for details.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum TransactionBehavior {
     Deferred,
     Immediate,
@@ -27,6 +30,111 @@ pub enum DropBehavior {
     Panic,
 }
 
+/// How `Connection::transaction_with_retry` retries a transaction that
+/// fails with `SQLITE_BUSY` or `SQLITE_LOCKED`.
+///
+/// The wait before retry `n` (counting from 0) is `initial_backoff * 2^n`,
+/// capped at `max_backoff`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of times the transaction is re-run after the first attempt.
+    pub max_retries: u32,
+    /// Wait before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the wait between two attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// 5 retries, waiting from 10 ms up to 1 s.
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn never() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// The wait before retry number `retry`, counting from 0.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32.checked_shl(retry).unwrap_or(u32::max_value());
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+/// Returns `true` if `err` is worth retrying: another connection holds a
+/// conflicting lock.
+fn is_busy(err: &Error) -> bool {
+    match *err {
+        Error::SqliteFailure(ref e, _) => {
+            e.code == ErrorCode::DatabaseBusy || e.code == ErrorCode::DatabaseLocked
+        }
+        _ => false,
+    }
+}
+
+/// The name of a savepoint, checked to be a plain SQL identifier (ASCII
+/// letters, digits and `_`, not starting with a digit) so it can be
+/// interpolated into `SAVEPOINT`, `RELEASE` and `ROLLBACK TO` safely.
+///
+/// ```rust,no_run
+/// # use rusqlite::{Connection, Result, SavepointName};
+/// fn import(conn: &mut Connection) -> Result<()> {
+///     let name = SavepointName::new("import")?;
+///     let sp = conn.savepoint_named(&name)?;
+///     sp.commit()
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SavepointName(String);
+
+impl SavepointName {
+    /// Check `name`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if `name` is not a plain SQL identifier.
+    pub fn new<T: Into<String>>(name: T) -> Result<SavepointName> {
+        let name = name.into();
+        let valid = match name.as_bytes().first() {
+            Some(c) if c.is_ascii_alphabetic() || *c == b'_' => {
+                name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_')
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(Error::SqliteFailure(
+                crate::ffi::Error::new(crate::ffi::SQLITE_MISUSE),
+                Some(format!("invalid savepoint name: {:?}", name)),
+            ));
+        }
+        Ok(SavepointName(name))
+    }
+
+    /// The name as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SavepointName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 /// Represents a transaction on a database connection.
 ///
 /// ## Note
@@ -141,6 +249,11 @@ impl Transaction<'_> {
         Savepoint::with_depth_and_name(self.conn, 1, name)
     }
 
+    /// Create a new savepoint with a checked name. See `savepoint()`.
+    pub fn savepoint_named(&mut self, name: &SavepointName) -> Result<Savepoint<'_>> {
+        Savepoint::with_depth_and_name(self.conn, 1, name.as_str())
+    }
+
     /// Get the current setting for what happens to the transaction when it is
     /// dropped.
     pub fn drop_behavior(&self) -> DropBehavior {
@@ -252,6 +365,16 @@ impl Savepoint<'_> {
         Savepoint::with_depth_and_name(self.conn, self.depth + 1, name)
     }
 
+    /// Begin a nested savepoint with a checked name.
+    pub fn savepoint_named(&mut self, name: &SavepointName) -> Result<Savepoint<'_>> {
+        Savepoint::with_depth_and_name(self.conn, self.depth + 1, name.as_str())
+    }
+
+    /// The name of the savepoint.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Get the current setting for what happens to the savepoint when it is
     /// dropped.
     pub fn drop_behavior(&self) -> DropBehavior {
@@ -406,4 +529,72 @@ impl Connection {
     pub fn savepoint_with_name<T: Into<String>>(&mut self, name: T) -> Result<Savepoint<'_>> {
         Savepoint::with_name(self, name)
     }
+
+    /// Begin a new savepoint with a checked name.
+    ///
+    /// See `savepoint`.
+    ///
+    /// # Failure
+    ///
+    /// Will return `Err` if the underlying SQLite call fails.
+    pub fn savepoint_named(&mut self, name: &SavepointName) -> Result<Savepoint<'_>> {
+        Savepoint::with_name(self, name.as_str())
+    }
+
+    /// Run `f` in a transaction with the given behavior and commit it,
+    /// re-running it from the start while beginning, running or committing
+    /// fails with `SQLITE_BUSY` or `SQLITE_LOCKED`, as allowed by `policy`.
+    ///
+    /// `f` may be called several times, so it should only act through the
+    /// transaction. It can nest savepoints with `Transaction::savepoint` or
+    /// `Transaction::savepoint_named`.
+    ///
+    /// On `wasm32` the thread cannot sleep between attempts, so a busy error
+    /// is returned at once, as with `RetryPolicy::never()`. Callers there
+    /// should retry asynchronously instead, waiting `RetryPolicy::backoff`
+    /// between attempts.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// # use rusqlite::{Connection, Result, RetryPolicy, TransactionBehavior, NO_PARAMS};
+    /// fn bump(conn: &mut Connection) -> Result<usize> {
+    ///     conn.transaction_with_retry(
+    ///         TransactionBehavior::Immediate,
+    ///         RetryPolicy::default(),
+    ///         |tx| tx.execute("UPDATE counter SET n = n + 1", NO_PARAMS),
+    ///     )
+    /// }
+    /// ```
+    ///
+    /// # Failure
+    ///
+    /// Will return the error of the last attempt if `f` or the underlying
+    /// SQLite calls fail, or the last `SQLITE_BUSY` / `SQLITE_LOCKED` once
+    /// the retries are used up. The transaction is rolled back in both cases.
+    pub fn transaction_with_retry<T, F>(
+        &mut self,
+        behavior: TransactionBehavior,
+        policy: RetryPolicy,
+        mut f: F,
+    ) -> Result<T>
+    where
+        F: FnMut(&mut Transaction<'_>) -> Result<T>,
+    {
+        let mut retry = 0;
+        loop {
+            let result = Transaction::new(self, behavior).and_then(|mut tx| {
+                let value = f(&mut tx)?;
+                tx.commit()?;
+                Ok(value)
+            });
+            match result {
+                Err(ref err) if clock::CAN_SLEEP && retry < policy.max_retries && is_busy(err) => {
+                    clock::sleep(policy.backoff(retry));
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
 }
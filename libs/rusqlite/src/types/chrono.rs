@@ -4,7 +4,9 @@ use std::borrow::Cow;
 
 use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
 
-use crate::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use crate::types::{
+    ColumnType, FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef,
+};
 use crate::Result;
 
 /// ISO 8601 calendar date without timezone => "YYYY-MM-DD"
@@ -125,3 +127,20 @@ impl FromSql for DateTime<Local> {
         Ok(utc_dt.with_timezone(&Local))
     }
 }
+
+/// Dates and times are stored as ISO 8601 text.
+impl ColumnType for NaiveDate {
+    const SQL_TYPE: &'static str = "TEXT";
+}
+
+impl ColumnType for NaiveTime {
+    const SQL_TYPE: &'static str = "TEXT";
+}
+
+impl ColumnType for NaiveDateTime {
+    const SQL_TYPE: &'static str = "TEXT";
+}
+
+impl<Tz: TimeZone> ColumnType for DateTime<Tz> {
+    const SQL_TYPE: &'static str = "TEXT";
+}
@@ -0,0 +1,59 @@
+//! Column types for `#[derive(Table)]`.
+use std::borrow::Cow;
+
+use crate::pragma::Sql;
+
+/// The declared type of a column holding values of a `ToSql`/`FromSql`
+/// type, used by `#[derive(Table)]` to write `CREATE TABLE` statements.
+pub trait ColumnType {
+    /// The type the column is declared with, which gives it an `INTEGER`,
+    /// `REAL`, `TEXT` or `BLOB` affinity.
+    const SQL_TYPE: &'static str;
+    /// Whether the column may hold `NULL`.
+    const NULLABLE: bool = false;
+}
+
+macro_rules! column_type(
+    ($sql_type:expr, $($t:ty),+) => {$(
+        impl ColumnType for $t {
+            const SQL_TYPE: &'static str = $sql_type;
+        }
+    )+}
+);
+
+column_type!("INTEGER", bool, i8, i16, i32, i64, isize, u8, u16, u32);
+column_type!("REAL", f64);
+column_type!("TEXT", String, str, Cow<'_, str>, time::Timespec);
+column_type!("BLOB", Vec<u8>, [u8]);
+
+#[cfg(feature = "i128_blob")]
+column_type!("BLOB", i128);
+
+#[cfg(feature = "uuid")]
+column_type!("BLOB", uuid::Uuid);
+
+impl<T: ColumnType> ColumnType for Option<T> {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+    const NULLABLE: bool = true;
+}
+
+impl<T: ColumnType + ?Sized> ColumnType for &'_ T {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+    const NULLABLE: bool = T::NULLABLE;
+}
+
+/// The definition of the column `name` in a `CREATE TABLE` statement, with
+/// `name` quoted.
+#[doc(hidden)]
+pub fn column_definition<T: ColumnType + ?Sized>(name: &str, primary_key: bool) -> String {
+    let mut sql = Sql::new();
+    sql.push_quoted_identifier(name);
+    let mut definition = format!("{} {}", sql.as_str(), T::SQL_TYPE);
+    if primary_key {
+        definition.push_str(" PRIMARY KEY");
+    }
+    if !T::NULLABLE {
+        definition.push_str(" NOT NULL");
+    }
+    definition
+}
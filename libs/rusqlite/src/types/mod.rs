@@ -48,16 +48,24 @@
 //! implements `ToSql` or `FromSql` for the cases where you want to know if a
 //! value was NULL (which gets translated to `None`).
 
+#[doc(hidden)]
+pub use self::column_type::column_definition;
+pub use self::column_type::ColumnType;
 pub use self::from_sql::{FromSql, FromSqlError, FromSqlResult};
 pub use self::to_sql::{ToSql, ToSqlOutput};
 pub use self::value::Value;
 pub use self::value_ref::ValueRef;
+#[cfg(feature = "pointer")]
+pub use self::pointer::Pointer;
 
 use std::fmt;
 
 #[cfg(feature = "chrono")]
 mod chrono;
+mod column_type;
 mod from_sql;
+#[cfg(feature = "pointer")]
+pub mod pointer;
 #[cfg(feature = "serde_json")]
 pub mod serde_json;
 mod time;
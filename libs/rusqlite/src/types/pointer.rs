@@ -0,0 +1,127 @@
+//! Typed pointer passing, see http://sqlite.org/bindptr.html.
+//!
+//! A `Pointer<T>` bound as a parameter (or returned from a function) is only
+//! visible to functions that ask for it with the same type name, through
+//! `functions::Context::get_pointer`. SQL sees it as `NULL`.
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::{c_char, c_void};
+use std::rc::Rc;
+
+use super::{ToSql, ToSqlOutput};
+use crate::Result;
+
+thread_local! {
+    // SQLite keeps the type name pointer around for as long as the value
+    // lives, so each distinct name is leaked once.
+    static TYPE_NAMES: RefCell<HashMap<&'static str, &'static CStr>> = RefCell::new(HashMap::new());
+}
+
+fn intern(type_name: &'static str) -> &'static CStr {
+    TYPE_NAMES.with(|names| {
+        *names.borrow_mut().entry(type_name).or_insert_with(|| {
+            let name = CString::new(type_name).expect("pointer type name contains a nul byte");
+            Box::leak(name.into_boxed_c_str())
+        })
+    })
+}
+
+/// A shared reference to a Rust value that is passed to SQLite as a pointer
+/// tagged with `type_name`.
+pub struct Pointer<T> {
+    value: Rc<T>,
+    type_name: &'static str,
+}
+
+impl<T: 'static> Pointer<T> {
+    /// Wrap `value` so it can be passed as a pointer of type `type_name`.
+    pub fn new(value: T, type_name: &'static str) -> Pointer<T> {
+        Pointer::from_rc(Rc::new(value), type_name)
+    }
+
+    /// Share an existing `Rc` as a pointer of type `type_name`.
+    pub fn from_rc(value: Rc<T>, type_name: &'static str) -> Pointer<T> {
+        Pointer { value, type_name }
+    }
+
+    /// Returns the type name the pointer is tagged with.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+impl<T> std::ops::Deref for Pointer<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> Clone for Pointer<T> {
+    fn clone(&self) -> Pointer<T> {
+        Pointer {
+            value: self.value.clone(),
+            type_name: self.type_name,
+        }
+    }
+}
+
+impl<T: 'static> ToSql for Pointer<T> {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::Pointer(RawPointer {
+            value: self.value.clone(),
+            type_name: intern(self.type_name),
+        }))
+    }
+}
+
+/// A type-erased `Pointer`, as handed to SQLite.
+#[derive(Clone)]
+pub struct RawPointer {
+    value: Rc<dyn Any>,
+    type_name: &'static CStr,
+}
+
+impl RawPointer {
+    /// Returns a pointer to a new strong reference to the value, to be
+    /// released by `free_pointer`.
+    pub(crate) fn into_raw(self) -> *mut c_void {
+        Box::into_raw(Box::new(self.value)) as *mut c_void
+    }
+
+    pub(crate) fn type_name_ptr(&self) -> *const c_char {
+        self.type_name.as_ptr()
+    }
+}
+
+impl PartialEq for RawPointer {
+    fn eq(&self, other: &RawPointer) -> bool {
+        Rc::ptr_eq(&self.value, &other.value) && self.type_name == other.type_name
+    }
+}
+
+impl fmt::Debug for RawPointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawPointer")
+            .field("type_name", &self.type_name)
+            .finish()
+    }
+}
+
+pub(crate) unsafe extern "C" fn free_pointer(p: *mut c_void) {
+    drop(Box::from_raw(p as *mut Rc<dyn Any>));
+}
+
+/// Borrow the value behind a pointer created by `RawPointer::into_raw`.
+/// Returns `None` if it holds a value of another type.
+pub(crate) unsafe fn pointer_value<'a, T: 'static>(p: *mut c_void) -> Option<&'a T> {
+    if p.is_null() {
+        None
+    } else {
+        (*(p as *const Rc<dyn Any>)).downcast_ref::<T>()
+    }
+}
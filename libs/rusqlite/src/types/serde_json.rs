@@ -2,7 +2,9 @@
 // made pub for testing
 //use serde_json::Value;
 pub use serde_json::Value;
-use crate::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use crate::types::{
+    ColumnType, FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef,
+};
 use crate::Result;
 
 /// Serialize JSON `Value` to text.
@@ -23,3 +25,8 @@ impl FromSql for Value {
         .map_err(|err| FromSqlError::Other(Box::new(err)))
     }
 }
+
+/// JSON values are stored as text.
+impl ColumnType for Value {
+    const SQL_TYPE: &'static str = "TEXT";
+}
@@ -1,4 +1,6 @@
 use super::{Null, Value, ValueRef};
+#[cfg(feature = "pointer")]
+use super::pointer::RawPointer;
 #[cfg(feature = "array")]
 use crate::vtab::array::Array;
 use crate::Result;
@@ -20,6 +22,10 @@ pub enum ToSqlOutput<'a> {
 
     #[cfg(feature = "array")]
     Array(Array),
+
+    /// A typed pointer, see `Pointer`.
+    #[cfg(feature = "pointer")]
+    Pointer(RawPointer),
 }
 
 // Generically allow any type that can be converted into a ValueRef
@@ -78,6 +84,8 @@ impl ToSql for ToSqlOutput<'_> {
             ToSqlOutput::ZeroBlob(i) => ToSqlOutput::ZeroBlob(i),
             #[cfg(feature = "array")]
             ToSqlOutput::Array(ref a) => ToSqlOutput::Array(a.clone()),
+            #[cfg(feature = "pointer")]
+            ToSqlOutput::Pointer(ref p) => ToSqlOutput::Pointer(p.clone()),
         })
     }
 }
@@ -1,5 +1,7 @@
 //! `ToSql` and `FromSql` implementation for [`url::Url`].
-use crate::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use crate::types::{
+    ColumnType, FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef,
+};
 use crate::Result;
 use url::Url;
 
@@ -22,3 +24,7 @@ impl FromSql for Url {
         }
     }
 }
+
+impl ColumnType for Url {
+    const SQL_TYPE: &'static str = "TEXT";
+}
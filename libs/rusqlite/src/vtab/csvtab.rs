@@ -1,24 +1,28 @@
 //! CSV Virtual Table.
 //!
 //! Port of [csv](http://www.sqlite.org/cgi/src/finfo?name=ext/misc/csv.c) C extension.
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
 use std::os::raw::c_int;
 use std::path::Path;
 use std::result;
 use std::str;
+use std::sync::{Arc, Mutex};
 
 use crate::ffi;
-use crate::types::Null;
+use crate::types::{Null, ToSql, ValueRef};
 use crate::vtab::{
     dequote, escape_double_quote, parse_boolean, read_only_module, Context, CreateVTab, IndexInfo,
     Module, VTab, VTabConnection, VTabCursor, Values,
 };
-use crate::{Connection, Error, Result};
+use crate::{Connection, Error, Result, Statement};
 
 /// Register the "csv" module.
 /// ```sql
 /// CREATE VIRTUAL TABLE vtab USING csv(
 ///   filename=FILENAME -- Name of file containing CSV content
+///   | buffer=NAME -- Name of a buffer registered with `register_buffer`
 ///   [, schema=SCHEMA] -- Alternative CSV schema. 'CREATE TABLE x(col1 TEXT NOT NULL, col2 INT, ...);'
 ///   [, header=YES|NO] -- First row of CSV defines the names of columns if "yes". Default "no".
 ///   [, columns=N] -- Assume the CSV file contains N columns.
@@ -33,6 +37,50 @@ pub fn load_module(conn: &Connection) -> Result<()> {
 
 lazy_static::lazy_static! {
     static ref CSV_MODULE: Module<CSVTab> = read_only_module::<CSVTab>(1);
+    static ref CSV_BUFFERS: Mutex<HashMap<String, Arc<[u8]>>> = Mutex::new(HashMap::new());
+}
+
+/// Register `data` as CSV content that `csv(buffer=NAME)` tables can read,
+/// replacing any buffer previously registered under `name`.
+///
+/// Tables take a reference to the buffer when they are created, so
+/// replacing or unregistering it does not affect existing tables.
+pub fn register_buffer<T: Into<Vec<u8>>>(name: &str, data: T) {
+    let data: Arc<[u8]> = Arc::from(data.into());
+    CSV_BUFFERS.lock().unwrap().insert(name.to_owned(), data);
+}
+
+/// Unregister the buffer named `name`. Returns `false` if there was none.
+pub fn unregister_buffer(name: &str) -> bool {
+    CSV_BUFFERS.lock().unwrap().remove(name).is_some()
+}
+
+fn registered_buffer(name: &str) -> Option<Arc<[u8]>> {
+    CSV_BUFFERS.lock().unwrap().get(name).cloned()
+}
+
+/// The content read by a CSV virtual table.
+enum Source {
+    File(File),
+    Buffer(Cursor<Arc<[u8]>>),
+}
+
+impl Read for Source {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Source::File(ref mut f) => f.read(buf),
+            Source::Buffer(ref mut c) => c.read(buf),
+        }
+    }
+}
+
+impl Seek for Source {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match *self {
+            Source::File(ref mut f) => f.seek(pos),
+            Source::Buffer(ref mut c) => c.seek(pos),
+        }
+    }
 }
 
 /// An instance of the CSV virtual table
@@ -42,6 +90,8 @@ struct CSVTab {
     base: ffi::sqlite3_vtab,
     /// Name of the CSV file
     filename: String,
+    /// In-memory CSV content, used instead of `filename`
+    buffer: Option<Arc<[u8]>>,
     has_headers: bool,
     delimiter: u8,
     quote: u8,
@@ -50,12 +100,16 @@ struct CSVTab {
 }
 
 impl CSVTab {
-    fn reader(&self) -> result::Result<csv::Reader<File>, csv::Error> {
-        csv::ReaderBuilder::new()
+    fn reader(&self) -> result::Result<csv::Reader<Source>, csv::Error> {
+        let source = match self.buffer {
+            Some(ref buffer) => Source::Buffer(Cursor::new(buffer.clone())),
+            None => Source::File(File::open(&self.filename)?),
+        };
+        Ok(csv::ReaderBuilder::new()
             .has_headers(self.has_headers)
             .delimiter(self.delimiter)
             .quote(self.quote)
-            .from_path(&self.filename)
+            .from_reader(source))
     }
 
     fn parameter(c_slice: &[u8]) -> Result<(&str, &str)> {
@@ -96,6 +150,7 @@ impl VTab for CSVTab {
         let mut vtab = CSVTab {
             base: ffi::sqlite3_vtab::default(),
             filename: "".to_owned(),
+            buffer: None,
             has_headers: false,
             delimiter: b',',
             quote: b'"',
@@ -117,6 +172,16 @@ impl VTab for CSVTab {
                     }
                     vtab.filename = value.to_owned();
                 }
+                "buffer" => {
+                    if let Some(buffer) = registered_buffer(value) {
+                        vtab.buffer = Some(buffer);
+                    } else {
+                        return Err(Error::ModuleError(format!(
+                            "buffer '{}' is not registered",
+                            value
+                        )));
+                    }
+                }
                 "schema" => {
                     schema = Some(value.to_owned());
                 }
@@ -182,7 +247,7 @@ impl VTab for CSVTab {
             }
         }
 
-        if vtab.filename.is_empty() {
+        if vtab.filename.is_empty() && vtab.buffer.is_none() {
             return Err(Error::ModuleError("no CSV file specified".to_owned()));
         }
 
@@ -256,7 +321,7 @@ struct CSVTabCursor {
     /// Base class. Must be first
     base: ffi::sqlite3_vtab_cursor,
     /// The CSV reader object
-    reader: csv::Reader<File>,
+    reader: csv::Reader<Source>,
     /// Current cursor position used as rowid
     row_number: usize,
     /// Values of the current row
@@ -265,7 +330,7 @@ struct CSVTabCursor {
 }
 
 impl CSVTabCursor {
-    fn new(reader: csv::Reader<File>) -> CSVTabCursor {
+    fn new(reader: csv::Reader<Source>) -> CSVTabCursor {
         CSVTabCursor {
             base: ffi::sqlite3_vtab_cursor::default(),
             reader,
@@ -335,6 +400,71 @@ impl VTabCursor for CSVTabCursor {
     }
 }
 
+/// Options for `export_csv`.
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    /// Field delimiter. Default `,`.
+    pub delimiter: u8,
+    /// Quote character. Default `"`. `0` disables quoting.
+    pub quote: u8,
+    /// Write the column names as the first record. Default `true`.
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            delimiter: b',',
+            quote: b'"',
+            header: true,
+        }
+    }
+}
+
+/// Execute `stmt` with `params` and write its rows as CSV.
+///
+/// `NULL` is written as an empty field and blobs are written as is.
+///
+/// # Failure
+///
+/// Will return `Err` if binding parameters fails or the query fails.
+pub fn export_csv<P>(stmt: &mut Statement<'_>, params: P, options: &CsvOptions) -> Result<Vec<u8>>
+where
+    P: IntoIterator,
+    P::Item: ToSql,
+{
+    let mut builder = csv::WriterBuilder::new();
+    builder.delimiter(options.delimiter);
+    if options.quote == 0 {
+        builder.quote_style(csv::QuoteStyle::Never);
+    } else {
+        builder.quote(options.quote);
+    }
+    let mut writer = builder.from_writer(Vec::new());
+
+    let n = stmt.column_count();
+    if options.header {
+        writer.write_record(stmt.column_names())?;
+    }
+    let mut rows = stmt.query(params)?;
+    let mut record = Vec::with_capacity(n);
+    while let Some(row) = rows.next()? {
+        record.clear();
+        for col in 0..n {
+            record.push(match row.get_raw(col) {
+                ValueRef::Null => Vec::new(),
+                ValueRef::Integer(i) => i.to_string().into_bytes(),
+                ValueRef::Real(f) => f.to_string().into_bytes(),
+                ValueRef::Text(s) | ValueRef::Blob(s) => s.to_vec(),
+            });
+        }
+        writer.write_record(&record)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|err| Error::ModuleError(err.to_string()))
+}
+
 impl From<csv::Error> for Error {
     fn from(err: csv::Error) -> Error {
         Error::ModuleError(err.to_string())
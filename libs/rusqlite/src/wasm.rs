@@ -0,0 +1,142 @@
+//! Conversion of `Error` into JavaScript errors, for `wasm-bindgen`
+//! entry points.
+//!
+//! `impl From<Error> for JsValue` lets exported functions returning
+//! `Result<_, JsValue>` use `?` on rusqlite results. The thrown value is an
+//! instance of one of the classes returned by `errorClasses()`:
+//! `SqliteError`, with subclasses `SqlSyntaxError` and `ConstraintError`,
+//! which in turn has `UniqueConstraintError`, `PrimaryKeyConstraintError`,
+//! `NotNullConstraintError`, `ForeignKeyConstraintError` and
+//! `CheckConstraintError`.
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use crate::{ConstraintKind, Error};
+
+#[wasm_bindgen(inline_js = "
+export class SqliteError extends Error {
+    constructor(message, details) {
+        super(message);
+        this.name = new.target.name;
+        Object.assign(this, details);
+    }
+}
+export class SqlSyntaxError extends SqliteError {}
+export class ConstraintError extends SqliteError {}
+export class UniqueConstraintError extends ConstraintError {}
+export class PrimaryKeyConstraintError extends ConstraintError {}
+export class NotNullConstraintError extends ConstraintError {}
+export class ForeignKeyConstraintError extends ConstraintError {}
+export class CheckConstraintError extends ConstraintError {}
+
+const classes = {
+    SqliteError,
+    SqlSyntaxError,
+    ConstraintError,
+    UniqueConstraintError,
+    PrimaryKeyConstraintError,
+    NotNullConstraintError,
+    ForeignKeyConstraintError,
+    CheckConstraintError,
+};
+
+export function sqliteErrorClasses() {
+    return classes;
+}
+
+export function makeSqliteError(className, message, details) {
+    return new classes[className](message, details);
+}
+")]
+extern "C" {
+    #[wasm_bindgen(js_name = sqliteErrorClasses)]
+    fn sqlite_error_classes() -> Object;
+
+    #[wasm_bindgen(js_name = makeSqliteError)]
+    fn make_sqlite_error(class_name: &str, message: &str, details: &Object) -> JsValue;
+}
+
+/// Return the error classes thrown for rusqlite errors, keyed by name, so
+/// that callers can use `instanceof`.
+#[wasm_bindgen(js_name = errorClasses)]
+pub fn error_classes() -> Object {
+    sqlite_error_classes()
+}
+
+fn set(target: &Object, key: &str, value: JsValue) {
+    Reflect::set(target, &key.into(), &value).unwrap();
+}
+
+fn optional(value: Option<&str>) -> JsValue {
+    value.map_or(JsValue::NULL, JsValue::from)
+}
+
+/// Pick the JS error class for `err` and fill in the properties it carries:
+/// `code` and `extendedCode` for SQLite failures, `sql` and `offset` for
+/// syntax errors and `constraint` for constraint violations.
+fn classify(err: &Error, details: &Object) -> &'static str {
+    match *err {
+        Error::SqlInputError {
+            ref error,
+            ref sql,
+            offset,
+            ..
+        } => {
+            set(details, "code", format!("{:?}", error.code).into());
+            set(details, "extendedCode", error.extended_code.into());
+            set(details, "sql", sql.into());
+            set(details, "offset", (offset as u32).into());
+            "SqlSyntaxError"
+        }
+        Error::SqliteFailure(ref error, _) => {
+            set(details, "code", format!("{:?}", error.code).into());
+            set(details, "extendedCode", error.extended_code.into());
+            let constraint = match err.constraint() {
+                Some(constraint) => constraint,
+                None => return "SqliteError",
+            };
+            let info = Object::new();
+            set(&info, "kind", format!("{:?}", constraint.kind).into());
+            set(&info, "table", optional(constraint.table.as_deref()));
+            let columns: Array = constraint.columns.iter().map(JsValue::from).collect();
+            set(&info, "columns", columns.into());
+            set(&info, "name", optional(constraint.name.as_deref()));
+            set(details, "constraint", info.into());
+            match constraint.kind {
+                ConstraintKind::Unique => "UniqueConstraintError",
+                ConstraintKind::PrimaryKey => "PrimaryKeyConstraintError",
+                ConstraintKind::NotNull => "NotNullConstraintError",
+                ConstraintKind::ForeignKey => "ForeignKeyConstraintError",
+                ConstraintKind::Check => "CheckConstraintError",
+                ConstraintKind::Trigger | ConstraintKind::Other => "ConstraintError",
+            }
+        }
+        Error::MigrationFailed(step, ref err) => {
+            set(details, "migrationStep", (step as u32).into());
+            classify(err, details)
+        }
+        Error::ScriptFailed(index, ref err) => {
+            set(details, "statementIndex", (index as u32).into());
+            classify(err, details)
+        }
+        _ => "SqliteError",
+    }
+}
+
+/// Convert `err` to a JS error whose `sql` property is `sql`, unless the
+/// error carries the failing SQL itself. `code` and `extendedCode` are
+/// `null` for errors that do not come from SQLite.
+pub fn to_js_error(err: &Error, sql: Option<&str>) -> JsValue {
+    let details = Object::new();
+    set(&details, "code", JsValue::NULL);
+    set(&details, "extendedCode", JsValue::NULL);
+    set(&details, "sql", optional(sql));
+    let class_name = classify(err, &details);
+    make_sqlite_error(class_name, &err.to_string(), &details)
+}
+
+impl From<Error> for JsValue {
+    fn from(err: Error) -> JsValue {
+        to_js_error(&err, None)
+    }
+}
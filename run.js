@@ -1,2 +1,19 @@
 const sqlite_demo = require('./dist-nodejs/rust_sqlite_wasm')
-sqlite_demo.start();
+
+if (process.argv.includes('--shell')) {
+    const readline = require('readline');
+    const shell = new sqlite_demo.Shell();
+    const rl = readline.createInterface({
+        input: process.stdin,
+        output: process.stdout,
+        prompt: 'sqlite> ',
+    });
+    rl.prompt();
+    rl.on('line', line => {
+        process.stdout.write(shell.eval(line));
+        rl.setPrompt(shell.pending ? '   ...> ' : 'sqlite> ');
+        rl.prompt();
+    });
+} else {
+    sqlite_demo.start();
+}
@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::rc::Rc;
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use rusqlite::blob::Blob;
+use rusqlite::{Connection, DatabaseName};
+
+/// Number of bytes per chunk of `readable()` when the caller does not pick
+/// one.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The open blob shared by a `BlobHandle` and the callbacks of its streams.
+struct OpenBlob {
+    // Declared before `conn` so the blob is closed before the connection can
+    // be dropped.
+    blob: Option<Blob<'static>>,
+    #[allow(dead_code)]
+    conn: Rc<Connection>,
+}
+
+fn io_error(err: io::Error) -> JsValue {
+    if err.kind() == io::ErrorKind::WriteZero {
+        return js_sys::RangeError::new(
+            "write past the end of the blob; reserve its size with zeroblob(n)",
+        )
+        .into();
+    }
+    let message = err.to_string();
+    match err
+        .into_inner()
+        .map(|inner| inner.downcast::<rusqlite::Error>())
+    {
+        Some(Ok(err)) => JsValue::from(*err),
+        _ => js_sys::Error::new(&message).into(),
+    }
+}
+
+impl OpenBlob {
+    fn blob(&mut self) -> Result<&mut Blob<'static>, JsValue> {
+        self.blob
+            .as_mut()
+            .ok_or_else(|| js_sys::Error::new("blob is closed").into())
+    }
+
+    /// Read up to `max` bytes, or `None` at the end of the blob.
+    fn read(&mut self, max: usize) -> Result<Option<Vec<u8>>, JsValue> {
+        let mut buf = vec![0; max.max(1)];
+        let n = self.blob()?.read(&mut buf).map_err(io_error)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.truncate(n);
+        Ok(Some(buf))
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.blob()?.write_all(data).map_err(io_error)
+    }
+
+    fn close(&mut self) -> Result<(), JsValue> {
+        match self.blob.take() {
+            Some(blob) => Ok(blob.close()?),
+            None => Ok(()),
+        }
+    }
+}
+
+fn call_method(target: &JsValue, name: &str, args: &Array) -> Result<JsValue, JsValue> {
+    let method: Function = Reflect::get(target, &JsValue::from_str(name))?.dyn_into()?;
+    Reflect::apply(&method, target, args)
+}
+
+/// `undefined` on success, a rejected promise otherwise; the stream
+/// machinery treats the rejection as an error of the stream.
+fn settle(result: Result<(), JsValue>) -> JsValue {
+    match result {
+        Ok(()) => JsValue::UNDEFINED,
+        Err(err) => Promise::reject(&err).into(),
+    }
+}
+
+fn new_stream(class_name: &str, source: &Object) -> Result<JsValue, JsValue> {
+    let class = Reflect::get(&js_sys::global(), &JsValue::from_str(class_name))?;
+    if class.is_undefined() {
+        let message = format!("{} is not available", class_name);
+        return Err(js_sys::TypeError::new(&message).into());
+    }
+    Reflect::construct(&class.dyn_into::<Function>()?, &Array::of1(source))
+}
+
+/// Incremental access to a single blob, opened with `Database.openBlob`.
+///
+/// A blob cannot change size through this handle, so reserve the space
+/// first with `zeroblob(n)`:
+///
+/// ```js
+/// db.exec(`INSERT INTO attachment (id, data) VALUES (1, zeroblob(${file.size}))`);
+/// await file.stream().pipeTo(db.openBlob("attachment", "data", 1, false).writable());
+/// await db.openBlob("attachment", "data", 1, true).readable().pipeTo(upload);
+/// ```
+///
+/// The streams keep the blob open on their own, so they go on working after
+/// a temporary handle is freed; `close()` on the handle ends them too.
+#[wasm_bindgen]
+pub struct BlobHandle {
+    state: Rc<RefCell<OpenBlob>>,
+}
+
+impl BlobHandle {
+    /// Open the blob in `column` of row `rowid` of `table`.
+    pub fn open(
+        conn: Rc<Connection>,
+        table: &str,
+        column: &str,
+        rowid: i64,
+        read_only: bool,
+    ) -> rusqlite::Result<BlobHandle> {
+        let blob = conn.blob_open(DatabaseName::Main, table, column, rowid, read_only)?;
+        // The blob borrows the connection owned by `conn`. The `Rc` is stored
+        // alongside it and outlives it, so the borrow stays valid.
+        let blob: Blob<'static> = unsafe { mem::transmute(blob) };
+        Ok(BlobHandle {
+            state: Rc::new(RefCell::new(OpenBlob {
+                blob: Some(blob),
+                conn,
+            })),
+        })
+    }
+}
+
+/// A JS function calling `f`, owned by the JS side so that it lives as long
+/// as the stream holding it rather than the handle.
+fn callback<F>(f: F) -> JsValue
+where
+    F: FnMut(JsValue) -> JsValue + 'static,
+{
+    Closure::wrap(Box::new(f) as Box<dyn FnMut(JsValue) -> JsValue>).into_js_value()
+}
+
+#[wasm_bindgen]
+impl BlobHandle {
+    /// The size of the blob in bytes.
+    pub fn size(&self) -> Result<u32, JsValue> {
+        Ok(self.state.borrow_mut().blob()?.size() as u32)
+    }
+
+    /// Read up to `maxBytes` bytes (64 KiB by default) from the current
+    /// position; returns `undefined` at the end of the blob.
+    pub fn read(&self, max_bytes: Option<u32>) -> Result<Option<Uint8Array>, JsValue> {
+        let max = max_bytes.map_or(DEFAULT_CHUNK_SIZE, |n| n as usize);
+        let chunk = self.state.borrow_mut().read(max)?;
+        Ok(chunk.map(|chunk| Uint8Array::from(&chunk[..])))
+    }
+
+    /// Write `data` at the current position. Fails with a `RangeError` if it
+    /// does not fit in the blob.
+    pub fn write(&self, data: &[u8]) -> Result<(), JsValue> {
+        self.state.borrow_mut().write(data)
+    }
+
+    /// Move to byte `offset` from the start of the blob.
+    pub fn seek(&self, offset: u32) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state
+            .blob()?
+            .seek(SeekFrom::Start(u64::from(offset)))
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Move the handle to the same column of row `rowid`, at offset 0.
+    pub fn reopen(&self, rowid: f64) -> Result<(), JsValue> {
+        let rowid = to_rowid(rowid)?;
+        self.state.borrow_mut().blob()?.reopen(rowid)?;
+        Ok(())
+    }
+
+    /// Close the blob. Reading or writing afterwards fails.
+    pub fn close(&self) -> Result<(), JsValue> {
+        self.state.borrow_mut().close()
+    }
+
+    /// Return a `ReadableStream` of `Uint8Array` chunks of at most
+    /// `chunkSize` bytes (64 KiB by default), from the current position to
+    /// the end of the blob. The blob is closed when the stream ends or is
+    /// cancelled.
+    pub fn readable(&self, chunk_size: Option<u32>) -> Result<JsValue, JsValue> {
+        let chunk_size = chunk_size.map_or(DEFAULT_CHUNK_SIZE, |n| n as usize);
+        let state = Rc::clone(&self.state);
+        let pull = callback(move |controller| {
+            let chunk = state.borrow_mut().read(chunk_size);
+            settle(chunk.and_then(|chunk| {
+                match chunk {
+                    Some(chunk) => {
+                        let chunk = Uint8Array::from(&chunk[..]);
+                        call_method(&controller, "enqueue", &Array::of1(&chunk))?;
+                    }
+                    None => {
+                        state.borrow_mut().close()?;
+                        call_method(&controller, "close", &Array::new())?;
+                    }
+                }
+                Ok(())
+            }))
+        });
+        let state = Rc::clone(&self.state);
+        let cancel = callback(move |_| settle(state.borrow_mut().close()));
+
+        let source = Object::new();
+        Reflect::set(&source, &JsValue::from_str("pull"), &pull)?;
+        Reflect::set(&source, &JsValue::from_str("cancel"), &cancel)?;
+        new_stream("ReadableStream", &source)
+    }
+
+    /// Return a `WritableStream` that writes each `Uint8Array` chunk at the
+    /// current position. The blob is closed when the stream is closed or
+    /// aborted; a chunk that does not fit errors the stream.
+    pub fn writable(&self) -> Result<JsValue, JsValue> {
+        let state = Rc::clone(&self.state);
+        let write = callback(move |chunk| {
+            let chunk: Uint8Array = chunk.unchecked_into();
+            settle(state.borrow_mut().write(&chunk.to_vec()))
+        });
+        let state = Rc::clone(&self.state);
+        let close = callback(move |_| settle(state.borrow_mut().close()));
+        let state = Rc::clone(&self.state);
+        let abort = callback(move |_| settle(state.borrow_mut().close()));
+
+        let sink = Object::new();
+        Reflect::set(&sink, &JsValue::from_str("write"), &write)?;
+        Reflect::set(&sink, &JsValue::from_str("close"), &close)?;
+        Reflect::set(&sink, &JsValue::from_str("abort"), &abort)?;
+        new_stream("WritableStream", &sink)
+    }
+}
+
+/// Convert a JS number to a rowid.
+pub fn to_rowid(rowid: f64) -> Result<i64, JsValue> {
+    if rowid.fract() != 0.0 || rowid.abs() > 9_007_199_254_740_991.0 {
+        return Err(js_sys::RangeError::new("rowid must be a safe integer").into());
+    }
+    Ok(rowid as i64)
+}
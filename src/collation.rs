@@ -0,0 +1,37 @@
+use std::cmp::Ordering;
+
+use js_sys::{Array, Function, Intl, Object};
+use wasm_bindgen::prelude::*;
+
+struct SendComparator(Function);
+
+// wasm32-unknown-unknown is single threaded, so the comparator is only ever
+// called from the thread that created it.
+unsafe impl Send for SendComparator {}
+unsafe impl Sync for SendComparator {}
+
+/// Wrap a JS `(a, b) => number` function as a collation comparator. A
+/// comparator that throws or returns something other than a number treats
+/// the strings as equal, since SQLite has no way to report the error.
+pub fn js_comparator(compare: Function) -> impl Fn(&str, &str) -> Ordering + Send + Sync {
+    let compare = SendComparator(compare);
+    move |a, b| {
+        let result = compare
+            .0
+            .call2(&JsValue::NULL, &JsValue::from_str(a), &JsValue::from_str(b))
+            .ok()
+            .and_then(|r| r.as_f64())
+            .unwrap_or(0f64);
+        result.partial_cmp(&0f64).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A comparator backed by `new Intl.Collator(locales, options).compare`.
+pub fn intl_comparator(
+    locales: Option<Array>,
+    options: Option<Object>,
+) -> impl Fn(&str, &str) -> Ordering + Send + Sync {
+    let locales = locales.unwrap_or_else(Array::new);
+    let options = options.unwrap_or_else(Object::new);
+    js_comparator(Intl::Collator::new(&locales, &options).compare())
+}
@@ -0,0 +1,50 @@
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use rusqlite::types::{Value, ValueRef};
+
+// Integers outside this range lose precision as JS numbers.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991f64;
+
+/// Convert a SQLite value into a JS value. Integers and reals become numbers,
+/// text becomes a string and blobs become a `Uint8Array`.
+pub fn value_to_js(value: ValueRef<'_>) -> JsValue {
+    match value {
+        ValueRef::Null => JsValue::NULL,
+        ValueRef::Integer(i) => JsValue::from_f64(i as f64),
+        ValueRef::Real(f) => JsValue::from_f64(f),
+        ValueRef::Text(s) => JsValue::from_str(&String::from_utf8_lossy(s)),
+        ValueRef::Blob(b) => Uint8Array::from(b).into(),
+    }
+}
+
+/// Convert a JS value into a SQLite value. Numbers without a fractional part
+/// that fit in a safe integer are bound as integers.
+pub fn js_to_value(value: &JsValue) -> Result<Value, JsValue> {
+    if value.is_null() || value.is_undefined() {
+        Ok(Value::Null)
+    } else if let Some(b) = value.as_bool() {
+        Ok(Value::from(b))
+    } else if let Some(f) = value.as_f64() {
+        if f.fract() == 0f64 && f.abs() <= MAX_SAFE_INTEGER {
+            Ok(Value::Integer(f as i64))
+        } else {
+            Ok(Value::Real(f))
+        }
+    } else if let Some(s) = value.as_string() {
+        Ok(Value::Text(s))
+    } else if let Some(bytes) = value.dyn_ref::<Uint8Array>() {
+        Ok(Value::Blob(bytes.to_vec()))
+    } else {
+        Err(js_sys::TypeError::new("unsupported value type").into())
+    }
+}
+
+/// Convert an optional JS array of parameters into SQLite values.
+pub fn js_to_params(params: Option<Array>) -> Result<Vec<Value>, JsValue> {
+    match params {
+        Some(params) => params.iter().map(|p| js_to_value(&p)).collect(),
+        None => Ok(Vec::new()),
+    }
+}
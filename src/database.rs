@@ -0,0 +1,374 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use rusqlite::vtab::csvtab::{self, CsvOptions};
+use rusqlite::{extensions, Connection, StatementResult};
+
+use blob_handle::{to_rowid, BlobHandle};
+use collation::{intl_comparator, js_comparator};
+use convert::{js_to_params, value_to_js};
+use error::sql_error;
+use functions::{create_js_function, function_flags, JsAggregate};
+use query_plan::query_plan_to_js;
+use row_stream::{RowStream, DEFAULT_BATCH_SIZE};
+use schema::schema_to_json;
+use shell::Shell;
+use transaction::{retry_policy, JsTransaction};
+
+/// A SQLite connection exported to JavaScript.
+#[wasm_bindgen]
+pub struct Database {
+    conn: Rc<Connection>,
+    /// Whether a promise returned by `transaction` has not settled yet.
+    transaction_pending: Rc<Cell<bool>>,
+}
+
+#[wasm_bindgen]
+impl Database {
+    /// Open a new in-memory database with the `csv` module and the
+    /// `rusqlite::extensions` functions loaded. Its statement cache
+    /// normalizes SQL, so queries that only differ in whitespace share a
+    /// prepared statement.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Database, JsValue> {
+        let conn = Connection::open_in_memory()?;
+        conn.set_prepared_statement_cache_normalization(true);
+        csvtab::load_module(&conn)?;
+        extensions::register_all(&conn)?;
+        Ok(Database {
+            conn: Rc::new(conn),
+            transaction_pending: Rc::new(Cell::new(false)),
+        })
+    }
+
+    /// Run one or more SQL statements that do not take parameters.
+    pub fn exec(&self, sql: &str) -> Result<(), JsValue> {
+        self.conn.execute_batch(sql).map_err(sql_error(sql))
+    }
+
+    /// Run each statement of `sql` in turn, as a SQL console does, and
+    /// return one `{ start, end, columns, rows, changes, lastInsertRowid }`
+    /// object per statement, where `sql.slice(start, end)` is its text and
+    /// `rows` holds arrays of values. Stops at the first
+    /// failing statement, whose index is the `statementIndex` of the thrown
+    /// error.
+    #[wasm_bindgen(js_name = executeScript)]
+    pub fn execute_script(&self, sql: &str) -> Result<Array, JsValue> {
+        let results = self.conn.execute_script(sql).map_err(sql_error(sql))?;
+        results
+            .iter()
+            .map(|result| statement_result_to_js(sql, result))
+            .collect()
+    }
+
+    /// Return the `EXPLAIN QUERY PLAN` tree of `sql` as a JS object with
+    /// `text`, `steps`, `hasFullScan` and `usesTempBTree` properties.
+    #[wasm_bindgen(js_name = explainQueryPlan)]
+    pub fn explain_query_plan(&self, sql: &str) -> Result<JsValue, JsValue> {
+        self.conn
+            .explain_query_plan(sql)
+            .map(|plan| query_plan_to_js(&plan))
+            .map_err(sql_error(sql))
+    }
+
+    /// Return the tables, views and triggers of the database as a plain JS
+    /// object: `{ tables, views, triggers }`, where each table has
+    /// `columns`, `indexes` and `foreignKeys`.
+    pub fn schema(&self) -> Result<JsValue, JsValue> {
+        let schema = self.conn.schema()?;
+        js_sys::JSON::parse(&schema_to_json(&schema).to_string())
+    }
+
+    /// Run `sql` with `params` and return a `RowStream` that yields the rows
+    /// in batches of `batchSize` (256 by default) through `for await`.
+    pub fn stream(
+        &self,
+        sql: &str,
+        params: Option<Array>,
+        batch_size: Option<u32>,
+    ) -> Result<JsValue, JsValue> {
+        let params = js_to_params(params)?;
+        let batch_size = batch_size.map_or(DEFAULT_BATCH_SIZE, |n| n as usize);
+        RowStream::new(Rc::clone(&self.conn), sql, &params, batch_size)
+            .map(RowStream::into_js)
+            .map_err(sql_error(sql))
+    }
+
+    /// Run `sql` with `params` and return the result as an Arrow IPC stream
+    /// of record batches with at most `batchSize` rows (all rows by default).
+    #[wasm_bindgen(js_name = queryArrow)]
+    pub fn query_arrow(
+        &self,
+        sql: &str,
+        params: Option<Array>,
+        batch_size: Option<u32>,
+    ) -> Result<Uint8Array, JsValue> {
+        let params = js_to_params(params)?;
+        let mut stmt = self.conn.prepare_cached(sql).map_err(sql_error(sql))?;
+        let bytes = stmt
+            .query_arrow_ipc(&params, batch_size.unwrap_or(0) as usize)
+            .map_err(sql_error(sql))?;
+        Ok(Uint8Array::from(&bytes[..]))
+    }
+
+    /// Run `sql` with `params` and return its rows as CSV. `options` may set
+    /// `delimiter`, `quote` (an empty string disables quoting) and `header`.
+    #[wasm_bindgen(js_name = exportCsv)]
+    pub fn export_csv(
+        &self,
+        sql: &str,
+        params: Option<Array>,
+        options: Option<Object>,
+    ) -> Result<Uint8Array, JsValue> {
+        let params = js_to_params(params)?;
+        let options = match options {
+            Some(options) => csv_options(&options)?,
+            None => CsvOptions::default(),
+        };
+        let mut stmt = self.conn.prepare_cached(sql).map_err(sql_error(sql))?;
+        let bytes = csvtab::export_csv(&mut stmt, &params, &options).map_err(sql_error(sql))?;
+        Ok(Uint8Array::from(&bytes[..]))
+    }
+
+    /// Register `func` as a scalar SQL function taking `nArgs` arguments
+    /// (`-1` for any number). `options` may set `deterministic`, `innocuous`
+    /// and `directOnly`.
+    #[wasm_bindgen(js_name = createFunction)]
+    pub fn create_function(
+        &self,
+        name: &str,
+        n_args: i32,
+        func: Function,
+        options: Option<Object>,
+    ) -> Result<(), JsValue> {
+        let flags = function_flags(options.as_ref())?;
+        create_js_function(&self.conn, name, n_args, flags, func)?;
+        Ok(())
+    }
+
+    /// Register an aggregate SQL function built from an object with `step`
+    /// and optional `init` and `finalize` functions. It accepts any number of
+    /// arguments.
+    #[wasm_bindgen(js_name = createAggregate)]
+    pub fn create_aggregate(
+        &self,
+        name: &str,
+        aggregate: Object,
+        options: Option<Object>,
+    ) -> Result<(), JsValue> {
+        let flags = function_flags(options.as_ref())?;
+        let aggregate = JsAggregate::from_object(&aggregate)?;
+        self.conn
+            .create_aggregate_function(name, -1, flags, aggregate)?;
+        Ok(())
+    }
+
+    /// Register `compare(a, b)` as the collation `name`. It is installed
+    /// the first time SQL uses `COLLATE name`.
+    #[wasm_bindgen(js_name = createCollation)]
+    pub fn create_collation(&self, name: &str, compare: Function) -> Result<(), JsValue> {
+        self.conn.register_collation(name, js_comparator(compare))?;
+        Ok(())
+    }
+
+    /// Register a locale-aware collation `name` that compares strings with
+    /// `new Intl.Collator(locales, options)`.
+    #[wasm_bindgen(js_name = createLocaleCollation)]
+    pub fn create_locale_collation(
+        &self,
+        name: &str,
+        locales: Option<Array>,
+        options: Option<Object>,
+    ) -> Result<(), JsValue> {
+        self.conn
+            .register_collation(name, intl_comparator(locales, options))?;
+        Ok(())
+    }
+
+    /// Open a `Shell` on this database.
+    pub fn shell(&self) -> Shell {
+        Shell::with_connection(Rc::clone(&self.conn))
+    }
+
+    /// Open the blob in `column` of row `rowid` of `table` for incremental
+    /// reading, or writing unless `readOnly` is set.
+    #[wasm_bindgen(js_name = openBlob)]
+    pub fn open_blob(
+        &self,
+        table: &str,
+        column: &str,
+        rowid: f64,
+        read_only: bool,
+    ) -> Result<BlobHandle, JsValue> {
+        let rowid = to_rowid(rowid)?;
+        Ok(BlobHandle::open(
+            Rc::clone(&self.conn),
+            table,
+            column,
+            rowid,
+            read_only,
+        )?)
+    }
+
+    /// Return the counters of the prepared statement cache used by
+    /// `queryArrow` and `exportCsv`: `{ hits, misses, evictions,
+    /// prepareTimeMs, cached, pinned }`.
+    #[wasm_bindgen(js_name = cacheStats)]
+    pub fn cache_stats(&self) -> Result<JsValue, JsValue> {
+        let stats = self.conn.prepared_statement_cache_stats();
+        let prepare_time_ms = stats.prepare_time.as_secs_f64() * 1000.0;
+        let obj = Object::new();
+        for &(key, value) in &[
+            ("hits", stats.hits as f64),
+            ("misses", stats.misses as f64),
+            ("evictions", stats.evictions as f64),
+            ("prepareTimeMs", prepare_time_ms),
+            ("cached", stats.cached as f64),
+            ("pinned", stats.pinned as f64),
+        ] {
+            Reflect::set(&obj, &JsValue::from_str(key), &JsValue::from_f64(value))?;
+        }
+        Ok(obj.into())
+    }
+
+    /// Prepare `sql` and keep it cached until `unpinStatement(sql)`, however
+    /// many other statements go through the cache.
+    #[wasm_bindgen(js_name = pinStatement)]
+    pub fn pin_statement(&self, sql: &str) -> Result<(), JsValue> {
+        self.conn
+            .pin_prepared_statement(sql)
+            .map_err(sql_error(sql))
+    }
+
+    /// Let the statement pinned for `sql` be evicted again. Returns whether
+    /// it was pinned.
+    #[wasm_bindgen(js_name = unpinStatement)]
+    pub fn unpin_statement(&self, sql: &str) -> Result<bool, JsValue> {
+        Ok(self.conn.unpin_prepared_statement(sql))
+    }
+
+    /// Choose whether the statement cache normalizes SQL, which `new`
+    /// turns on. Without it, only identical SQL shares a statement.
+    #[wasm_bindgen(js_name = setCacheNormalization)]
+    pub fn set_cache_normalization(&self, normalize: bool) {
+        self.conn
+            .set_prepared_statement_cache_normalization(normalize)
+    }
+
+    /// Call `f` inside a transaction and resolve to its result. `f` may
+    /// return a promise: the transaction is committed once it resolves and
+    /// rolled back if it rejects or `f` throws. If a transaction was already
+    /// opened with `exec("BEGIN")`, `f` runs in a savepoint of it instead.
+    ///
+    /// ```js
+    /// await db.transaction(async () => {
+    ///     db.exec("INSERT INTO upload (name) VALUES ('a.txt')");
+    ///     await send("a.txt");
+    /// });
+    /// ```
+    ///
+    /// When an attempt fails with `SQLITE_BUSY` or `SQLITE_LOCKED`, `f` is
+    /// called again in a new transaction after a `setTimeout` backoff.
+    /// `retry` may set `maxRetries` (5 by default), `initialBackoffMs` (10)
+    /// and `maxBackoffMs` (1000); the wait doubles after each attempt.
+    ///
+    /// Only one transaction can be pending at a time: calling `transaction`
+    /// again before the promise settles rejects. Every other statement run
+    /// on this database while the promise is pending, from `f` or not, is
+    /// part of the transaction and is undone if it rolls back.
+    pub fn transaction(&self, f: Function, retry: Option<Object>) -> Promise {
+        match retry_policy(retry.as_ref()) {
+            Ok(policy) => JsTransaction::new(
+                Rc::clone(&self.conn),
+                Rc::clone(&self.transaction_pending),
+                f,
+                policy,
+            )
+            .run(),
+            Err(e) => Promise::reject(&e),
+        }
+    }
+
+    /// Remove the collation `name`. Returns whether it was registered.
+    #[wasm_bindgen(js_name = removeCollation)]
+    pub fn remove_collation(&self, name: &str) -> Result<bool, JsValue> {
+        Ok(self.conn.unregister_collation(name)?)
+    }
+}
+
+// `span` is in bytes; JS strings index UTF-16 code units.
+fn utf16_offset(sql: &str, offset: usize) -> JsValue {
+    JsValue::from_f64(sql[..offset].encode_utf16().count() as f64)
+}
+
+fn statement_result_to_js(sql: &str, result: &StatementResult) -> Result<JsValue, JsValue> {
+    let columns: Array = result
+        .columns
+        .iter()
+        .map(|c| JsValue::from_str(c))
+        .collect();
+    let rows: Array = result
+        .rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| value_to_js(value.into()))
+                .collect::<Array>()
+        })
+        .collect();
+    let obj = Object::new();
+    for &(key, ref value) in &[
+        ("start", utf16_offset(sql, result.span.start)),
+        ("end", utf16_offset(sql, result.span.end)),
+        ("columns", columns.into()),
+        ("rows", rows.into()),
+        ("changes", JsValue::from_f64(result.changes as f64)),
+        (
+            "lastInsertRowid",
+            JsValue::from_f64(result.last_insert_rowid as f64),
+        ),
+    ] {
+        Reflect::set(&obj, &JsValue::from_str(key), value)?;
+    }
+    Ok(obj.into())
+}
+
+fn csv_char(options: &Object, key: &str, default: u8) -> Result<u8, JsValue> {
+    let value = Reflect::get(options, &JsValue::from_str(key))?;
+    if value.is_undefined() {
+        return Ok(default);
+    }
+    match value.as_string() {
+        Some(ref s) if s.is_empty() => Ok(0),
+        Some(ref s) if s.len() == 1 => Ok(s.as_bytes()[0]),
+        _ => Err(js_sys::TypeError::new(&format!("{} must be a single character", key)).into()),
+    }
+}
+
+fn csv_options(options: &Object) -> Result<CsvOptions, JsValue> {
+    let defaults = CsvOptions::default();
+    let header = Reflect::get(options, &JsValue::from_str("header"))?;
+    Ok(CsvOptions {
+        delimiter: csv_char(options, "delimiter", defaults.delimiter)?,
+        quote: csv_char(options, "quote", defaults.quote)?,
+        header: header.as_bool().unwrap_or(defaults.header),
+    })
+}
+
+/// Register `data` as CSV content readable by
+/// `CREATE VIRTUAL TABLE t USING csv(buffer=name)`.
+#[wasm_bindgen(js_name = registerCsvBuffer)]
+pub fn register_csv_buffer(name: &str, data: &Uint8Array) -> Result<(), JsValue> {
+    csvtab::register_buffer(name, data.to_vec());
+    Ok(())
+}
+
+impl Database {
+    /// Borrow the underlying connection.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+}
@@ -0,0 +1,19 @@
+//! Errors thrown by the exported functions.
+//!
+//! They return `Result<_, JsValue>`, so SQLite failures are thrown as the
+//! classes of `errorClasses()`. Panics are not caught: the wasm target is
+//! built with `panic = "abort"`, so a panic traps the instance after
+//! `wasm_println`'s hook has logged it, and the module has to be
+//! instantiated again. Exported functions therefore report every expected
+//! failure through their `Result`.
+
+use wasm_bindgen::prelude::*;
+
+use rusqlite::wasm::to_js_error;
+use rusqlite::Error;
+
+/// Map a rusqlite error from running `sql` to a JS error with its `sql`
+/// property set.
+pub fn sql_error(sql: &str) -> impl Fn(Error) -> JsValue + '_ {
+    move |err| to_js_error(&err, Some(sql))
+}
@@ -0,0 +1,181 @@
+//! Text rendering of query results for `Shell`, kept free of JS types so it
+//! can be tested natively.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use rusqlite::types::Value;
+
+/// How `Shell` prints rows, as picked with `.mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Values separated by `|`, one row per line. The default.
+    List,
+    /// RFC 4180 CSV.
+    Csv,
+    /// A JSON array of objects keyed by column name.
+    Json,
+    /// An ASCII table with a header row.
+    Table,
+    /// One `column = value` line per value, with a blank line between rows.
+    Line,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "list" => Ok(Mode::List),
+            "csv" => Ok(Mode::Csv),
+            "json" => Ok(Mode::Json),
+            "table" => Ok(Mode::Table),
+            "line" => Ok(Mode::Line),
+            _ => Err(format!(
+                "mode should be one of: csv json line list table (got {:?})",
+                s
+            )),
+        }
+    }
+}
+
+/// `value` as the CLI shows it: `NULL` is empty, blobs are read as UTF-8.
+pub fn value_to_text(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => f.to_string(),
+        Value::Text(ref s) => s.clone(),
+        Value::Blob(ref b) => String::from_utf8_lossy(b).into_owned(),
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn json_value(value: &Value) -> String {
+    match *value {
+        Value::Null => "null".to_owned(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) if f.is_finite() => f.to_string(),
+        Value::Real(_) => "null".to_owned(),
+        Value::Text(ref s) => json_string(s),
+        Value::Blob(ref b) => {
+            let hex: String = b.iter().map(|byte| format!("{:02x}", byte)).collect();
+            json_string(&hex)
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    ::serde_json::to_string(s).unwrap()
+}
+
+fn format_table(columns: &[String], rows: &[Vec<String>], out: &mut String) {
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(Some(name.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut border = String::from("+");
+    for width in &widths {
+        border.push_str(&"-".repeat(width + 2));
+        border.push('+');
+    }
+    let line = |out: &mut String, cells: &[String]| {
+        out.push('|');
+        for (cell, width) in cells.iter().zip(&widths) {
+            let pad = width - cell.chars().count();
+            write!(out, " {}{} |", cell, " ".repeat(pad)).unwrap();
+        }
+        out.push('\n');
+    };
+    writeln!(out, "{}", border).unwrap();
+    line(out, columns);
+    writeln!(out, "{}", border).unwrap();
+    for row in rows {
+        line(out, row);
+    }
+    writeln!(out, "{}", border).unwrap();
+}
+
+/// Render a result set in `mode`. `headers` adds a header line in `List`
+/// and `Csv` modes; the other modes always name the columns. Statements
+/// without result columns render as an empty string.
+pub fn format_rows(mode: Mode, headers: bool, columns: &[String], rows: &[Vec<Value>]) -> String {
+    let mut out = String::new();
+    if columns.is_empty() {
+        return out;
+    }
+    let text = || -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(value_to_text).collect())
+            .collect()
+    };
+    match mode {
+        Mode::List => {
+            if headers {
+                writeln!(out, "{}", columns.join("|")).unwrap();
+            }
+            for row in text() {
+                writeln!(out, "{}", row.join("|")).unwrap();
+            }
+        }
+        Mode::Csv => {
+            let csv_line = |fields: &[String]| -> String {
+                let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                fields.join(",")
+            };
+            if headers {
+                out.push_str(&csv_line(columns));
+                out.push_str("\r\n");
+            }
+            for row in text() {
+                out.push_str(&csv_line(&row));
+                out.push_str("\r\n");
+            }
+        }
+        Mode::Json => {
+            if rows.is_empty() {
+                return out;
+            }
+            let objects: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    let fields: Vec<String> = columns
+                        .iter()
+                        .zip(row)
+                        .map(|(name, value)| format!("{}:{}", json_string(name), json_value(value)))
+                        .collect();
+                    format!("{{{}}}", fields.join(","))
+                })
+                .collect();
+            writeln!(out, "[{}]", objects.join(",\n")).unwrap();
+        }
+        Mode::Table => format_table(columns, &text(), &mut out),
+        Mode::Line => {
+            let width = columns.iter().map(|c| c.chars().count()).max().unwrap_or(0);
+            for (i, row) in text().iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                }
+                for (name, value) in columns.iter().zip(row) {
+                    let pad = width - name.chars().count();
+                    writeln!(out, "{}{} = {}", " ".repeat(pad), name, value).unwrap();
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,165 @@
+use js_sys::{Array, Function, Object, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use rusqlite::functions::{Aggregate, Context, FunctionFlags};
+use rusqlite::types::Value;
+use rusqlite::{Connection, Error, Result};
+
+use convert::{js_to_value, value_to_js};
+
+/// Returns the message of a thrown JS value.
+pub fn js_error_message(err: &JsValue) -> String {
+    if let Some(err) = err.dyn_ref::<js_sys::Error>() {
+        String::from(err.message())
+    } else if let Some(s) = err.as_string() {
+        s
+    } else {
+        format!("{:?}", err)
+    }
+}
+
+fn js_function_error(err: JsValue) -> Error {
+    Error::UserFunctionError(js_error_message(&err).into())
+}
+
+fn js_args(ctx: &Context<'_>) -> Array {
+    (0..ctx.len())
+        .map(|i| value_to_js(ctx.get_raw(i)))
+        .collect()
+}
+
+fn js_result(value: &JsValue) -> Result<Value> {
+    js_to_value(value).map_err(js_function_error)
+}
+
+fn option(options: &Object, key: &str) -> ::std::result::Result<bool, JsValue> {
+    let value = Reflect::get(options, &JsValue::from_str(key))?;
+    Ok(value.as_bool().unwrap_or(false))
+}
+
+/// Build the flags of a function from an optional
+/// `{ deterministic, innocuous, directOnly }` object.
+pub fn function_flags(options: Option<&Object>) -> ::std::result::Result<FunctionFlags, JsValue> {
+    let mut flags = FunctionFlags::SQLITE_UTF8;
+    if let Some(options) = options {
+        if option(options, "deterministic")? {
+            flags |= FunctionFlags::SQLITE_DETERMINISTIC;
+        }
+        if option(options, "innocuous")? {
+            flags |= FunctionFlags::SQLITE_INNOCUOUS;
+        }
+        if option(options, "directOnly")? {
+            flags |= FunctionFlags::SQLITE_DIRECTONLY;
+        }
+    }
+    Ok(flags)
+}
+
+struct SendFunction(Function);
+
+// wasm32-unknown-unknown is single threaded, so the function is only ever
+// called from the thread that created it.
+unsafe impl Send for SendFunction {}
+
+/// Register `func` as a scalar SQL function. Arguments are passed as JS
+/// values and the return value is converted back into a SQLite value.
+/// Exceptions thrown by `func` become SQL errors.
+pub fn create_js_function(
+    conn: &Connection,
+    name: &str,
+    n_arg: i32,
+    flags: FunctionFlags,
+    func: Function,
+) -> Result<()> {
+    let func = SendFunction(func);
+    conn.create_scalar_function(name, n_arg, flags, move |ctx| {
+        let value = func
+            .0
+            .apply(&JsValue::NULL, &js_args(ctx))
+            .map_err(js_function_error)?;
+        js_result(&value)
+    })
+}
+
+/// The state of a JS aggregate, or the exception thrown by `init`.
+type Accumulator = ::std::result::Result<JsValue, String>;
+
+/// An aggregate built from `{ init, step, finalize }` JS functions.
+///
+/// `init()` returns the initial state (`null` when omitted),
+/// `step(state, ...args)` returns the next state and `finalize(state)`
+/// returns the result (the state itself when omitted).
+pub struct JsAggregate {
+    init: Option<Function>,
+    step: Function,
+    finalize: Option<Function>,
+}
+
+impl JsAggregate {
+    pub fn from_object(obj: &Object) -> ::std::result::Result<JsAggregate, JsValue> {
+        let get = |key: &str| -> ::std::result::Result<Option<Function>, JsValue> {
+            let value = Reflect::get(obj, &JsValue::from_str(key))?;
+            if value.is_undefined() {
+                Ok(None)
+            } else {
+                value.dyn_into::<Function>().map(Some).map_err(|_| {
+                    js_sys::TypeError::new(&format!("{} must be a function", key)).into()
+                })
+            }
+        };
+        let init = get("init")?;
+        let finalize = get("finalize")?;
+        match get("step")? {
+            Some(step) => Ok(JsAggregate {
+                init,
+                step,
+                finalize,
+            }),
+            None => Err(js_sys::TypeError::new("step must be a function").into()),
+        }
+    }
+}
+
+impl Aggregate<Accumulator, Value> for JsAggregate {
+    fn init(&self) -> Accumulator {
+        match self.init {
+            Some(ref init) => init
+                .call0(&JsValue::NULL)
+                .map_err(|err| js_error_message(&err)),
+            None => Ok(JsValue::NULL),
+        }
+    }
+
+    fn step(&self, ctx: &mut Context<'_>, acc: &mut Accumulator) -> Result<()> {
+        let state = match *acc {
+            Ok(ref state) => state.clone(),
+            Err(ref err) => return Err(Error::UserFunctionError(err.clone().into())),
+        };
+        let args = js_args(ctx);
+        args.unshift(&state);
+        let state = self
+            .step
+            .apply(&JsValue::NULL, &args)
+            .map_err(js_function_error)?;
+        *acc = Ok(state);
+        Ok(())
+    }
+
+    fn finalize(&self, acc: Option<Accumulator>) -> Result<Value> {
+        let state = match acc {
+            Some(acc) => acc,
+            None => self.init(),
+        }
+        .map_err(|err| Error::UserFunctionError(err.into()))?;
+        match self.finalize {
+            Some(ref finalize) => {
+                let value = finalize
+                    .call1(&JsValue::NULL, &state)
+                    .map_err(js_function_error)?;
+                js_result(&value)
+            }
+            None => js_result(&state),
+        }
+    }
+}
@@ -4,7 +4,8 @@ use wasm_bindgen::prelude::*;
 
 extern crate rusqlite;
 
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, FromRow, Table, ToParams};
+use std::convert::TryFrom;
 
 extern crate libc_sys;
 
@@ -12,10 +13,41 @@ extern crate libc_sys;
 extern crate js_sys;
 #[cfg(test)]
 #[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+#[cfg(test)]
+#[macro_use]
 extern crate lazy_static;
-
-#[derive(Debug, Clone)]
+#[cfg(test)]
+extern crate futures;
+#[cfg(test)]
+extern crate wasm_bindgen_futures;
+
+mod blob_handle;
+mod collation;
+mod convert;
+mod database;
+mod error;
+mod format;
+mod functions;
+mod query_plan;
+mod row_stream;
+mod schema;
+mod shell;
+mod transaction;
+pub mod worker;
+
+pub use blob_handle::BlobHandle;
+pub use database::Database;
+pub use rusqlite::wasm::error_classes;
+pub use row_stream::RowStream;
+pub use shell::Shell;
+pub use worker::AsyncDatabase;
+
+#[derive(Debug, Clone, FromRow, ToParams, Table)]
 struct Person {
+    #[rusqlite(primary_key)]
     id: i32,
     name: String,
     time_created: f64,
@@ -23,78 +55,52 @@ struct Person {
 }
 
 #[wasm_bindgen]
-pub fn start() {
+pub fn start() -> Result<(), JsValue> {
     wasm_println::hook();
     println!("Sqlite Version {:?}", rusqlite::version());
     println!();
 
-    match Connection::open_in_memory() {
-        Ok(conn) => {
-            println!("Creating TABLE: person
-            CREATE TABLE person (
-                          id              INTEGER PRIMARY KEY,
-                          name            TEXT NOT NULL,
-                          time_created    INTEGER NOT NULL,
-                          data            BLOB
-                          )");
-            println!();
-            conn.execute(
-                "CREATE TABLE person (
-                          id              INTEGER PRIMARY KEY,
-                          name            TEXT NOT NULL,
-                          time_created    INTEGER NOT NULL,
-                           data            BLOB
-                           )",
-                params![],
-            ).expect("CREATE TABLE statement did not execute.");
-
-
-            let me = Person {
-                id: 0,
-                name: "Person ".to_string(),
-                time_created: js_sys::Date::new_0().value_of(),
-                data: None,
-            };
-            println!("Inserting into TABLE: \
-            INSERT INTO person(name,time_created,data) VALUES (?1, ?2, ?3,?4)");
-
-            for i in 0..10 {
-                conn.execute(
-                    "INSERT INTO person (id,name, time_created, data)
-                                   VALUES (?1, ?2, ?3,?4)",
-                    params![
-                        i,
-                        me.name.clone() + &i.to_string().clone(),
-                       js_sys::Date::new_0().value_of(),
-                        me.data
-                    ],
-                ).expect("Error inserting record.");
-            }
-            println!("Querying person table : \
-            SELECT id,name,time_created,data FROM person");
-            let mut stmt = conn
-                .prepare("SELECT id, name, time_created, data FROM person")
-                .expect("Error preparing statement.");
-
-            let person_iter = stmt
-                .query_map(params![], |row| {
-                    Ok(Person {
-                        id: row.get(0)?,
-                        name: row.get(1)?,
-                        time_created: row.get(2)?,
-                        data: row.get(3)?,
-                    })
-                })
-                .expect("Select query failed");
-
-            for p in person_iter {
-                let person = p.expect("Could not unwrap person").clone();
-                println!("{:?}", person);
-            }
-            println!("Done");
-        }
-        Err(e) => println!("{:?}", e),
+    let conn = Connection::open_in_memory()?;
+    let create_table = Person::create_table_sql();
+    println!("Creating TABLE: person
+    {}", create_table);
+    println!();
+    conn.execute(&create_table, params![])?;
+
+    let me = Person {
+        id: 0,
+        name: "Person ".to_string(),
+        time_created: js_sys::Date::new_0().value_of(),
+        data: None,
+    };
+    println!("Inserting into TABLE: \
+    INSERT INTO person(id,name,time_created,data) VALUES (:id, :name, :time_created, :data)");
+
+    let mut insert = conn.prepare(
+        "INSERT INTO person (id, name, time_created, data)
+                           VALUES (:id, :name, :time_created, :data)",
+    )?;
+    for i in 0..10 {
+        let person = Person {
+            id: i,
+            name: me.name.clone() + &i.to_string(),
+            time_created: js_sys::Date::new_0().value_of(),
+            data: me.data.clone(),
+        };
+        insert.execute_named(&person.to_named_params())?;
+    }
+    println!("Querying person table : \
+    SELECT id,name,time_created,data FROM person");
+    let mut stmt = conn.prepare("SELECT id, name, time_created, data FROM person")?;
+
+    let person_iter = stmt.query_map(params![], |row| Person::try_from(row))?;
+
+    for p in person_iter {
+        let person = p?;
+        println!("{:?}", person);
     }
+    println!("Done");
+    Ok(())
 }
 
 use rusqlite::ffi;
@@ -3501,4 +3507,2185 @@ mod test {
             db.query_row("SELECT CURRENT_TIMESTAMP", NO_PARAMS, |r| r.get(0));
         assert!(result.is_ok());
     }
+
+    #[wasm_bindgen_test]
+    fn test_explain_query_plan() {
+        use rusqlite::query_plan::PlanOp;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER PRIMARY KEY, y TEXT)")
+            .unwrap();
+
+        let plan = db
+            .explain_query_plan("SELECT y FROM foo WHERE x = ?")
+            .unwrap();
+        assert_eq!(1, plan.roots().len());
+        assert_eq!(PlanOp::Search, plan.roots()[0].op());
+        assert_eq!(Some("foo"), plan.roots()[0].table());
+        assert!(!plan.has_full_scan());
+        assert!(plan.status().is_none());
+
+        let plan = db
+            .explain_query_plan("SELECT y FROM foo ORDER BY y")
+            .unwrap();
+        assert!(plan.has_full_scan());
+        assert!(plan.uses_temp_b_tree());
+        assert_eq!(
+            "QUERY PLAN\n|--SCAN TABLE foo\n`--USE TEMP B-TREE FOR ORDER BY\n",
+            plan.to_string()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_statement_query_plan_status() {
+        use rusqlite::query_plan::QueryPlanStatus;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo (x INTEGER PRIMARY KEY, y TEXT);
+             INSERT INTO foo (y) VALUES ('c'), ('b'), ('a');",
+        )
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT y FROM foo ORDER BY y").unwrap();
+        assert_eq!(
+            Some(QueryPlanStatus::default()),
+            stmt.query_plan().unwrap().status()
+        );
+        {
+            let mut rows = stmt.query(NO_PARAMS).unwrap();
+            while rows.next().unwrap().is_some() {}
+        }
+        let status = stmt.query_plan().unwrap().status().unwrap();
+        assert_eq!(1, status.sorts);
+        assert!(status.fullscan_steps > 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_database_explain_query_plan() {
+        use js_sys::Reflect;
+        use wasm_bindgen::JsValue;
+
+        let db = ::Database::new().unwrap();
+        db.exec("CREATE TABLE foo (x INTEGER PRIMARY KEY, y TEXT)")
+            .unwrap();
+        let plan = db.explain_query_plan("SELECT y FROM foo ORDER BY y").unwrap();
+        let has_full_scan = Reflect::get(&plan, &JsValue::from_str("hasFullScan")).unwrap();
+        assert_eq!(Some(true), has_full_scan.as_bool());
+        let text = Reflect::get(&plan, &JsValue::from_str("text")).unwrap();
+        assert!(text.as_string().unwrap().starts_with("QUERY PLAN"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_worker_protocol_roundtrip() {
+        use rusqlite::types::Value;
+        use worker::{decode_request, encode_request, Request};
+
+        let request = Request::Transaction(vec![(
+            "INSERT INTO foo VALUES (?, ?, ?, ?)".to_owned(),
+            vec![
+                Value::Integer(1),
+                Value::Real(1.5),
+                Value::Text("a".to_owned()),
+                Value::Blob(vec![0, 255]),
+            ],
+        )]);
+        let message = encode_request(7, &request);
+        assert_eq!((7, request), decode_request(&message).unwrap());
+        assert!(decode_request(r#"{"id":1,"op":"drop"}"#).is_err());
+
+        // A malformed request is still answered under its id.
+        let mut host = ::worker::WorkerHost::new(Connection::open_in_memory().unwrap());
+        let (id, response) = ::worker::decode_response(&host.handle(r#"{"id":5,"op":"drop"}"#)).unwrap();
+        assert_eq!(5, id);
+        assert_eq!(Err("unknown op: \"drop\"".to_owned()), response);
+        let (id, _) = ::worker::decode_response(&host.handle("not json")).unwrap();
+        assert_eq!(0, id);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_worker_loopback() {
+        use rusqlite::types::Value;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        use worker::{Client, Loopback, Request, Response};
+
+        let client = Client::new(Loopback::new(Connection::open_in_memory().unwrap()));
+        let responses: Rc<RefCell<Vec<Response>>> = Rc::new(RefCell::new(Vec::new()));
+        let push = |responses: &Rc<RefCell<Vec<Response>>>| {
+            let responses = Rc::clone(responses);
+            move |response| responses.borrow_mut().push(response)
+        };
+
+        client.request(&Request::Exec("CREATE TABLE foo (x INTEGER, y TEXT)".to_owned()), push(&responses));
+        client.request(
+            &Request::Transaction(vec![
+                ("INSERT INTO foo VALUES (?, ?)".to_owned(), vec![Value::Integer(1), Value::Text("a".to_owned())]),
+                ("INSERT INTO foo VALUES (?, ?)".to_owned(), vec![Value::Integer(2), Value::Null]),
+            ]),
+            push(&responses),
+        );
+        client.request(
+            &Request::Query("SELECT x, y FROM foo WHERE x >= ? ORDER BY x".to_owned(), vec![Value::Integer(1)]),
+            push(&responses),
+        );
+        client.request(&Request::Exec("SELEC".to_owned()), push(&responses));
+        // Responses are only delivered once the transport is flushed.
+        assert_eq!(4, client.pending());
+        assert!(responses.borrow().is_empty());
+
+        client.flush();
+        assert_eq!(0, client.pending());
+        let responses = responses.borrow();
+        assert_eq!(4, responses.len());
+        assert_eq!(Ok(serde_json::Value::Null), responses[0]);
+        assert_eq!(Ok(json!([1, 1])), responses[1]);
+        assert_eq!(
+            Ok(json!({ "columns": ["x", "y"], "rows": [[1, "a"], [2, null]] })),
+            responses[2]
+        );
+        assert!(responses[3].as_ref().unwrap_err().contains("syntax error"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_worker_transaction_rollback() {
+        use worker::WorkerHost;
+
+        let mut host = WorkerHost::new(Connection::open_in_memory().unwrap());
+        host.handle(r#"{"id":1,"op":"exec","sql":"CREATE TABLE foo (x INTEGER UNIQUE)"}"#);
+        let response = host.handle(
+            r#"{"id":2,"op":"transaction","statements":[
+                {"sql":"INSERT INTO foo VALUES (?)","params":[1]},
+                {"sql":"INSERT INTO foo VALUES (?)","params":[1]}]}"#,
+        );
+        let (id, response) = ::worker::decode_response(&response).unwrap();
+        assert_eq!(2, id);
+        assert!(response.is_err());
+
+        let response = host.handle(r#"{"id":3,"op":"query","sql":"SELECT count(*) FROM foo"}"#);
+        let (_, response) = ::worker::decode_response(&response).unwrap();
+        assert_eq!(json!({ "columns": ["count(*)"], "rows": [[0]] }), response.unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_row_stream_batches() {
+        use rusqlite::types::Value;
+        use std::rc::Rc;
+
+        let db = Rc::new(Connection::open_in_memory().unwrap());
+        db.execute_batch(
+            "CREATE TABLE foo (x INTEGER, y TEXT);
+             INSERT INTO foo VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd'), (5, 'e');",
+        )
+            .unwrap();
+
+        let mut stream = ::RowStream::new(
+            Rc::clone(&db),
+            "SELECT x, y FROM foo WHERE x > ? ORDER BY x",
+            &[Value::Integer(1)],
+            2,
+        )
+            .unwrap();
+        assert_eq!(&["x".to_owned(), "y".to_owned()], stream.column_names());
+        assert_eq!(
+            vec![
+                vec![Value::Integer(2), Value::Text("b".to_owned())],
+                vec![Value::Integer(3), Value::Text("c".to_owned())],
+            ],
+            stream.fetch().unwrap()
+        );
+        assert_eq!(2, stream.fetch().unwrap().len());
+        assert!(!stream.is_closed());
+        assert!(stream.fetch().unwrap().is_empty());
+        assert!(stream.is_closed());
+        assert!(stream.fetch().unwrap().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_row_stream_close_early() {
+        use std::rc::Rc;
+
+        let db = Rc::new(Connection::open_in_memory().unwrap());
+        db.execute_batch("CREATE TABLE foo (x INTEGER); INSERT INTO foo VALUES (1), (2), (3);")
+            .unwrap();
+
+        let mut stream = ::RowStream::new(Rc::clone(&db), "SELECT x FROM foo", &[], 1).unwrap();
+        assert_eq!(1, stream.fetch().unwrap().len());
+        stream.close();
+        assert!(stream.is_closed());
+        assert!(stream.fetch().unwrap().is_empty());
+        // The connection can be closed once the stream has let go of it.
+        drop(stream);
+        Rc::try_unwrap(db).unwrap().close().unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    fn test_row_stream_parameter_count() {
+        use rusqlite::types::Value;
+        use std::rc::Rc;
+
+        let db = Rc::new(Connection::open_in_memory().unwrap());
+        let stream = |params: &[Value]| {
+            ::RowStream::new(Rc::clone(&db), "SELECT ? + ?", params, 1).map(|_| ())
+        };
+        assert_eq!(Err(Error::InvalidParameterCount(1, 2)), stream(&[Value::Integer(1)]));
+        assert_eq!(
+            Err(Error::InvalidParameterCount(3, 2)),
+            stream(&[Value::Integer(1), Value::Integer(2), Value::Integer(3)])
+        );
+        assert_eq!(Ok(()), stream(&[Value::Integer(1), Value::Integer(2)]));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_query_arrow_types() {
+        use rusqlite::arrow::{ArrayData, DataType};
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo (i INTEGER, r REAL, t TEXT, b BLOB, n INTEGER, m);
+             INSERT INTO foo VALUES (1, 1.5, 'a', x'00ff', NULL, 1);
+             INSERT INTO foo VALUES (NULL, 2.5, 'b', NULL, NULL, 'two');
+             INSERT INTO foo VALUES (3, 3, NULL, x'01', NULL, 3.5);",
+        )
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT i, r, t, b, n, m, i + r AS s FROM foo").unwrap();
+        let batches = stmt.query_arrow(NO_PARAMS, 2).unwrap();
+        assert_eq!(2, batches.len());
+        assert_eq!(2, batches[0].num_rows());
+        assert_eq!(1, batches[1].num_rows());
+
+        let types: Vec<DataType> = batches[0]
+            .schema()
+            .fields
+            .iter()
+            .map(|f| f.data_type)
+            .collect();
+        assert_eq!(
+            vec![
+                DataType::Int64,
+                DataType::Float64,
+                DataType::Utf8,
+                DataType::Binary,
+                DataType::Int64,
+                DataType::Utf8,
+                DataType::Float64,
+            ],
+            types
+        );
+        assert_eq!(&ArrayData::Int64(vec![Some(1), None]), batches[0].column(0));
+        assert_eq!(1, batches[0].column(0).null_count());
+        assert_eq!(
+            &ArrayData::Utf8(vec![Some("1".to_owned()), Some("two".to_owned())]),
+            batches[0].column(5)
+        );
+        assert_eq!(&ArrayData::Float64(vec![Some(3.0)]), batches[1].column(1));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_query_arrow_ipc() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER, y TEXT); INSERT INTO foo VALUES (1, 'a'), (2, NULL);")
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT x, y FROM foo").unwrap();
+        let bytes = stmt.query_arrow_ipc(NO_PARAMS, 0).unwrap();
+        assert_eq!(0, bytes.len() % 8);
+        assert_eq!(&[0xff, 0xff, 0xff, 0xff], &bytes[..4]);
+        assert_eq!(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0], &bytes[bytes.len() - 8..]);
+
+        // The schema message is followed by a single record batch message.
+        let schema_len = i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let batch = 8 + schema_len;
+        assert_eq!(&[0xff, 0xff, 0xff, 0xff], &bytes[batch..batch + 4]);
+
+        let mut stmt = db.prepare("SELECT x FROM foo WHERE x > 10").unwrap();
+        let (schema, batches) = stmt.query_arrow_with_schema(NO_PARAMS, 0).unwrap();
+        assert_eq!(1, schema.fields.len());
+        assert!(batches.is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_csv_buffer_module() {
+        use rusqlite::vtab::csvtab;
+
+        let db = Connection::open_in_memory().unwrap();
+        csvtab::load_module(&db).unwrap();
+        csvtab::register_buffer("upload", "colA;colB\n1;\"a;b\"\n2;c\n");
+        db.execute_batch(
+            "CREATE VIRTUAL TABLE vtab USING csv(buffer=upload, header=yes, delimiter=';')",
+        )
+            .unwrap();
+        // Existing tables keep reading the buffer they were created with.
+        assert!(csvtab::unregister_buffer("upload"));
+        assert!(!csvtab::unregister_buffer("upload"));
+
+        let mut s = db.prepare("SELECT rowid, colA, colB FROM vtab").unwrap();
+        assert_eq!(vec!["rowid", "colA", "colB"], s.column_names());
+        let rows: Vec<(i64, String, String)> = s
+            .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (1, "1".to_owned(), "a;b".to_owned()),
+                (2, "2".to_owned(), "c".to_owned()),
+            ],
+            rows
+        );
+
+        let err = db
+            .execute_batch("CREATE VIRTUAL TABLE missing USING csv(buffer=upload)")
+            .unwrap_err();
+        assert!(err.to_string().contains("not registered"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_export_csv() {
+        use rusqlite::vtab::csvtab::{export_csv, CsvOptions};
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE foo (x INTEGER, y TEXT);
+             INSERT INTO foo VALUES (1, 'a,b'), (2, NULL), (3, 'say \"hi\"');",
+        )
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT x, y FROM foo WHERE x > ?").unwrap();
+        let csv = export_csv(&mut stmt, &[0], &CsvOptions::default()).unwrap();
+        assert_eq!(
+            "x,y\n1,\"a,b\"\n2,\n3,\"say \"\"hi\"\"\"\n",
+            String::from_utf8(csv).unwrap()
+        );
+
+        let options = CsvOptions {
+            delimiter: b'\t',
+            quote: 0,
+            header: false,
+        };
+        let csv = export_csv(&mut stmt, &[1], &options).unwrap();
+        assert_eq!("2\t\n3\tsay \"hi\"\n", String::from_utf8(csv).unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_database_create_function() {
+        use js_sys::{Function, Object, Reflect};
+        use wasm_bindgen::JsValue;
+
+        let db = ::Database::new().unwrap();
+        let options = Object::new();
+        Reflect::set(&options, &JsValue::from_str("deterministic"), &JsValue::TRUE).unwrap();
+        db.create_function(
+            "js_add",
+            2,
+            Function::new_with_args("a, b", "return a + b"),
+            Some(options),
+        )
+            .unwrap();
+        db.create_function(
+            "js_fail",
+            0,
+            Function::new_no_args("throw new Error('boom')"),
+            None,
+        )
+            .unwrap();
+
+        let conn = db.connection();
+        let sum: i64 = conn.query_row("SELECT js_add(40, 2)", NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(42, sum);
+        let text: String = conn
+            .query_row("SELECT js_add('a', 'b')", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("ab", text);
+        let half: f64 = conn.query_row("SELECT js_add(1, 0.5)", NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(1.5, half);
+
+        let err = conn
+            .query_row("SELECT js_fail()", NO_PARAMS, |r| r.get::<_, i64>(0))
+            .unwrap_err();
+        assert!(err.to_string().contains("boom"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_database_create_aggregate() {
+        use js_sys::{Function, Object, Reflect};
+        use wasm_bindgen::JsValue;
+
+        let db = ::Database::new().unwrap();
+        let aggregate = Object::new();
+        Reflect::set(&aggregate, &JsValue::from_str("init"), &Function::new_no_args("return []")).unwrap();
+        Reflect::set(
+            &aggregate,
+            &JsValue::from_str("step"),
+            &Function::new_with_args("acc, value", "acc.push(value); return acc"),
+        )
+            .unwrap();
+        Reflect::set(
+            &aggregate,
+            &JsValue::from_str("finalize"),
+            &Function::new_with_args("acc", "return acc.join('|')"),
+        )
+            .unwrap();
+        db.create_aggregate("js_join", aggregate, None).unwrap();
+
+        let conn = db.connection();
+        conn.execute_batch("CREATE TABLE foo (x TEXT); INSERT INTO foo VALUES ('a'), ('b'), ('c');")
+            .unwrap();
+        let joined: String = conn
+            .query_row("SELECT js_join(x) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("a|b|c", joined);
+        let empty: String = conn
+            .query_row("SELECT js_join(x) FROM foo WHERE 0", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!("", empty);
+
+        let missing_step = Object::new();
+        assert!(db.create_aggregate("js_bad", missing_step, None).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_function_subtype() {
+        use rusqlite::functions::FunctionFlags;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.create_scalar_function("tag", 1, FunctionFlags::SQLITE_UTF8, |ctx| {
+            ctx.set_result_subtype(42);
+            ctx.get::<i64>(0)
+        })
+            .unwrap();
+        db.create_scalar_function(
+            "subtype",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_SUBTYPE,
+            |ctx| Ok(ctx.get_subtype(0) as i64),
+        )
+            .unwrap();
+
+        let tagged: (i64, i64) = db
+            .query_row("SELECT tag(7), subtype(tag(7))", NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!((7, 42), tagged);
+        let untagged: i64 = db.query_row("SELECT subtype(7)", NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(0, untagged);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_function_pointer() {
+        use rusqlite::functions::FunctionFlags;
+        use rusqlite::types::Pointer;
+
+        struct Prefix(String);
+
+        let db = Connection::open_in_memory().unwrap();
+        db.create_scalar_function("make_prefix", 1, FunctionFlags::SQLITE_UTF8, |ctx| {
+            Ok(Pointer::new(Prefix(ctx.get(0)?), "prefix"))
+        })
+            .unwrap();
+        db.create_scalar_function("has_prefix", 2, FunctionFlags::SQLITE_UTF8, |ctx| {
+            let text = ctx.get::<String>(1)?;
+            Ok(ctx
+                .get_pointer::<Prefix>(0, "prefix")?
+                .map(|prefix| text.starts_with(&prefix.0)))
+        })
+            .unwrap();
+
+        let prefix = Pointer::new(Prefix("ab".to_owned()), "prefix");
+        let matched: Option<bool> = db
+            .query_row("SELECT has_prefix(?, 'abc')", &[&prefix], |r| r.get(0))
+            .unwrap();
+        assert_eq!(Some(true), matched);
+        let kind: String = db.query_row("SELECT typeof(?)", &[&prefix], |r| r.get(0)).unwrap();
+        assert_eq!("null", kind);
+
+        let matched: Option<bool> = db
+            .query_row("SELECT has_prefix(make_prefix('x'), 'abc')", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(Some(false), matched);
+
+        // Pointers tagged with another type name are not visible.
+        let other = Pointer::new(Prefix("ab".to_owned()), "other");
+        let matched: Option<bool> = db
+            .query_row("SELECT has_prefix(?, 'abc')", &[&other], |r| r.get(0))
+            .unwrap();
+        assert_eq!(None, matched);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_extensions_regexp() {
+        let db = Connection::open_in_memory().unwrap();
+        rusqlite::extensions::regexp::register(&db).unwrap();
+        db.execute_batch("CREATE TABLE foo (x TEXT); INSERT INTO foo VALUES ('apple'), ('banana'), (NULL);")
+            .unwrap();
+
+        let matches: Vec<String> = db
+            .prepare("SELECT x FROM foo WHERE x REGEXP '^b.n' ORDER BY x")
+            .unwrap()
+            .query_map(NO_PARAMS, |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec!["banana".to_owned()], matches);
+        let null: Option<bool> = db
+            .query_row("SELECT NULL REGEXP 'a'", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(None, null);
+        assert!(db
+            .query_row("SELECT 'a' REGEXP '('", NO_PARAMS, |r| r.get::<_, bool>(0))
+            .is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_extensions_uuid() {
+        let db = Connection::open_in_memory().unwrap();
+        rusqlite::extensions::uuid::register(&db).unwrap();
+
+        let (text, blob): (String, Vec<u8>) = db
+            .query_row("SELECT uuid4(), uuid_blob(uuid4())", NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!(36, text.len());
+        assert_eq!(16, blob.len());
+
+        let roundtrip: String = db
+            .query_row(
+                "SELECT uuid_str(uuid_blob('{A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11}'))",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11", roundtrip);
+        let invalid: Option<String> = db
+            .query_row("SELECT uuid_str('not a uuid')", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(None, invalid);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_extensions_math() {
+        let db = Connection::open_in_memory().unwrap();
+        rusqlite::extensions::math::register(&db).unwrap();
+
+        let values: (f64, f64, f64, f64, f64) = db
+            .query_row(
+                "SELECT sqrt(16), pow(2, 10), log(100), log(2, 8), degrees(pi())",
+                NO_PARAMS,
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!((4.0, 1024.0, 2.0, 3.0, 180.0), values);
+        let nulls: (Option<f64>, Option<f64>) = db
+            .query_row("SELECT sqrt(-1), cos(NULL)", NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!((None, None), nulls);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_extensions_unicase() {
+        let db = Connection::open_in_memory().unwrap();
+        rusqlite::extensions::unicase::register(&db).unwrap();
+        db.execute_batch("CREATE TABLE foo (x TEXT); INSERT INTO foo VALUES ('Straße'), ('STRASSE'), ('b');")
+            .unwrap();
+
+        let count: i64 = db
+            .query_row(
+                "SELECT count(DISTINCT x COLLATE unicase) FROM foo",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_collation_registry() {
+        let db = Connection::open_in_memory().unwrap();
+        db.register_collation("reverse", |a: &str, b: &str| b.cmp(a))
+            .unwrap();
+        assert!(db.collations().contains("REVERSE"));
+        assert_eq!(vec!["reverse".to_owned()], db.collations().names());
+        db.execute_batch("CREATE TABLE foo (x TEXT); INSERT INTO foo VALUES ('a'), ('c'), ('b');")
+            .unwrap();
+
+        let sorted = |db: &Connection| -> Vec<String> {
+            db.prepare("SELECT x FROM foo ORDER BY x COLLATE reverse")
+                .unwrap()
+                .query_map(NO_PARAMS, |r| r.get(0))
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap()
+        };
+        assert_eq!(vec!["c", "b", "a"], sorted(&db));
+
+        // re-registering replaces the installed collation
+        db.register_collation("Reverse", |a: &str, b: &str| a.cmp(b))
+            .unwrap();
+        assert_eq!(vec!["a", "b", "c"], sorted(&db));
+
+        assert!(db.unregister_collation("reverse").unwrap());
+        assert!(!db.unregister_collation("reverse").unwrap());
+        assert!(db
+            .prepare("SELECT x FROM foo ORDER BY x COLLATE reverse")
+            .is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_collation_registry_fallback() {
+        let db = Connection::open_in_memory().unwrap();
+        db.register_collation("registered", |a: &str, b: &str| a.cmp(b))
+            .unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        db.collation_needed(move |db: &Connection, name: &str| {
+            tx.send(name.to_owned()).unwrap();
+            db.create_collation(name, unicase_compare)
+        })
+        .unwrap();
+
+        db.query_row("SELECT 'a' = 'A' COLLATE registered", NO_PARAMS, |r| r.get::<_, bool>(0))
+            .unwrap();
+        let eq: bool = db
+            .query_row("SELECT 'a' = 'A' COLLATE other", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert!(eq);
+        let asked: Vec<String> = rx.try_iter().collect();
+        assert_eq!(vec!["other".to_owned()], asked);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_js_locale_collation() {
+        let db = ::Database::new().unwrap();
+        let options = js_sys::Object::new();
+        js_sys::Reflect::set(&options, &"sensitivity".into(), &"base".into()).unwrap();
+        db.create_locale_collation("de", Some(js_sys::Array::of1(&"de".into())), Some(options))
+            .unwrap();
+        let conn = db.connection();
+        conn.execute_batch("CREATE TABLE foo (x TEXT); INSERT INTO foo VALUES ('z'), ('ä'), ('a');")
+            .unwrap();
+
+        let sorted: Vec<String> = conn
+            .prepare("SELECT x FROM foo ORDER BY x COLLATE de, x")
+            .unwrap()
+            .query_map(NO_PARAMS, |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec!["a", "ä", "z"], sorted);
+        let eq: bool = conn
+            .query_row("SELECT 'a' = 'Ä' COLLATE de", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert!(eq);
+        assert!(db.remove_collation("de").unwrap());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Color {
+        Red,
+        Green,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        id: i64,
+        name: String,
+        price: Option<f64>,
+        in_stock: bool,
+        color: Color,
+        data: Vec<u8>,
+    }
+
+    fn items_db() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE item (id INTEGER, name TEXT, price REAL, in_stock INTEGER, color TEXT, data BLOB);",
+        )
+        .unwrap();
+        db
+    }
+
+    #[wasm_bindgen_test]
+    fn test_serde_roundtrip() {
+        let db = items_db();
+        let items = vec![
+            Item {
+                id: 1,
+                name: "pen".to_owned(),
+                price: Some(1.5),
+                in_stock: true,
+                color: Color::Red,
+                data: vec![1, 2, 3],
+            },
+            Item {
+                id: 2,
+                name: "ink".to_owned(),
+                price: None,
+                in_stock: false,
+                color: Color::Green,
+                data: vec![],
+            },
+        ];
+        let mut insert = db
+            .prepare("INSERT INTO item VALUES (:id, :name, :price, :in_stock, :color, :data)")
+            .unwrap();
+        for item in &items {
+            let params = to_named_params(item).unwrap();
+            insert
+                .execute_named(&params.for_statement(&insert).unwrap())
+                .unwrap();
+        }
+
+        let mut stmt = db
+            .prepare("SELECT data, color, in_stock, price, name, id FROM item ORDER BY id")
+            .unwrap();
+        let rows: Vec<Item> = stmt
+            .query_as::<Item, _>(NO_PARAMS)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(items, rows);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_serde_positional() {
+        let db = items_db();
+        let params = to_params(&(7, "cup", None::<f64>, true, Color::Green, vec![9u8])).unwrap();
+        assert_eq!(6, params.len());
+        db.execute("INSERT INTO item VALUES (?, ?, ?, ?, ?, ?)", &params)
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT id, name FROM item").unwrap();
+        let (id, name): (i64, String) = stmt
+            .query_as::<(i64, String), _>(NO_PARAMS)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!((7, "cup".to_owned()), (id, name));
+
+        let mut stmt = db.prepare("SELECT count(*) FROM item").unwrap();
+        let count: Vec<i64> = stmt
+            .query_as::<i64, _>(NO_PARAMS)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec![1], count);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_serde_errors() {
+        let db = items_db();
+        db.execute_batch("INSERT INTO item VALUES (1, 'pen', 'cheap', 1, 'Blue', NULL);")
+            .unwrap();
+
+        let mut stmt = db.prepare("SELECT id, name FROM item").unwrap();
+        let err = stmt
+            .query_as::<Item, _>(NO_PARAMS)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(Error::InvalidColumnName("in_stock".to_owned()), err);
+
+        let mut stmt = db.prepare("SELECT * FROM item").unwrap();
+        let err = stmt
+            .query_as::<Item, _>(NO_PARAMS)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        match err {
+            Error::FromSqlConversionFailure(2, types::Type::Text, _) => (),
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        let mut stmt = db
+            .prepare("INSERT INTO item (id, name) VALUES (:id, :name)")
+            .unwrap();
+        assert!(to_named_params(&(1, "pen")).is_err());
+        let mut map = std::collections::BTreeMap::new();
+        map.insert("id", 2);
+        map.insert("unused", 3);
+        let params = to_named_params(&map).unwrap();
+        assert_eq!(2, params.as_slice().len());
+        let bound = params.for_statement(&stmt).unwrap();
+        assert_eq!(1, bound.len());
+        stmt.execute_named(&bound).unwrap();
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct PartialItem {
+        id: i64,
+        price: Option<f64>,
+        #[serde(default)]
+        in_stock: bool,
+    }
+
+    #[wasm_bindgen_test]
+    fn test_serde_missing_columns() {
+        let db = items_db();
+        db.execute_batch("INSERT INTO item VALUES (1, 'pen', 1.5, 1, 'Red', NULL);")
+            .unwrap();
+        let partial: PartialItem = db
+            .query_row("SELECT id FROM item", NO_PARAMS, |r| r.deserialize())
+            .unwrap();
+        assert_eq!(
+            PartialItem {
+                id: 1,
+                price: None,
+                in_stock: false,
+            },
+            partial
+        );
+    }
+
+    #[derive(Debug, PartialEq, FromRow, ToParams, Table)]
+    #[rusqlite(table = "items")]
+    struct DerivedItem {
+        #[rusqlite(primary_key)]
+        id: i64,
+        #[rusqlite(rename = "label")]
+        name: String,
+        price: Option<f64>,
+        in_stock: bool,
+        data: Vec<u8>,
+    }
+
+    #[derive(Debug, PartialEq, FromRow)]
+    struct Pair(i64, String);
+
+    #[wasm_bindgen_test]
+    fn test_derive_table() {
+        assert_eq!("items", DerivedItem::NAME);
+        assert_eq!(
+            &["id", "label", "price", "in_stock", "data"],
+            DerivedItem::COLUMNS
+        );
+        assert_eq!(
+            "CREATE TABLE \"items\" (\"id\" INTEGER PRIMARY KEY NOT NULL, \
+             \"label\" TEXT NOT NULL, \"price\" REAL, \"in_stock\" INTEGER NOT NULL, \
+             \"data\" BLOB NOT NULL)",
+            DerivedItem::create_table_sql()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_derive_roundtrip() {
+        use std::convert::TryFrom;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(&DerivedItem::create_table_sql()).unwrap();
+        let item = DerivedItem {
+            id: 1,
+            name: "pen".to_owned(),
+            price: None,
+            in_stock: true,
+            data: vec![1, 2],
+        };
+        db.execute_named(
+            "INSERT INTO items VALUES (:id, :label, :price, :in_stock, :data)",
+            &item.to_named_params(),
+        )
+        .unwrap();
+        let other = DerivedItem {
+            id: 2,
+            name: "ink".to_owned(),
+            price: Some(2.5),
+            in_stock: false,
+            data: vec![],
+        };
+        db.execute("INSERT INTO items VALUES (?, ?, ?, ?, ?)", &other.to_params())
+            .unwrap();
+
+        let mut stmt = db
+            .prepare("SELECT data, in_stock, price, label, id FROM items ORDER BY id")
+            .unwrap();
+        let items: Vec<DerivedItem> = stmt
+            .query_map(NO_PARAMS, |row| DerivedItem::try_from(row))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(vec![item, other], items);
+
+        let pair = db
+            .query_row("SELECT id, label FROM items WHERE id = 2", NO_PARAMS, |row| {
+                Pair::try_from(row)
+            })
+            .unwrap();
+        assert_eq!(Pair(2, "ink".to_owned()), pair);
+        assert_eq!(
+            Error::InvalidColumnName("label".to_owned()),
+            db.query_row("SELECT id FROM items WHERE id = 1", NO_PARAMS, |row| {
+                DerivedItem::try_from(row)
+            })
+            .unwrap_err()
+        );
+    }
+
+    fn migration_steps<'a>() -> Vec<migrations::M<'a>> {
+        vec![
+            migrations::M::up("CREATE TABLE parent (id INTEGER PRIMARY KEY);")
+                .down("DROP TABLE parent;"),
+            migrations::M::up(
+                "CREATE TABLE child (id INTEGER PRIMARY KEY, parent_id INTEGER REFERENCES parent(id));",
+            )
+            .down("DROP TABLE child;"),
+            migrations::M::up_with(|conn| {
+                conn.execute("INSERT INTO parent (id) VALUES (?)", &[1])?;
+                Ok(())
+            }),
+        ]
+    }
+
+    #[wasm_bindgen_test]
+    fn test_migrations_up_and_down() {
+        let mut db = Connection::open_in_memory().unwrap();
+        let migrations = migrations::Migrations::new(migration_steps());
+        assert_eq!(0, migrations.current_version(&db).unwrap());
+
+        migrations.to_latest(&mut db).unwrap();
+        assert_eq!(3, migrations.current_version(&db).unwrap());
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM parent", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(1, count);
+        // running again is a no-op
+        migrations.to_latest(&mut db).unwrap();
+
+        // step 3 has no down migration
+        match migrations.to_version(&mut db, 1).unwrap_err() {
+            Error::MigrationFailed(3, _) => (),
+            err => panic!("unexpected error {:?}", err),
+        }
+        assert_eq!(3, migrations.current_version(&db).unwrap());
+
+        let migrations = migrations::Migrations::new(
+            migration_steps().into_iter().take(2).collect(),
+        );
+        assert!(migrations.to_latest(&mut db).is_err());
+        db.pragma_update(None, "user_version", &2).unwrap();
+        migrations.to_version(&mut db, 0).unwrap();
+        assert_eq!(0, migrations.current_version(&db).unwrap());
+        assert!(db.prepare("SELECT * FROM parent").is_err());
+
+        db.pragma_update(None, "user_version", &-1).unwrap();
+        assert!(migrations.current_version(&db).is_err());
+        assert!(migrations.to_latest(&mut db).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_migrations_failure_rolls_back() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.pragma_update(None, "foreign_keys", &true).unwrap();
+        let mut steps = migration_steps();
+        steps.push(migrations::M::up(
+            "CREATE TABLE broken (x INTEGER); INSERT INTO nowhere VALUES (1);",
+        ));
+        let migrations = migrations::Migrations::new(steps);
+
+        match migrations.to_latest(&mut db).unwrap_err() {
+            Error::MigrationFailed(4, err) => match *err {
+                Error::SqliteFailure(_, Some(ref msg)) => assert!(msg.contains("nowhere")),
+                ref err => panic!("unexpected error {:?}", err),
+            },
+            err => panic!("unexpected error {:?}", err),
+        }
+        assert_eq!(3, migrations.current_version(&db).unwrap());
+        assert!(db.prepare("SELECT * FROM broken").is_err());
+        let foreign_keys: bool = db
+            .pragma_query_value(None, "foreign_keys", |r| r.get(0))
+            .unwrap();
+        assert!(foreign_keys);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_migrations_foreign_key_check() {
+        let mut db = Connection::open_in_memory().unwrap();
+        let mut steps = migration_steps();
+        steps.push(migrations::M::up(
+            "INSERT INTO child (id, parent_id) VALUES (1, 42);",
+        ));
+        let migrations = migrations::Migrations::new(steps);
+
+        match migrations.to_latest(&mut db).unwrap_err() {
+            Error::MigrationFailed(4, err) => match *err {
+                Error::SqliteFailure(e, Some(ref msg)) => {
+                    assert_eq!(ErrorCode::ConstraintViolation, e.code);
+                    assert!(msg.contains("child row 1"), "{}", msg);
+                }
+                ref err => panic!("unexpected error {:?}", err),
+            },
+            err => panic!("unexpected error {:?}", err),
+        }
+        let children: i64 = db
+            .query_row("SELECT count(*) FROM child", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(0, children);
+        assert_eq!(3, migrations.current_version(&db).unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_schema() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE parent (id INTEGER PRIMARY KEY AUTOINCREMENT, a TEXT, b TEXT, UNIQUE (a, b));
+             CREATE TABLE child (
+                 id INTEGER NOT NULL PRIMARY KEY,
+                 name TEXT COLLATE NOCASE DEFAULT 'x',
+                 pa TEXT, pb TEXT,
+                 FOREIGN KEY (pa, pb) REFERENCES parent (a, b) ON DELETE CASCADE
+             );
+             CREATE INDEX child_name ON child (name) WHERE name IS NOT NULL;
+             CREATE VIEW names AS SELECT name FROM child;
+             CREATE TRIGGER child_insert AFTER INSERT ON child BEGIN SELECT 1; END;",
+        )
+        .unwrap();
+
+        let schema = db.schema().unwrap();
+        let tables: Vec<&str> = schema.tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(vec!["child", "parent"], tables);
+        assert_eq!("names", schema.views[0].name);
+        assert_eq!(vec!["name"], schema.views[0].columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>());
+        assert_eq!(1, schema.triggers.len());
+        assert_eq!("child", schema.triggers[0].table);
+
+        let child = &schema.tables[0];
+        assert_eq!(
+            schema::ColumnInfo {
+                name: "name".to_owned(),
+                decl_type: Some("TEXT".to_owned()),
+                not_null: false,
+                default: Some("'x'".to_owned()),
+                primary_key: 0,
+                collation: Some("NOCASE".to_owned()),
+                auto_increment: false,
+            },
+            child.columns[1]
+        );
+        assert!(child.columns[0].not_null);
+        assert_eq!(1, child.columns[0].primary_key);
+        assert_eq!(1, child.indexes.len());
+        assert_eq!("child_name", child.indexes[0].name);
+        assert!(child.indexes[0].partial);
+        assert_eq!(vec![Some("name".to_owned())], child.indexes[0].columns);
+        assert_eq!(
+            vec![schema::ForeignKeyInfo {
+                table: "parent".to_owned(),
+                from: vec!["pa".to_owned(), "pb".to_owned()],
+                to: vec![Some("a".to_owned()), Some("b".to_owned())],
+                on_update: "NO ACTION".to_owned(),
+                on_delete: "CASCADE".to_owned(),
+            }],
+            child.foreign_keys
+        );
+
+        let parent = db.table_info("parent").unwrap().unwrap();
+        assert!(parent.columns[0].auto_increment);
+        assert_eq!("u", parent.indexes[0].origin);
+        assert!(parent.indexes[0].unique);
+        assert_eq!(None, db.table_info("missing").unwrap());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_js_schema() {
+        let db = ::Database::new().unwrap();
+        db.exec("CREATE TABLE foo (id INTEGER PRIMARY KEY, name TEXT NOT NULL);")
+            .unwrap();
+        let schema = db.schema().unwrap();
+        let json = js_sys::JSON::stringify(&schema).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&String::from(json)).unwrap();
+        assert_eq!("foo", json["tables"][0]["name"]);
+        assert_eq!(true, json["tables"][0]["columns"][1]["notNull"]);
+        assert_eq!("TEXT", json["tables"][0]["columns"][1]["declType"]);
+    }
+    #[wasm_bindgen_test]
+    fn test_constraint_violation() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             CREATE TABLE parent (id INTEGER PRIMARY KEY);
+             CREATE TABLE person (
+                 id INTEGER PRIMARY KEY,
+                 first TEXT NOT NULL,
+                 last TEXT,
+                 age INTEGER CONSTRAINT adult CHECK (age >= 18),
+                 parent_id INTEGER REFERENCES parent (id),
+                 UNIQUE (first, last)
+             );
+             INSERT INTO person (id, first, last) VALUES (1, 'a', 'b');",
+        )
+        .unwrap();
+
+        let err = db
+            .execute("INSERT INTO person (first, last) VALUES ('a', 'b')", NO_PARAMS)
+            .unwrap_err();
+        assert_eq!(
+            Some(ConstraintViolation {
+                kind: ConstraintKind::Unique,
+                table: Some("person".to_owned()),
+                columns: vec!["first".to_owned(), "last".to_owned()],
+                name: None,
+            }),
+            err.constraint()
+        );
+
+        let err = db
+            .execute("INSERT INTO person (id, first) VALUES (1, 'c')", NO_PARAMS)
+            .unwrap_err();
+        let violation = err.constraint().unwrap();
+        assert_eq!(ConstraintKind::PrimaryKey, violation.kind);
+        assert_eq!(vec!["id".to_owned()], violation.columns);
+
+        let err = db
+            .execute("INSERT INTO person (last) VALUES ('c')", NO_PARAMS)
+            .unwrap_err();
+        let violation = err.constraint().unwrap();
+        assert_eq!(ConstraintKind::NotNull, violation.kind);
+        assert_eq!(Some("person".to_owned()), violation.table);
+        assert_eq!(vec!["first".to_owned()], violation.columns);
+
+        let err = db
+            .execute("INSERT INTO person (first, age) VALUES ('c', 3)", NO_PARAMS)
+            .unwrap_err();
+        let violation = err.constraint().unwrap();
+        assert_eq!(ConstraintKind::Check, violation.kind);
+        assert_eq!(Some("adult".to_owned()), violation.name);
+
+        let err = db
+            .execute("INSERT INTO person (first, parent_id) VALUES ('c', 9)", NO_PARAMS)
+            .unwrap_err();
+        assert_eq!(ConstraintKind::ForeignKey, err.constraint().unwrap().kind);
+
+        assert_eq!(None, Error::QueryReturnedNoRows.constraint());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_sql_input_error_offset() {
+        let db = Connection::open_in_memory().unwrap();
+        let sql = "SELECT 1 FROM FROM";
+        match db.prepare(sql).unwrap_err() {
+            Error::SqlInputError {
+                error,
+                msg,
+                sql: err_sql,
+                offset,
+            } => {
+                assert_eq!(ErrorCode::Unknown, error.code);
+                assert_eq!("near \"FROM\": syntax error", msg);
+                assert_eq!(sql, err_sql);
+                // the second FROM, not the first
+                assert_eq!(14, offset);
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        match db.execute_batch("SELECT 1; SELECT 1 FROM FROM").unwrap_err() {
+            Error::SqlInputError { offset, .. } => assert_eq!(24, offset),
+            err => panic!("unexpected error {:?}", err),
+        }
+        match db.execute_batch("SELECT (1").unwrap_err() {
+            Error::SqlInputError { offset, .. } => assert_eq!(9, offset),
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        // not a syntax error, so there is no offset
+        match db.prepare("SELECT * FROM missing").unwrap_err() {
+            Error::SqliteFailure(_, Some(msg)) => assert!(msg.contains("missing")),
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_js_error_classes() {
+        use js_sys::{Function, Reflect};
+        use wasm_bindgen::{JsCast, JsValue};
+
+        let db = ::Database::new().unwrap();
+        db.exec("CREATE TABLE foo (id INTEGER PRIMARY KEY, name TEXT UNIQUE);")
+            .unwrap();
+        db.exec("INSERT INTO foo (name) VALUES ('a');").unwrap();
+
+        let classes = ::error_classes();
+        let instance_of = Function::new_with_args("e, c", "return e instanceof c");
+        let is_a = |err: &JsValue, name: &str| {
+            let class = Reflect::get(&classes, &JsValue::from_str(name)).unwrap();
+            instance_of.call2(&JsValue::NULL, err, &class).unwrap() == JsValue::TRUE
+        };
+        let get = |value: &JsValue, key: &str| Reflect::get(value, &JsValue::from_str(key)).unwrap();
+
+        let err = db.exec("INSERT INTO foo (name) VALUES ('a');").unwrap_err();
+        assert!(err.is_instance_of::<js_sys::Error>());
+        assert!(is_a(&err, "SqliteError"));
+        assert!(is_a(&err, "ConstraintError"));
+        assert!(is_a(&err, "UniqueConstraintError"));
+        assert!(!is_a(&err, "SqlSyntaxError"));
+        assert_eq!(Some("UniqueConstraintError".to_owned()), get(&err, "name").as_string());
+        assert_eq!(Some("ConstraintViolation".to_owned()), get(&err, "code").as_string());
+        let constraint = get(&err, "constraint");
+        assert_eq!(Some("Unique".to_owned()), get(&constraint, "kind").as_string());
+        assert_eq!(Some("foo".to_owned()), get(&constraint, "table").as_string());
+
+        let err = db.exec("SELEC 1").unwrap_err();
+        assert!(is_a(&err, "SqlSyntaxError"));
+        assert_eq!(Some(0.0), get(&err, "offset").as_f64());
+    }
+    #[wasm_bindgen_test]
+    fn test_error_into_js_value() {
+        use js_sys::Reflect;
+        use wasm_bindgen::{JsCast, JsValue};
+
+        let get = |value: &JsValue, key: &str| Reflect::get(value, &JsValue::from_str(key)).unwrap();
+
+        let err = JsValue::from(Error::QueryReturnedNoRows);
+        assert!(err.is_instance_of::<js_sys::Error>());
+        assert_eq!(Some("SqliteError".to_owned()), get(&err, "name").as_string());
+        assert_eq!(Some("Query returned no rows".to_owned()), get(&err, "message").as_string());
+        assert!(get(&err, "code").is_null());
+        assert!(get(&err, "sql").is_null());
+
+        let db = ::Database::new().unwrap();
+        let err = db.exec("SELECT * FROM missing").unwrap_err();
+        assert_eq!(Some("Unknown".to_owned()), get(&err, "code").as_string());
+        assert_eq!(Some(f64::from(ffi::SQLITE_ERROR)), get(&err, "extendedCode").as_f64());
+        assert_eq!(Some("SELECT * FROM missing".to_owned()), get(&err, "sql").as_string());
+        assert_eq!(Some("no such table: missing".to_owned()), get(&err, "message").as_string());
+        assert!(get(&err, "stack").is_string());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_start() {
+        assert!(::start().is_ok());
+    }
+    #[wasm_bindgen_test]
+    fn test_database_open_blob() {
+        use js_sys::Reflect;
+        use wasm_bindgen::{JsCast, JsValue};
+
+        let db = ::Database::new().unwrap();
+        db.exec("CREATE TABLE attachment (id INTEGER PRIMARY KEY, data BLOB);
+                 INSERT INTO attachment (id, data) VALUES (1, zeroblob(6));")
+            .unwrap();
+
+        let writer = db.open_blob("attachment", "data", 1.0, false).unwrap();
+        assert_eq!(6, writer.size().unwrap());
+        writer.write(b"abc").unwrap();
+        writer.write(b"def").unwrap();
+        let err = writer.write(b"g").unwrap_err();
+        assert!(err.is_instance_of::<js_sys::RangeError>());
+        writer.close().unwrap();
+        assert!(writer.write(b"abc").is_err());
+
+        let reader = db.open_blob("attachment", "data", 1.0, true).unwrap();
+        assert_eq!(b"abcd".to_vec(), reader.read(Some(4)).unwrap().unwrap().to_vec());
+        assert_eq!(b"ef".to_vec(), reader.read(None).unwrap().unwrap().to_vec());
+        assert!(reader.read(None).unwrap().is_none());
+        reader.seek(1).unwrap();
+        assert_eq!(b"bc".to_vec(), reader.read(Some(2)).unwrap().unwrap().to_vec());
+        assert!(reader.write(b"x").is_err());
+        assert!(reader.reopen(1.5).is_err());
+
+        let err = db.open_blob("attachment", "data", 2.0, true).err().unwrap();
+        assert_eq!(
+            Some("SqliteError".to_owned()),
+            Reflect::get(&err, &JsValue::from_str("name")).unwrap().as_string()
+        );
+
+        let has_streams = !Reflect::get(&js_sys::global(), &JsValue::from_str("ReadableStream"))
+            .unwrap()
+            .is_undefined();
+        if has_streams {
+            let reader = db.open_blob("attachment", "data", 1.0, true).unwrap();
+            let stream = reader.readable(Some(4)).unwrap();
+            assert!(Reflect::get(&stream, &JsValue::from_str("getReader"))
+                .unwrap()
+                .is_function());
+            let writer = db.open_blob("attachment", "data", 1.0, false).unwrap();
+            let stream = writer.writable().unwrap();
+            assert!(Reflect::get(&stream, &JsValue::from_str("getWriter"))
+                .unwrap()
+                .is_function());
+        }
+    }
+    #[wasm_bindgen_test]
+    fn test_blobstore() {
+        use rusqlite::blobstore::{BlobId, BlobStore};
+        use std::io::{Read, Seek, SeekFrom};
+
+        let db = Connection::open_in_memory().unwrap();
+        let store = BlobStore::with_chunk_size(&db, 4).unwrap();
+        let a = store.put(&b"abcdefghij"[..]).unwrap();
+        let b = store.put(&b"abcdXXXXij"[..]).unwrap();
+        let empty = store.put(&b""[..]).unwrap();
+        let chunk_count = |db: &Connection| -> i64 {
+            db.query_row("SELECT count(*) FROM blobstore_chunk", NO_PARAMS, |r| r.get(0))
+                .unwrap()
+        };
+        // "abcd" and "ij" are shared
+        assert_eq!(4, chunk_count(&db));
+        assert_eq!(10, store.size(a).unwrap());
+        assert_eq!(0, store.size(empty).unwrap());
+
+        let mut data = Vec::new();
+        store.get(b).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(b"abcdXXXXij".to_vec(), data);
+
+        let mut reader = store.get(a).unwrap();
+        reader.seek(SeekFrom::Start(3)).unwrap();
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(b"defg", &buf);
+        reader.seek(SeekFrom::End(-1)).unwrap();
+        data.clear();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(b"j".to_vec(), data);
+
+        assert!(store.delete(a).unwrap());
+        assert!(!store.delete(a).unwrap());
+        assert_eq!(Err(Error::QueryReturnedNoRows), store.get(a).map(|_| ()));
+        assert_eq!(1, store.gc().unwrap());
+        assert_eq!(3, chunk_count(&db));
+        data.clear();
+        store.get(b).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(b"abcdXXXXij".to_vec(), data);
+
+        // chunks are addressed by their SHA-256
+        let abc = BlobStore::new(&db).unwrap().put(&b"abc"[..]).unwrap();
+        assert_ne!(BlobId(0), abc);
+        let hash: String = db
+            .query_row(
+                "SELECT lower(hex(hash)) FROM blobstore_chunk WHERE length(data) = 3",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            hash
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_blobstore_put_failure() {
+        use rusqlite::blobstore::BlobStore;
+        use std::io;
+
+        struct Failing(usize);
+        impl io::Read for Failing {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0 == 0 {
+                    return Err(io::Error::new(io::ErrorKind::Other, "disconnected"));
+                }
+                self.0 -= 1;
+                buf[0] = b'x';
+                Ok(1)
+            }
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+        let store = BlobStore::with_chunk_size(&db, 2).unwrap();
+        match store.put(Failing(5)).unwrap_err() {
+            Error::ToSqlConversionFailure(err) => assert_eq!("disconnected", err.to_string()),
+            err => panic!("unexpected error {:?}", err),
+        }
+        let rows: i64 = db
+            .query_row(
+                "SELECT (SELECT count(*) FROM blobstore_blob) + (SELECT count(*) FROM blobstore_chunk)",
+                NO_PARAMS,
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(0, rows);
+        assert!(db.is_autocommit());
+    }
+    #[wasm_bindgen_test]
+    fn test_transaction_with_retry() {
+        use std::time::Duration;
+
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE foo (x INTEGER)").unwrap();
+        let policy = RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(1));
+        assert_eq!(policy.backoff(5), Duration::from_millis(2));
+        assert_eq!(policy.backoff(40), Duration::from_millis(2));
+
+        // wasm32 cannot sleep between attempts, so busy errors are returned
+        // at once there
+        let retries = if cfg!(target_arch = "wasm32") { 0 } else { 3 };
+        let busy = || Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_BUSY), None);
+        let mut attempts = 0;
+        let n = db
+            .transaction_with_retry(TransactionBehavior::Immediate, policy, |tx| {
+                attempts += 1;
+                tx.execute("INSERT INTO foo VALUES (1)", NO_PARAMS)?;
+                if attempts < 3 && retries > 0 {
+                    return Err(busy());
+                }
+                let name = SavepointName::new("inner").unwrap();
+                let sp = tx.savepoint_named(&name)?;
+                assert_eq!(sp.name(), "inner");
+                sp.execute("INSERT INTO foo VALUES (2)", NO_PARAMS)?;
+                sp.commit()?;
+                Ok(attempts)
+            })
+            .unwrap();
+        assert_eq!(n, if retries > 0 { 3 } else { 1 });
+        assert!(db.is_autocommit());
+        let sum: i64 = db
+            .query_row("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(sum, 3);
+
+        let mut attempts = 0;
+        let err = db
+            .transaction_with_retry(TransactionBehavior::Deferred, policy, |_| -> Result<()> {
+                attempts += 1;
+                Err(busy())
+            })
+            .unwrap_err();
+        assert_eq!(attempts, retries + 1);
+        match err {
+            Error::SqliteFailure(e, _) => assert_eq!(e.code, ErrorCode::DatabaseBusy),
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        let mut attempts = 0;
+        db.transaction_with_retry(TransactionBehavior::Deferred, policy, |_| -> Result<()> {
+            attempts += 1;
+            Err(Error::QueryReturnedNoRows)
+        })
+        .unwrap_err();
+        assert_eq!(attempts, 1);
+
+        assert!(SavepointName::new("_sp1").is_ok());
+        assert!(SavepointName::new("1sp").is_err());
+        assert!(SavepointName::new("sp; DROP TABLE foo").is_err());
+        assert!(SavepointName::new("").is_err());
+    }
+
+    // edition 2015 has no `async fn`, so the promise tests return futures
+    #[wasm_bindgen_test(async)]
+    fn test_database_transaction() -> impl std::future::Future<Output = ()> {
+        use futures::future::join3;
+        use futures::FutureExt;
+        use js_sys::{Function, Promise};
+        use std::rc::Rc;
+        use wasm_bindgen::prelude::{Closure, JsValue};
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+
+        let db = Rc::new(::Database::new().unwrap());
+        db.exec("CREATE TABLE foo (x INTEGER)").unwrap();
+
+        let thrown = db.transaction(Function::new_no_args("throw new Error('boom')"), None);
+        assert!(db.connection().is_autocommit());
+
+        // The transaction stays open until the returned promise settles.
+        let inner = Rc::clone(&db);
+        let insert = Closure::once_into_js(move || {
+            inner.exec("INSERT INTO foo VALUES (1)").unwrap();
+            Promise::resolve(&JsValue::from_f64(42.0))
+        });
+        let committed = db.transaction(insert.unchecked_into(), None);
+        assert!(!db.connection().is_autocommit());
+        let concurrent = db.transaction(Function::new_no_args("return 1"), None);
+
+        join3(
+            JsFuture::from(thrown),
+            JsFuture::from(committed),
+            JsFuture::from(concurrent),
+        )
+        .map(move |(thrown, committed, concurrent)| {
+            let thrown: js_sys::Error = thrown.unwrap_err().unchecked_into();
+            assert_eq!(String::from(thrown.message()), "boom");
+            assert_eq!(committed.unwrap().as_f64(), Some(42.0));
+            let concurrent: js_sys::Error = concurrent.unwrap_err().unchecked_into();
+            assert_eq!(
+                String::from(concurrent.message()),
+                "a transaction is already pending"
+            );
+            assert!(db.connection().is_autocommit());
+            let n: i64 = db
+                .connection()
+                .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap();
+            assert_eq!(n, 1);
+        })
+    }
+
+    #[wasm_bindgen_test(async)]
+    fn test_database_transaction_savepoint() -> impl std::future::Future<Output = ()> {
+        use futures::FutureExt;
+        use js_sys::Promise;
+        use std::rc::Rc;
+        use wasm_bindgen::prelude::{Closure, JsValue};
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+
+        let db = Rc::new(::Database::new().unwrap());
+        db.exec("CREATE TABLE foo (x INTEGER); BEGIN; INSERT INTO foo VALUES (1)")
+            .unwrap();
+
+        let inner = Rc::clone(&db);
+        let insert = Closure::once_into_js(move || {
+            inner.exec("INSERT INTO foo VALUES (2)").unwrap();
+            Promise::reject(&JsValue::from_str("undo"))
+        });
+        let rolled_back = db.transaction(insert.unchecked_into(), None);
+
+        JsFuture::from(rolled_back).map(move |result| {
+            assert_eq!(result.unwrap_err().as_string(), Some("undo".to_owned()));
+            // Only the savepoint is rolled back; the outer transaction is
+            // still open.
+            assert!(!db.connection().is_autocommit());
+            db.exec("COMMIT").unwrap();
+            let sum: i64 = db
+                .connection()
+                .query_row("SELECT SUM(x) FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap();
+            assert_eq!(sum, 1);
+        })
+    }
+
+    #[wasm_bindgen_test(async)]
+    fn test_database_transaction_retry() -> impl std::future::Future<Output = ()> {
+        use futures::FutureExt;
+        use js_sys::{Object, Promise, Reflect};
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use wasm_bindgen::prelude::{Closure, JsValue};
+        use wasm_bindgen::JsCast;
+        use wasm_bindgen_futures::JsFuture;
+
+        let db = Rc::new(::Database::new().unwrap());
+        db.exec("CREATE TABLE foo (x INTEGER)").unwrap();
+
+        // Fail twice with a busy error; each attempt is rolled back.
+        let attempts = Rc::new(Cell::new(0));
+        let (inner, counter) = (Rc::clone(&db), Rc::clone(&attempts));
+        let f = Closure::wrap(Box::new(move || -> Promise {
+            inner.exec("INSERT INTO foo VALUES (1)").unwrap();
+            counter.set(counter.get() + 1);
+            if counter.get() < 3 {
+                let busy = Object::new();
+                Reflect::set(&busy, &"code".into(), &"DatabaseBusy".into()).unwrap();
+                return Promise::reject(&busy);
+            }
+            Promise::resolve(&JsValue::from_f64(7.0))
+        }) as Box<dyn FnMut() -> Promise>)
+        .into_js_value();
+
+        let options = Object::new();
+        Reflect::set(&options, &"maxRetries".into(), &3.into()).unwrap();
+        Reflect::set(&options, &"initialBackoffMs".into(), &1.into()).unwrap();
+        Reflect::set(&options, &"maxBackoffMs".into(), &2.into()).unwrap();
+        let committed = db.transaction(f.unchecked_into(), Some(options));
+
+        JsFuture::from(committed).map(move |result| {
+            assert_eq!(result.unwrap().as_f64(), Some(7.0));
+            assert_eq!(attempts.get(), 3);
+            assert!(db.connection().is_autocommit());
+            let n: i64 = db
+                .connection()
+                .query_row("SELECT count(*) FROM foo", NO_PARAMS, |r| r.get(0))
+                .unwrap();
+            assert_eq!(n, 1);
+        })
+    }
+    #[wasm_bindgen_test]
+    fn test_normalize_sql() {
+        assert_eq!(normalize_sql("SELECT 1"), "SELECT 1");
+        assert_eq!(normalize_sql("  SELECT\n\t1 ;; "), "SELECT 1");
+        assert_eq!(
+            normalize_sql("SELECT  'a  b', \"c  d\"  FROM [e  f]"),
+            "SELECT 'a  b', \"c  d\" FROM [e  f]"
+        );
+        assert_eq!(normalize_sql("SELECT 'it''s  ok'"), "SELECT 'it''s  ok'");
+        assert_eq!(
+            normalize_sql("SELECT 1 -- one\n   + 1"),
+            "SELECT 1 -- one\n+ 1"
+        );
+        assert_eq!(normalize_sql("SELECT /*  x  */  1"), "SELECT /*  x  */ 1");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_cache_stats() {
+        let db = Connection::open_in_memory().unwrap();
+        db.set_prepared_statement_cache_capacity(1);
+
+        db.prepare_cached("SELECT 1").unwrap();
+        db.prepare_cached("SELECT 1").unwrap();
+        db.prepare_cached("SELECT  1").unwrap();
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 2, 1));
+        assert_eq!(stats.cached, 1);
+
+        db.set_prepared_statement_cache_normalization(true);
+        db.prepare_cached("SELECT\n 1;").unwrap();
+        db.prepare_cached("SELECT   1").unwrap();
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 3, 2));
+
+        db.reset_prepared_statement_cache_stats();
+        db.set_prepared_statement_cache_capacity(0);
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (0, 0, 1));
+        assert_eq!(stats.cached, 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_cache_pin() {
+        let db = Connection::open_in_memory().unwrap();
+        db.set_prepared_statement_cache_capacity(1);
+        db.pin_prepared_statement("SELECT 1").unwrap();
+        assert!(db.pin_prepared_statement("SELECT FROM").is_err());
+
+        for _ in 0..3 {
+            db.prepare_cached("SELECT 1").unwrap();
+            db.prepare_cached("SELECT 2").unwrap();
+            db.prepare_cached("SELECT 3").unwrap();
+        }
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!(stats.hits, 3);
+        assert_eq!(stats.pinned, 1);
+        assert_eq!(stats.cached, 1);
+
+        assert!(db.unpin_prepared_statement("SELECT 1"));
+        assert!(!db.unpin_prepared_statement("SELECT 1"));
+        let stats = db.prepared_statement_cache_stats();
+        assert_eq!(stats.pinned, 0);
+        assert_eq!(stats.cached, 1);
+        db.prepare_cached("SELECT 1").unwrap();
+        assert_eq!(db.prepared_statement_cache_stats().hits, 4);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_database_cache_stats() {
+        use js_sys::Reflect;
+        use wasm_bindgen::JsValue;
+
+        let db = ::Database::new().unwrap();
+        db.exec("CREATE TABLE foo (x INTEGER)").unwrap();
+        db.pin_statement("SELECT x FROM foo").unwrap();
+        db.query_arrow("SELECT x\n  FROM foo;", None, None).unwrap();
+        db.query_arrow("SELECT x FROM foo WHERE x > 0", None, None).unwrap();
+        assert!(db.unpin_statement("SELECT x FROM foo").unwrap());
+
+        let stats = db.cache_stats().unwrap();
+        let get = |key: &str| Reflect::get(&stats, &JsValue::from_str(key)).unwrap().as_f64();
+        assert_eq!(get("hits"), Some(1.0));
+        assert_eq!(get("misses"), Some(1.0));
+        assert_eq!(get("pinned"), Some(0.0));
+        assert!(get("prepareTimeMs").unwrap() >= 0.0);
+
+        db.set_cache_normalization(false);
+        db.query_arrow("SELECT x\n  FROM foo;", None, None).unwrap();
+        let stats = db.cache_stats().unwrap();
+        let get = |key: &str| Reflect::get(&stats, &JsValue::from_str(key)).unwrap().as_f64();
+        assert_eq!(get("misses"), Some(2.0));
+    }
+    #[wasm_bindgen_test]
+    fn test_execute_script() {
+        use rusqlite::types::Value;
+
+        let db = Connection::open_in_memory().unwrap();
+        let script = "CREATE TABLE foo (x INTEGER PRIMARY KEY, y TEXT);\n\
+                      INSERT INTO foo (y) VALUES ('a'), ('b'); -- two rows\n\
+                      SELECT x, y FROM foo ORDER BY x;\n\
+                      /* trailing comment */ ";
+        let results = db.execute_script(script).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            &script[results[0].span.clone()],
+            "CREATE TABLE foo (x INTEGER PRIMARY KEY, y TEXT);"
+        );
+        assert_eq!(
+            &script[results[1].span.clone()],
+            "INSERT INTO foo (y) VALUES ('a'), ('b');"
+        );
+        assert_eq!(results[1].changes, 2);
+        assert_eq!(results[1].last_insert_rowid, 2);
+        assert!(results[1].columns.is_empty());
+        assert_eq!(results[2].columns, vec!["x", "y"]);
+        assert_eq!(results[2].changes, 0);
+        assert_eq!(
+            results[2].rows,
+            vec![
+                vec![Value::Integer(1), Value::Text("a".to_owned())],
+                vec![Value::Integer(2), Value::Text("b".to_owned())],
+            ]
+        );
+
+        assert!(db.execute_script("  -- nothing\n").unwrap().is_empty());
+
+        // CREATE does not repeat the count of the INSERT before it.
+        let results = db
+            .execute_script("INSERT INTO foo (y) VALUES ('c'); CREATE TABLE bar (z);")
+            .unwrap();
+        assert_eq!(results[0].changes, 1);
+        assert_eq!(results[1].changes, 0);
+
+        match db.execute_script("DELETE FROM foo; INSERT INTO foo VALUES (1, 'a'), (1, 'b'); SELECT 1") {
+            Err(Error::ScriptFailed(1, err)) => {
+                assert_eq!(err.constraint().unwrap().kind, ConstraintKind::PrimaryKey)
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let n: i64 = db
+            .query_row("SELECT COUNT(*) FROM foo", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(n, 0);
+
+        match db.execute_script("SELECT 1; SELECT FROM foo") {
+            Err(Error::ScriptFailed(1, err)) => match *err {
+                Error::SqlInputError { offset, ref sql, .. } => {
+                    assert_eq!(offset, 17);
+                    assert_eq!(sql, "SELECT 1; SELECT FROM foo");
+                }
+                ref err => panic!("unexpected error: {:?}", err),
+            },
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_database_execute_script() {
+        use js_sys::{Array, Reflect};
+        use wasm_bindgen::{JsCast, JsValue};
+
+        let db = ::Database::new().unwrap();
+        let results = db
+            .execute_script("CREATE TABLE \u{e9}t\u{e9} (x); INSERT INTO \u{e9}t\u{e9} VALUES (7); SELECT x FROM \u{e9}t\u{e9}")
+            .unwrap();
+        assert_eq!(results.length(), 3);
+        let get = |i: u32, key: &str| Reflect::get(&results.get(i), &JsValue::from_str(key)).unwrap();
+        assert_eq!(get(1, "start").as_f64(), Some(22.0));
+        assert_eq!(get(1, "changes").as_f64(), Some(1.0));
+        assert_eq!(get(1, "lastInsertRowid").as_f64(), Some(1.0));
+        let rows: Array = get(2, "rows").dyn_into().unwrap();
+        let row: Array = rows.get(0).dyn_into().unwrap();
+        assert_eq!(row.get(0).as_f64(), Some(7.0));
+
+        let err = db.execute_script("SELECT 1; SELECT x FROM nowhere").unwrap_err();
+        assert_eq!(
+            Reflect::get(&err, &JsValue::from_str("statementIndex")).unwrap().as_f64(),
+            Some(1.0)
+        );
+    }
+    #[wasm_bindgen_test]
+    fn test_format_rows() {
+        use format::{format_rows, Mode};
+        use rusqlite::types::Value;
+
+        let columns = vec!["id".to_owned(), "name".to_owned()];
+        let rows = vec![
+            vec![Value::Integer(1), Value::Text("a, \"b\"".to_owned())],
+            vec![Value::Integer(22), Value::Null],
+        ];
+        assert_eq!(format_rows(Mode::List, false, &columns, &rows), "1|a, \"b\"\n22|\n");
+        assert_eq!(
+            format_rows(Mode::List, true, &columns, &rows),
+            "id|name\n1|a, \"b\"\n22|\n"
+        );
+        assert_eq!(
+            format_rows(Mode::Csv, true, &columns, &rows),
+            "id,name\r\n1,\"a, \"\"b\"\"\"\r\n22,\r\n"
+        );
+        assert_eq!(
+            format_rows(Mode::Json, false, &columns, &rows),
+            "[{\"id\":1,\"name\":\"a, \\\"b\\\"\"},\n{\"id\":22,\"name\":null}]\n"
+        );
+        assert_eq!(
+            format_rows(Mode::Table, false, &columns, &rows),
+            "+----+--------+\n\
+             | id | name   |\n\
+             +----+--------+\n\
+             | 1  | a, \"b\" |\n\
+             | 22 |        |\n\
+             +----+--------+\n"
+        );
+        assert_eq!(
+            format_rows(Mode::Line, false, &columns, &rows),
+            "  id = 1\nname = a, \"b\"\n\n  id = 22\nname = \n"
+        );
+        assert_eq!(format_rows(Mode::Json, false, &columns, &[]), "");
+        assert_eq!(format_rows(Mode::List, true, &[], &[]), "");
+        assert!("html".parse::<Mode>().is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_shell() {
+        let mut shell = ::Shell::new().unwrap();
+        assert_eq!(shell.eval("CREATE TABLE t (a INTEGER, b TEXT);").unwrap(), "");
+        assert_eq!(shell.eval("CREATE INDEX t_b ON t (b);").unwrap(), "");
+        assert_eq!(shell.eval("INSERT INTO t VALUES (1, 'x'),").unwrap(), "");
+        assert!(shell.pending());
+        assert_eq!(shell.eval("  (2, 'y');").unwrap(), "");
+        assert!(!shell.pending());
+
+        assert_eq!(shell.eval("SELECT * FROM t ORDER BY a;").unwrap(), "1|x\n2|y\n");
+        shell.eval(".headers on").unwrap();
+        shell.eval(".mode csv").unwrap();
+        assert_eq!(
+            shell.eval("SELECT * FROM t WHERE a = 2;").unwrap(),
+            "a,b\r\n2,y\r\n"
+        );
+        assert_eq!(shell.eval(".tables").unwrap(), "t\n");
+        assert_eq!(shell.eval(".indexes t").unwrap(), "t_b\n");
+        assert_eq!(
+            shell.eval(".schema t%").unwrap(),
+            "CREATE TABLE t (a INTEGER, b TEXT);\nCREATE INDEX t_b ON t (b);\n"
+        );
+        assert_eq!(
+            shell.eval(".dump").unwrap(),
+            "PRAGMA foreign_keys=OFF;\n\
+             BEGIN TRANSACTION;\n\
+             CREATE TABLE t (a INTEGER, b TEXT);\n\
+             INSERT INTO \"t\" VALUES(1,'x');\n\
+             INSERT INTO \"t\" VALUES(2,'y');\n\
+             CREATE INDEX t_b ON t (b);\n\
+             COMMIT;\n"
+        );
+
+        ::database::register_csv_buffer(
+            "shell_people",
+            &js_sys::Uint8Array::from(&b"name,age\nann,30\nbob,41\n"[..]),
+        )
+        .unwrap();
+        assert_eq!(shell.eval(".import shell_people people").unwrap(), "");
+        shell.eval(".mode list").unwrap();
+        shell.eval(".headers off").unwrap();
+        assert_eq!(
+            shell.eval("SELECT name, age FROM people ORDER BY age;").unwrap(),
+            "ann|30\nbob|41\n"
+        );
+
+        shell.register_script("setup", ".mode line\nSELECT 1 AS one,\n 2 AS two;\n.read setup");
+        assert_eq!(
+            shell.eval(".read setup").unwrap(),
+            "one = 1\ntwo = 2\nError: script setup reads itself\n"
+        );
+
+        shell.eval(".timer on").unwrap();
+        assert!(shell.eval("SELECT 1;").unwrap().contains("Run Time: real "));
+        assert_eq!(
+            shell.eval("SELECT * FROM nowhere;").unwrap(),
+            "Error: no such table: nowhere\n"
+        );
+        assert!(shell.eval(".mode html").unwrap().starts_with("Error: mode should be"));
+        assert!(shell.eval(".frobnicate").unwrap().starts_with("Error: unknown command"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_sql_literal() {
+        use rusqlite::pragma::Sql;
+        use rusqlite::types::ValueRef;
+
+        let literal = |value| {
+            let mut sql = Sql::new();
+            sql.push_literal(value).unwrap();
+            sql.as_str().to_owned()
+        };
+        assert_eq!(literal(ValueRef::Null), "NULL");
+        assert_eq!(literal(ValueRef::Integer(-3)), "-3");
+        assert_eq!(literal(ValueRef::Real(1.0)), "1.0");
+        assert_eq!(literal(ValueRef::Real(0.5)), "0.5");
+        assert_eq!(literal(ValueRef::Real(::std::f64::INFINITY)), "1e999");
+        assert_eq!(literal(ValueRef::Text(b"it's")), "'it''s'");
+        assert_eq!(literal(ValueRef::Blob(&[0, 0xab])), "X'00AB'");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_dump_restore() {
+        use rusqlite::vtab::csvtab;
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE child (id INTEGER PRIMARY KEY, parent INTEGER REFERENCES parent(id));
+             CREATE TABLE parent (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, score REAL, data BLOB);
+             CREATE VIEW names AS SELECT name FROM parent;
+             CREATE TRIGGER parent_gone AFTER DELETE ON parent BEGIN
+                 DELETE FROM child WHERE parent = old.id;
+             END;
+             INSERT INTO parent (name, score, data) VALUES ('it''s', 2.0, X'00FF'), (NULL, 0.5, NULL);
+             DELETE FROM parent WHERE id = 2;
+             INSERT INTO child VALUES (1, 1);",
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        db.dump(&mut out, &DumpOptions::default()).unwrap();
+        let sql = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = sql.lines().collect();
+        assert_eq!(lines[0], "PRAGMA foreign_keys=OFF;");
+        assert_eq!(lines[1], "BEGIN TRANSACTION;");
+        assert!(lines[2].starts_with("CREATE TABLE parent"));
+        assert_eq!(lines[3], "INSERT INTO \"parent\" VALUES(1,'it''s',2.0,X'00FF');");
+        assert!(lines[4].starts_with("CREATE TABLE child"));
+        assert_eq!(lines[5], "INSERT INTO \"child\" VALUES(1,1);");
+        assert_eq!(lines[6], "DELETE FROM sqlite_sequence WHERE name='parent';");
+        assert_eq!(lines[7], "INSERT INTO sqlite_sequence VALUES('parent',2);");
+        assert!(lines[8].starts_with("CREATE VIEW names"));
+        assert!(lines[9].starts_with("CREATE TRIGGER parent_gone"));
+        assert_eq!(lines.last(), Some(&"COMMIT;"));
+
+        let copy = Connection::open_in_memory().unwrap();
+        copy.restore(sql.as_bytes()).unwrap();
+        let row: (String, f64, Vec<u8>) = copy
+            .query_row("SELECT name, score, data FROM parent", NO_PARAMS, |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(row, ("it's".to_owned(), 2.0, vec![0, 0xff]));
+        copy.execute("INSERT INTO parent (name) VALUES ('new')", NO_PARAMS)
+            .unwrap();
+        assert_eq!(copy.last_insert_rowid(), 3);
+        copy.execute("DELETE FROM parent WHERE id = 1", NO_PARAMS)
+            .unwrap();
+        let children: i64 = copy
+            .query_row("SELECT count(*) FROM child", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(children, 0);
+
+        let mut out = Vec::new();
+        let options = DumpOptions {
+            tables: Some("child".to_owned()),
+            schema: false,
+            transaction: false,
+            ..DumpOptions::default()
+        };
+        db.dump(&mut out, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "INSERT INTO \"child\" VALUES(1,1);\n"
+        );
+
+        // Virtual tables go through sqlite_master and PRAGMA writable_schema.
+        csvtab::register_buffer("dump", "x,y\n1,one\n2,two\n");
+        let db = Connection::open_in_memory().unwrap();
+        csvtab::load_module(&db).unwrap();
+        db.execute_batch("CREATE VIRTUAL TABLE csv USING csv(buffer=dump, header=yes)")
+            .unwrap();
+        let mut out = Vec::new();
+        db.dump(&mut out, &DumpOptions::default()).unwrap();
+        let sql = String::from_utf8(out).unwrap();
+        assert!(sql.contains("PRAGMA writable_schema=ON;"));
+        let copy = Connection::open_in_memory().unwrap();
+        csvtab::load_module(&copy).unwrap();
+        copy.restore(sql.as_bytes()).unwrap();
+        let y: String = copy
+            .query_row("SELECT y FROM csv WHERE x = '2'", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(y, "two");
+        assert!(csvtab::unregister_buffer("dump"));
+
+        let failed = Connection::open_in_memory().unwrap();
+        assert!(failed
+            .restore(&b"BEGIN;\nCREATE TABLE a (x);\nINSERT INTO missing VALUES (1);\nCOMMIT;\n"[..])
+            .is_err());
+        assert!(failed.is_autocommit());
+        let tables: i64 = failed
+            .query_row("SELECT count(*) FROM sqlite_master", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(tables, 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_diff() {
+        use rusqlite::diff::{diff, RowChange, SchemaChange};
+        use rusqlite::types::Value;
+
+        let a = Connection::open_in_memory().unwrap();
+        a.execute_batch(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, v TEXT);
+             INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, 'c');
+             CREATE INDEX t_v ON t (v);
+             CREATE TABLE n (x);
+             INSERT INTO n VALUES ('p'), ('q');
+             CREATE TABLE w (a);
+             INSERT INTO w VALUES (1);
+             CREATE TABLE gone (x);",
+        )
+        .unwrap();
+        let b = Connection::open_in_memory().unwrap();
+        b.execute_batch(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, v TEXT);
+             INSERT INTO t VALUES (1, 'a'), (2, 'B'), (4, 'd');
+             CREATE INDEX t_v ON t (v, id);
+             CREATE TABLE n (x);
+             INSERT INTO n VALUES ('p'), ('r'), ('new');
+             CREATE TABLE w (a, b);
+             INSERT INTO w VALUES (1, X'00');
+             CREATE VIEW vv AS SELECT 1;",
+        )
+        .unwrap();
+
+        assert!(diff(&a, &a).unwrap().is_empty());
+        let d = diff(&a, &b).unwrap();
+        let names: Vec<&str> = d.schema.iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["gone", "t_v", "vv", "w"]);
+        match d.schema[0] {
+            SchemaChange::Dropped { ref kind, .. } => assert_eq!(kind, "table"),
+            ref change => panic!("unexpected {:?}", change),
+        }
+        match d.schema[1] {
+            SchemaChange::Altered { ref new_sql, .. } => {
+                assert_eq!(new_sql, "CREATE INDEX t_v ON t (v, id)")
+            }
+            ref change => panic!("unexpected {:?}", change),
+        }
+
+        let tables: Vec<&str> = d.tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(tables, ["n", "t", "w"]);
+        assert_eq!(d.tables[0].key, ["rowid"]);
+        assert_eq!(
+            d.tables[0].rows,
+            [
+                RowChange::Update {
+                    key: vec![Value::Integer(2)],
+                    old: vec![Value::Text("q".to_owned())],
+                    new: vec![Value::Text("r".to_owned())],
+                },
+                RowChange::Insert {
+                    key: vec![Value::Integer(3)],
+                    row: vec![Value::Text("new".to_owned())],
+                },
+            ]
+        );
+        assert_eq!(d.tables[1].key, ["id"]);
+        assert_eq!(
+            d.tables[1].rows,
+            [
+                RowChange::Update {
+                    key: vec![Value::Integer(2)],
+                    old: vec![Value::Integer(2), Value::Text("b".to_owned())],
+                    new: vec![Value::Integer(2), Value::Text("B".to_owned())],
+                },
+                RowChange::Delete {
+                    key: vec![Value::Integer(3)],
+                    row: vec![Value::Integer(3), Value::Text("c".to_owned())],
+                },
+                RowChange::Insert {
+                    key: vec![Value::Integer(4)],
+                    row: vec![Value::Integer(4), Value::Text("d".to_owned())],
+                },
+            ]
+        );
+
+        let script = d.to_sql().unwrap();
+        assert!(script.contains("UPDATE \"t\" SET \"v\"='B' WHERE \"id\"=2;\n"));
+        assert!(script.contains("DELETE FROM \"t\" WHERE \"id\"=3;\n"));
+        assert!(script.contains("INSERT INTO \"n\"(\"rowid\",\"x\") VALUES(3,'new');\n"));
+        assert!(script.contains("INSERT INTO \"w\"(\"rowid\",\"a\",\"b\") VALUES(1,1,X'00');\n"));
+        a.execute_batch(&script).unwrap();
+        assert!(diff(&a, &b).unwrap().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_attach() {
+        let db = Connection::open_in_memory().unwrap();
+        let names = |db: &Connection| -> Vec<String> {
+            db.databases().unwrap().into_iter().map(|d| d.name).collect()
+        };
+        for &name in &["main", "TEMP", ""] {
+            assert!(db.attach(name, AttachSource::Memory).is_err());
+            assert!(db.detach(name).is_err());
+        }
+        {
+            let aux = db.attach("aux", AttachSource::Memory).unwrap();
+            db.execute_batch("CREATE TABLE aux.t (x); INSERT INTO aux.t VALUES (1);")
+                .unwrap();
+            assert!(db.attach("aux", AttachSource::Memory).is_err());
+            db.pragma_update(Some(aux.name()), "user_version", &7)
+                .unwrap();
+            let version: i64 = db
+                .query_row("PRAGMA aux.user_version", NO_PARAMS, |row| row.get(0))
+                .unwrap();
+            assert_eq!(version, 7);
+            assert_eq!(names(&db), ["main", "aux"]);
+        }
+        assert_eq!(names(&db), ["main"]);
+
+        let image = {
+            let src = Connection::open_in_memory().unwrap();
+            src.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES ('copied');")
+                .unwrap();
+            src.serialize(DatabaseName::Main).unwrap()
+        };
+        let copy = db.attach("copy", AttachSource::Bytes(&image)).unwrap();
+        let x: String = db
+            .query_row("SELECT x FROM copy.t", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(x, "copied");
+        db.execute("INSERT INTO copy.t VALUES (zeroblob(10000))", NO_PARAMS)
+            .unwrap();
+        let databases = db.databases().unwrap();
+        let info = &databases[1];
+        assert_eq!(info.name, "copy");
+        assert_eq!(info.file, None);
+        assert!(!info.readonly);
+        copy.detach().unwrap();
+
+        let err = db
+            .attach(
+                "v",
+                AttachSource::Vfs {
+                    path: "v.db",
+                    vfs: "no-such-vfs",
+                },
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("no such vfs"));
+        assert_eq!(names(&db), ["main"]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_snapshot_requires_wal() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch("CREATE TABLE t (x); INSERT INTO t VALUES (1);")
+            .unwrap();
+        match db.current_snapshot().unwrap_err() {
+            Error::SqliteFailure(err, _) => assert_eq!(err.code, ErrorCode::Unknown),
+            err => panic!("unexpected error {}", err),
+        }
+        assert!(db.is_autocommit());
+        assert!(db.recover_snapshots(DatabaseName::Main).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_snapshot_read_while_writing() {
+        memvfs::register().unwrap();
+        let open = || {
+            Connection::open_with_flags_and_vfs("snapshot.db", OpenFlags::default(), memvfs::NAME)
+                .unwrap()
+        };
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT count(*) FROM log", NO_PARAMS, |r| r.get(0))
+                .unwrap()
+        };
+
+        let writer = open();
+        let mode: String = writer
+            .query_row("PRAGMA journal_mode = WAL", NO_PARAMS, |r| r.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+        writer
+            .execute_batch("CREATE TABLE log (x); INSERT INTO log VALUES (1);")
+            .unwrap();
+
+        let mut reader = open();
+        let start = reader.current_snapshot().unwrap();
+        writer.execute("INSERT INTO log VALUES (2)", NO_PARAMS).unwrap();
+        {
+            let tx = reader.read_snapshot(&start).unwrap();
+            assert_eq!(count(&tx), 1);
+            // the writer keeps committing while the reader is at the snapshot
+            writer.execute("INSERT INTO log VALUES (3)", NO_PARAMS).unwrap();
+            assert_eq!(count(&tx), 1);
+            assert_eq!(count(&writer), 3);
+        }
+        assert_eq!(count(&reader), 3);
+        assert!(start < reader.current_snapshot().unwrap());
+    }
 }
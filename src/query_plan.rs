@@ -0,0 +1,58 @@
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use rusqlite::query_plan::{PlanOp, QueryPlan, QueryPlanNode, QueryPlanStatus};
+
+fn set(obj: &Object, key: &str, value: &JsValue) {
+    Reflect::set(obj, &JsValue::from_str(key), value).unwrap_throw();
+}
+
+fn optional_str(s: Option<&str>) -> JsValue {
+    s.map(JsValue::from_str).unwrap_or(JsValue::NULL)
+}
+
+fn plan_op_name(op: PlanOp) -> &'static str {
+    match op {
+        PlanOp::Scan => "SCAN",
+        PlanOp::Search => "SEARCH",
+        PlanOp::UseTempBTree => "USE TEMP B-TREE",
+        PlanOp::Other => "OTHER",
+    }
+}
+
+fn plan_node_to_js(node: &QueryPlanNode) -> JsValue {
+    let obj = Object::new();
+    set(&obj, "id", &JsValue::from(node.id()));
+    set(&obj, "parent", &JsValue::from(node.parent()));
+    set(&obj, "detail", &JsValue::from_str(node.detail()));
+    set(&obj, "op", &JsValue::from_str(plan_op_name(node.op())));
+    set(&obj, "table", &optional_str(node.table()));
+    set(&obj, "index", &optional_str(node.index()));
+    let children: Array = node.children().iter().map(plan_node_to_js).collect();
+    set(&obj, "children", &children);
+    obj.into()
+}
+
+fn plan_status_to_js(status: QueryPlanStatus) -> JsValue {
+    let obj = Object::new();
+    set(&obj, "fullscanSteps", &JsValue::from(status.fullscan_steps));
+    set(&obj, "sorts", &JsValue::from(status.sorts));
+    set(&obj, "autoIndexes", &JsValue::from(status.auto_indexes));
+    obj.into()
+}
+
+/// Convert a `QueryPlan` into a plain JS object.
+pub fn query_plan_to_js(plan: &QueryPlan) -> JsValue {
+    let obj = Object::new();
+    set(&obj, "text", &JsValue::from_str(&plan.to_string()));
+    let steps: Array = plan.roots().iter().map(plan_node_to_js).collect();
+    set(&obj, "steps", &steps);
+    set(&obj, "hasFullScan", &JsValue::from(plan.has_full_scan()));
+    set(&obj, "usesTempBTree", &JsValue::from(plan.uses_temp_b_tree()));
+    set(
+        &obj,
+        "status",
+        &plan.status().map(plan_status_to_js).unwrap_or(JsValue::NULL),
+    );
+    obj.into()
+}
@@ -0,0 +1,165 @@
+use std::mem;
+use std::rc::Rc;
+
+use js_sys::{Array, Function, Object, Promise, Reflect, Symbol};
+use wasm_bindgen::prelude::*;
+
+use rusqlite::types::Value;
+use rusqlite::{Connection, Result, Statement};
+
+use convert::value_to_js;
+
+/// Number of rows yielded per batch when the caller does not pick one.
+pub const DEFAULT_BATCH_SIZE: usize = 256;
+
+/// Steps a statement in batches, implementing the JS async iterator protocol.
+///
+/// Each call to `next()` resolves to an array of up to `batchSize` row
+/// objects keyed by column name:
+///
+/// ```js
+/// for await (const rows of db.stream("SELECT * FROM person", [], 100)) {
+///     rows.forEach(render);
+/// }
+/// ```
+///
+/// Breaking out of the loop calls `return()`, which finalizes the statement.
+#[wasm_bindgen]
+pub struct RowStream {
+    // Declared before `conn` so the statement is finalized before the
+    // connection can be dropped.
+    stmt: Option<Statement<'static>>,
+    columns: Vec<String>,
+    batch_size: usize,
+    #[allow(dead_code)]
+    conn: Rc<Connection>,
+}
+
+impl RowStream {
+    /// Prepare `sql` on `conn` and bind `params` to it.
+    pub fn new(
+        conn: Rc<Connection>,
+        sql: &str,
+        params: &[Value],
+        batch_size: usize,
+    ) -> Result<RowStream> {
+        let stmt = conn.prepare(sql)?;
+        // The statement borrows the connection owned by `conn`. The `Rc` is
+        // stored alongside it and outlives it, so the borrow stays valid.
+        let mut stmt: Statement<'static> = unsafe { mem::transmute(stmt) };
+        stmt.bind_parameters(params)?;
+        let columns = stmt.column_names().into_iter().map(str::to_owned).collect();
+        Ok(RowStream {
+            stmt: Some(stmt),
+            columns,
+            batch_size: batch_size.max(1),
+            conn,
+        })
+    }
+
+    /// Returns the column names of the result set.
+    pub fn column_names(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Returns `true` once the statement has been exhausted or closed.
+    pub fn is_closed(&self) -> bool {
+        self.stmt.is_none()
+    }
+
+    /// Step the statement up to `batch_size` times and return the rows read.
+    /// An empty batch means the stream is exhausted. The statement is
+    /// finalized as soon as it is done or fails.
+    pub fn fetch(&mut self) -> Result<Vec<Vec<Value>>> {
+        let mut rows = Vec::new();
+        let result = match self.stmt {
+            Some(ref stmt) => loop {
+                if rows.len() == self.batch_size {
+                    break Ok(());
+                }
+                match stmt.step() {
+                    Ok(true) => rows.push(
+                        (0..self.columns.len())
+                            .map(|i| Value::from(stmt.value_ref(i)))
+                            .collect(),
+                    ),
+                    Ok(false) => break Ok(()),
+                    Err(e) => break Err(e),
+                }
+            },
+            None => Ok(()),
+        };
+        if result.is_err() || rows.len() < self.batch_size {
+            self.close();
+        }
+        result.map(|_| rows)
+    }
+
+    /// Finalize the statement; later fetches return no rows.
+    pub fn close(&mut self) {
+        self.stmt = None;
+    }
+
+    /// The next `{ value, done }` result of the async iterator.
+    fn next_batch(&mut self) -> ::std::result::Result<JsValue, JsValue> {
+        let rows = self.fetch()?;
+        if rows.is_empty() {
+            return Ok(iter_result(&JsValue::UNDEFINED, true));
+        }
+        let batch: Array = rows.iter().map(|row| self.row_to_js(row)).collect();
+        Ok(iter_result(&batch, false))
+    }
+
+    fn row_to_js(&self, row: &[Value]) -> JsValue {
+        let obj = Object::new();
+        for (name, value) in self.columns.iter().zip(row) {
+            Reflect::set(&obj, &JsValue::from_str(name), &value_to_js(value.into())).unwrap_throw();
+        }
+        obj.into()
+    }
+
+    /// Convert into a JS object that can also be used with `for await`.
+    pub fn into_js(self) -> JsValue {
+        let stream = JsValue::from(self);
+        Reflect::set(
+            &stream,
+            &Symbol::async_iterator(),
+            &Function::new_no_args("return this"),
+        )
+        .unwrap_throw();
+        stream
+    }
+}
+
+fn iter_result(value: &JsValue, done: bool) -> JsValue {
+    let obj = Object::new();
+    Reflect::set(&obj, &JsValue::from_str("value"), value).unwrap_throw();
+    Reflect::set(&obj, &JsValue::from_str("done"), &JsValue::from(done)).unwrap_throw();
+    obj.into()
+}
+
+#[wasm_bindgen]
+impl RowStream {
+    /// The column names of the result set.
+    #[wasm_bindgen(getter)]
+    pub fn columns(&self) -> Array {
+        self.columns.iter().map(|c| JsValue::from_str(c)).collect()
+    }
+
+    /// Resolves to `{ value: rows, done: false }` while rows remain and to
+    /// `{ value: undefined, done: true }` afterwards.
+    #[wasm_bindgen(js_name = "next")]
+    pub fn next_rows(&mut self) -> Promise {
+        match self.next_batch() {
+            Ok(result) => Promise::resolve(&result),
+            Err(e) => Promise::reject(&e),
+        }
+    }
+
+    /// Stop early and finalize the statement.
+    #[wasm_bindgen(js_name = "return")]
+    pub fn finish(&mut self) -> Promise {
+        self.close();
+        Promise::resolve(&iter_result(&JsValue::UNDEFINED, true))
+    }
+}
@@ -0,0 +1,61 @@
+use rusqlite::schema::{ColumnInfo, ForeignKeyInfo, IndexInfo, Schema, TableInfo, TriggerInfo};
+use serde_json::Value;
+
+fn column_to_json(column: &ColumnInfo) -> Value {
+    json!({
+        "name": column.name,
+        "declType": column.decl_type,
+        "notNull": column.not_null,
+        "default": column.default,
+        "primaryKey": column.primary_key,
+        "collation": column.collation,
+        "autoIncrement": column.auto_increment,
+    })
+}
+
+fn index_to_json(index: &IndexInfo) -> Value {
+    json!({
+        "name": index.name,
+        "unique": index.unique,
+        "origin": index.origin,
+        "partial": index.partial,
+        "columns": index.columns,
+    })
+}
+
+fn foreign_key_to_json(fk: &ForeignKeyInfo) -> Value {
+    json!({
+        "table": fk.table,
+        "from": fk.from,
+        "to": fk.to,
+        "onUpdate": fk.on_update,
+        "onDelete": fk.on_delete,
+    })
+}
+
+fn table_to_json(table: &TableInfo) -> Value {
+    json!({
+        "name": table.name,
+        "sql": table.sql,
+        "columns": table.columns.iter().map(column_to_json).collect::<Vec<_>>(),
+        "indexes": table.indexes.iter().map(index_to_json).collect::<Vec<_>>(),
+        "foreignKeys": table.foreign_keys.iter().map(foreign_key_to_json).collect::<Vec<_>>(),
+    })
+}
+
+fn trigger_to_json(trigger: &TriggerInfo) -> Value {
+    json!({
+        "name": trigger.name,
+        "table": trigger.table,
+        "sql": trigger.sql,
+    })
+}
+
+/// Convert a `Schema` into the JSON handed to JS, with camelCase keys.
+pub fn schema_to_json(schema: &Schema) -> Value {
+    json!({
+        "tables": schema.tables.iter().map(table_to_json).collect::<Vec<_>>(),
+        "views": schema.views.iter().map(table_to_json).collect::<Vec<_>>(),
+        "triggers": schema.triggers.iter().map(trigger_to_json).collect::<Vec<_>>(),
+    })
+}
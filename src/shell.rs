@@ -0,0 +1,279 @@
+//! A line-oriented SQL shell in the style of the `sqlite3` command-line
+//! tool, for terminals and web page consoles.
+
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use rusqlite::pragma::Sql;
+use rusqlite::{is_complete, Connection, DumpOptions, Error};
+
+use database::Database;
+use format::{format_rows, Mode};
+
+const HELP: &str = "\
+.dump ?PATTERN?          Render tables matching PATTERN as SQL
+.headers on|off          Turn display of headers on or off
+.help                    Show this message
+.import BUFFER TABLE     Import CSV from a registered buffer into TABLE
+.indexes ?TABLE?         Show names of indexes
+.mode MODE               Set output mode: csv json line list table
+.read NAME               Run the script registered as NAME
+.schema ?PATTERN?        Show the CREATE statements matching PATTERN
+.tables ?PATTERN?        List names of tables matching a LIKE pattern
+.timer on|off            Turn the timer for SQL statements on or off
+";
+
+fn parse_switch(arg: Option<&str>) -> Result<bool, String> {
+    match arg {
+        Some("on") | Some("yes") | Some("true") | Some("1") => Ok(true),
+        Some("off") | Some("no") | Some("false") | Some("0") => Ok(false),
+        _ => Err("expected on or off".to_owned()),
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    let mut sql = Sql::new();
+    sql.push_identifier(name);
+    sql.as_str().to_owned()
+}
+
+fn error_message(err: Error) -> String {
+    match err {
+        Error::ScriptFailed(_, err) => err.to_string(),
+        err => err.to_string(),
+    }
+}
+
+/// Run `sql` with one text parameter and collect the first column.
+fn query_column(conn: &Connection, sql: &str, param: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn.prepare(sql).map_err(error_message)?;
+    let names = stmt
+        .query_map(&[param], |row| row.get(0))
+        .and_then(|rows| rows.collect())
+        .map_err(error_message)?;
+    Ok(names)
+}
+
+/// An interactive SQL shell that understands the common dot-commands of the
+/// `sqlite3` command-line tool:
+///
+/// ```js
+/// const shell = new Shell();
+/// shell.eval(".mode table");
+/// process.stdout.write(shell.eval("SELECT 1 AS one;"));
+/// ```
+///
+/// A statement may span several lines; `eval` returns an empty string until
+/// it is complete. Errors are returned as `Error: ...` lines, as the
+/// command-line tool prints them.
+#[wasm_bindgen]
+pub struct Shell {
+    conn: Rc<Connection>,
+    mode: Mode,
+    headers: bool,
+    timer: bool,
+    pending: String,
+    scripts: HashMap<String, String>,
+    reading: Vec<String>,
+}
+
+impl Shell {
+    /// A shell on `conn`, which may be shared with a `Database`.
+    pub fn with_connection(conn: Rc<Connection>) -> Shell {
+        Shell {
+            conn,
+            mode: Mode::List,
+            headers: false,
+            timer: false,
+            pending: String::new(),
+            scripts: HashMap::new(),
+            reading: Vec::new(),
+        }
+    }
+
+    fn eval_line(&mut self, line: &str) -> String {
+        let result = if self.pending.is_empty() && line.trim_start().starts_with('.') {
+            self.command(line.trim())
+        } else {
+            self.pending.push_str(line);
+            self.pending.push('\n');
+            if self.pending.trim().is_empty() {
+                self.pending.clear();
+                return String::new();
+            }
+            if !is_complete(&self.pending) {
+                return String::new();
+            }
+            let sql = mem::take(&mut self.pending);
+            self.sql(&sql)
+        };
+        result.unwrap_or_else(|err| format!("Error: {}\n", err))
+    }
+
+    fn sql(&self, sql: &str) -> Result<String, String> {
+        let start = js_sys::Date::now();
+        let results = self.conn.execute_script(sql).map_err(error_message)?;
+        let mut out = String::new();
+        for result in &results {
+            out.push_str(&format_rows(
+                self.mode,
+                self.headers,
+                &result.columns,
+                &result.rows,
+            ));
+        }
+        if self.timer {
+            let elapsed = (js_sys::Date::now() - start) / 1000.0;
+            out.push_str(&format!("Run Time: real {:.3}\n", elapsed));
+        }
+        Ok(out)
+    }
+
+    fn command(&mut self, line: &str) -> Result<String, String> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let arg = |i: usize| {
+            args.get(i)
+                .map(|a| a.trim_matches(|c| c == '"' || c == '\''))
+        };
+        match (args[0], args.len()) {
+            (".help", 1) => Ok(HELP.to_owned()),
+            (".tables", 1) | (".tables", 2) => {
+                let names = query_column(
+                    &self.conn,
+                    "SELECT name FROM sqlite_master \
+                     WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' \
+                     AND name LIKE ?1 ORDER BY name",
+                    arg(1).unwrap_or("%"),
+                )?;
+                Ok(if names.is_empty() {
+                    String::new()
+                } else {
+                    names.join("  ") + "\n"
+                })
+            }
+            (".schema", 1) | (".schema", 2) => {
+                let statements = query_column(
+                    &self.conn,
+                    "SELECT sql FROM sqlite_master \
+                     WHERE sql NOT NULL AND name NOT LIKE 'sqlite_%' AND name LIKE ?1 \
+                     ORDER BY rowid",
+                    arg(1).unwrap_or("%"),
+                )?;
+                Ok(statements.iter().map(|sql| format!("{};\n", sql)).collect())
+            }
+            (".indexes", 1) | (".indexes", 2) => {
+                let names = query_column(
+                    &self.conn,
+                    "SELECT name FROM sqlite_master \
+                     WHERE type = 'index' AND tbl_name LIKE ?1 ORDER BY name",
+                    arg(1).unwrap_or("%"),
+                )?;
+                Ok(names.iter().map(|name| format!("{}\n", name)).collect())
+            }
+            (".mode", 2) => {
+                self.mode = arg(1).unwrap().parse()?;
+                Ok(String::new())
+            }
+            (".headers", 2) => {
+                self.headers = parse_switch(arg(1))?;
+                Ok(String::new())
+            }
+            (".timer", 2) => {
+                self.timer = parse_switch(arg(1))?;
+                Ok(String::new())
+            }
+            (".import", 3) => self.import(arg(1).unwrap(), arg(2).unwrap()),
+            (".dump", 1) | (".dump", 2) => {
+                let options = DumpOptions {
+                    tables: arg(1).map(str::to_owned),
+                    ..DumpOptions::default()
+                };
+                let mut out = Vec::new();
+                self.conn.dump(&mut out, &options).map_err(error_message)?;
+                String::from_utf8(out).map_err(|err| err.to_string())
+            }
+            (".read", 2) => self.read(arg(1).unwrap()),
+            _ => Err(format!(
+                "unknown command or invalid arguments: \"{}\". Enter \".help\" for help",
+                &args[0][1..]
+            )),
+        }
+    }
+
+    /// Load the CSV buffer `buffer`, registered with `registerCsvBuffer`,
+    /// into `table`, creating it from the header line if it does not exist.
+    fn import(&mut self, buffer: &str, table: &str) -> Result<String, String> {
+        if buffer.contains('\'') {
+            return Err(format!("invalid buffer name: {}", buffer));
+        }
+        let exists = !query_column(
+            &self.conn,
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            table,
+        )?
+        .is_empty();
+        let table = quote_identifier(table);
+        let copy = if exists {
+            format!("INSERT INTO {} SELECT * FROM temp.shell_import;", table)
+        } else {
+            format!("CREATE TABLE {} AS SELECT * FROM temp.shell_import;", table)
+        };
+        self.conn
+            .execute_batch(&format!(
+                "CREATE VIRTUAL TABLE temp.shell_import USING csv(buffer='{}', header=yes);",
+                buffer
+            ))
+            .map_err(error_message)?;
+        let result = self.conn.execute_batch(&copy).map_err(error_message);
+        self.conn
+            .execute_batch("DROP TABLE temp.shell_import;")
+            .map_err(error_message)?;
+        result.map(|_| String::new())
+    }
+
+    /// Feed the lines of the script registered as `name` to the shell.
+    fn read(&mut self, name: &str) -> Result<String, String> {
+        if self.reading.iter().any(|n| n == name) {
+            return Err(format!("script {} reads itself", name));
+        }
+        let script = match self.scripts.get(name) {
+            Some(script) => script.clone(),
+            None => return Err(format!("no script registered as {}", name)),
+        };
+        self.reading.push(name.to_owned());
+        let out = script.lines().map(|line| self.eval_line(line)).collect();
+        self.reading.pop();
+        Ok(out)
+    }
+}
+
+#[wasm_bindgen]
+impl Shell {
+    /// Open a shell on a new in-memory database, set up like
+    /// `new Database()`.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Shell, JsValue> {
+        Ok(Database::new()?.shell())
+    }
+
+    /// Run a dot-command or a line of SQL and return what the command-line
+    /// tool would print.
+    pub fn eval(&mut self, line: &str) -> Result<String, JsValue> {
+        Ok(self.eval_line(line))
+    }
+
+    /// Whether an incomplete SQL statement is waiting for more lines.
+    #[wasm_bindgen(getter)]
+    pub fn pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Make `text` available to `.read name`.
+    #[wasm_bindgen(js_name = registerScript)]
+    pub fn register_script(&mut self, name: &str, text: &str) {
+        self.scripts.insert(name.to_owned(), text.to_owned());
+    }
+}
@@ -0,0 +1,210 @@
+//! The transactions of `Database.transaction`, which wait for the promise
+//! returned by a JS function and retry on `SQLITE_BUSY`.
+//!
+//! `Connection::transaction_with_retry` cannot wait between attempts on
+//! wasm32, since the thread cannot sleep; here the backoff of the
+//! `RetryPolicy` is a `setTimeout` between two attempts instead.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use js_sys::{Function, Object, Promise, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use rusqlite::{Connection, RetryPolicy};
+
+use error::sql_error;
+
+/// Read a `RetryPolicy` from `{ maxRetries, initialBackoffMs, maxBackoffMs }`,
+/// using the defaults for the missing keys.
+pub fn retry_policy(options: Option<&Object>) -> Result<RetryPolicy, JsValue> {
+    let mut policy = RetryPolicy::default();
+    if let Some(options) = options {
+        if let Some(n) = number(options, "maxRetries")? {
+            policy.max_retries = n as u32;
+        }
+        if let Some(ms) = number(options, "initialBackoffMs")? {
+            policy.initial_backoff = Duration::from_millis(ms as u64);
+        }
+        if let Some(ms) = number(options, "maxBackoffMs")? {
+            policy.max_backoff = Duration::from_millis(ms as u64);
+        }
+    }
+    Ok(policy)
+}
+
+fn number(options: &Object, key: &str) -> Result<Option<f64>, JsValue> {
+    let value = Reflect::get(options, &JsValue::from_str(key))?;
+    if value.is_undefined() {
+        return Ok(None);
+    }
+    match value.as_f64() {
+        Some(n) if n >= 0.0 && n.fract() == 0.0 => Ok(Some(n)),
+        _ => {
+            Err(js_sys::RangeError::new(&format!("{} must be a non-negative integer", key)).into())
+        }
+    }
+}
+
+/// Whether `reason` is a `SqliteError` worth retrying: another connection
+/// holds a conflicting lock.
+fn is_busy(reason: &JsValue) -> bool {
+    if !reason.is_object() {
+        return false;
+    }
+    let code = Reflect::get(reason, &JsValue::from_str("code")).ok();
+    match code.and_then(|code| code.as_string()) {
+        Some(code) => code == "DatabaseBusy" || code == "DatabaseLocked",
+        None => false,
+    }
+}
+
+/// `promise.then(on_resolve, on_reject)`. `Promise::then2` only takes
+/// closures returning `()`, so call `then` directly to pass the value or the
+/// rejection through.
+fn then(promise: &Promise, on_resolve: &JsValue, on_reject: &JsValue) -> Result<Promise, JsValue> {
+    let then: Function = Reflect::get(promise, &JsValue::from_str("then"))?.dyn_into()?;
+    Ok(then.call2(promise, on_resolve, on_reject)?.unchecked_into())
+}
+
+/// A promise resolved after `duration`.
+fn delay(duration: Duration) -> Result<Promise, JsValue> {
+    let global = js_sys::global();
+    let set_timeout: Function =
+        Reflect::get(&global, &JsValue::from_str("setTimeout"))?.dyn_into()?;
+    let ms = JsValue::from_f64(duration.as_millis() as f64);
+    let mut result = Ok(());
+    let promise = Promise::new(&mut |resolve, _| {
+        result = set_timeout.call2(&global, &resolve, &ms).map(|_| ());
+    });
+    result.map(|()| promise)
+}
+
+fn settled(result: Result<Promise, JsValue>) -> Promise {
+    match result {
+        Ok(promise) => promise,
+        Err(err) => Promise::reject(&err),
+    }
+}
+
+/// A call of `Database.transaction`, whose attempts run `f` in turn.
+pub struct JsTransaction {
+    conn: Rc<Connection>,
+    /// Set while a `transaction` promise of the `Database` is unsettled.
+    pending: Rc<Cell<bool>>,
+    f: Function,
+    policy: RetryPolicy,
+    /// Whether the attempts run in a savepoint of an open transaction.
+    nested: bool,
+}
+
+impl JsTransaction {
+    /// Prepare to run `f` on `conn`, in a savepoint if a transaction is
+    /// already open.
+    pub fn new(
+        conn: Rc<Connection>,
+        pending: Rc<Cell<bool>>,
+        f: Function,
+        policy: RetryPolicy,
+    ) -> JsTransaction {
+        let nested = !conn.is_autocommit();
+        JsTransaction {
+            conn,
+            pending,
+            f,
+            policy,
+            nested,
+        }
+    }
+
+    /// Run the attempts and return a promise of the result of the last one.
+    /// Rejects at once if another transaction is pending.
+    pub fn run(self) -> Promise {
+        if self.pending.get() {
+            return Promise::reject(&js_sys::Error::new("a transaction is already pending"));
+        }
+        self.pending.set(true);
+        self.attempt(0)
+    }
+
+    fn statements(&self) -> (&'static str, &'static str, &'static str) {
+        if self.nested {
+            (
+                "SAVEPOINT js_transaction",
+                "RELEASE js_transaction",
+                "ROLLBACK TO js_transaction; RELEASE js_transaction",
+            )
+        } else {
+            ("BEGIN", "COMMIT", "ROLLBACK")
+        }
+    }
+
+    /// Run attempt number `retry` (counting from 0), then retry it after
+    /// the backoff if it failed with a busy error and retries are left.
+    fn attempt(self, retry: u32) -> Promise {
+        let promise = settled(self.begin_and_call());
+
+        let pending = Rc::clone(&self.pending);
+        let on_resolve = Closure::once_into_js(move |value: JsValue| -> JsValue {
+            pending.set(false);
+            value
+        });
+        let on_reject = Closure::once_into_js(move |reason: JsValue| -> JsValue {
+            if retry < self.policy.max_retries && is_busy(&reason) {
+                return self.retry(retry).into();
+            }
+            self.pending.set(false);
+            Promise::reject(&reason).into()
+        });
+        settled(then(&promise, &on_resolve, &on_reject))
+    }
+
+    fn retry(self, retry: u32) -> Promise {
+        let pending = Rc::clone(&self.pending);
+        let result = delay(self.policy.backoff(retry)).and_then(|wait| {
+            let next = Closure::once_into_js(move |_: JsValue| -> JsValue {
+                self.attempt(retry + 1).into()
+            });
+            then(&wait, &next, &JsValue::UNDEFINED)
+        });
+        if result.is_err() {
+            pending.set(false);
+        }
+        settled(result)
+    }
+
+    /// Begin the transaction, call `f` and commit or roll back once the
+    /// value it returns settles.
+    fn begin_and_call(&self) -> Result<Promise, JsValue> {
+        let (begin, commit, rollback) = self.statements();
+        self.conn.execute_batch(begin).map_err(sql_error(begin))?;
+        let promise = self.call(commit, rollback);
+        if promise.is_err() {
+            let _ = self.conn.execute_batch(rollback);
+        }
+        promise
+    }
+
+    fn call(&self, commit: &'static str, rollback: &'static str) -> Result<Promise, JsValue> {
+        let value = self.f.call0(&JsValue::UNDEFINED)?;
+
+        let conn = Rc::clone(&self.conn);
+        let on_resolve = Closure::once_into_js(move |value: JsValue| -> JsValue {
+            match conn.execute_batch(commit) {
+                Ok(()) => value,
+                Err(err) => {
+                    let _ = conn.execute_batch(rollback);
+                    Promise::reject(&sql_error(commit)(err)).into()
+                }
+            }
+        });
+        let conn = Rc::clone(&self.conn);
+        let on_reject = Closure::once_into_js(move |reason: JsValue| -> JsValue {
+            let _ = conn.execute_batch(rollback);
+            Promise::reject(&reason).into()
+        });
+        then(&Promise::resolve(&value), &on_resolve, &on_reject)
+    }
+}
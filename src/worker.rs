@@ -0,0 +1,470 @@
+//! Run a `Connection` inside a Web Worker (or a Node `worker_threads`
+//! worker) and talk to it from the main thread with Promises.
+//!
+//! The worker calls `startWorker(self)` (or `startWorker(parentPort)` under
+//! Node) and the main thread wraps the `Worker` object in an `AsyncDatabase`.
+//! Both sides exchange JSON strings through `postMessage`:
+//!
+//! ```text
+//! -> {"id":1,"op":"exec","sql":"CREATE TABLE foo (x)"}
+//! <- {"id":1,"ok":true,"result":null}
+//! -> {"id":2,"op":"query","sql":"SELECT x FROM foo WHERE x > ?","params":[1]}
+//! <- {"id":2,"ok":true,"result":{"columns":["x"],"rows":[[2],[3]]}}
+//! -> {"id":3,"op":"transaction","statements":[{"sql":"INSERT INTO foo VALUES (?)","params":[4]}]}
+//! <- {"id":3,"ok":true,"result":[1]}
+//! -> {"id":4,"op":"exec","sql":"SELEC"}
+//! <- {"id":4,"ok":false,"error":"near \"SELEC\": syntax error"}
+//! ```
+//!
+//! Blobs travel as arrays of byte values.
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use js_sys::{Array, Function, Promise, Reflect, JSON};
+use serde_json::Value as Json;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, Result};
+
+/// A request sent from an `AsyncDatabase` to its worker.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    /// Run one or more statements without parameters.
+    Exec(String),
+    /// Run a single statement and return its rows.
+    Query(String, Vec<Value>),
+    /// Run each statement with its parameters inside one transaction.
+    Transaction(Statements),
+}
+
+/// Statements with their parameters, as run by `Request::Transaction`.
+pub type Statements = Vec<(String, Vec<Value>)>;
+
+/// The result of a `Request`: a JSON value or an error message.
+pub type Response = ::std::result::Result<Json, String>;
+
+/// Convert a SQLite value into its JSON wire form.
+pub fn value_to_json(value: ValueRef<'_>) -> Json {
+    match value {
+        ValueRef::Null => Json::Null,
+        ValueRef::Integer(i) => Json::from(i),
+        ValueRef::Real(f) => Json::from(f),
+        ValueRef::Text(s) => Json::from(String::from_utf8_lossy(s).into_owned()),
+        ValueRef::Blob(b) => Json::from(b.to_vec()),
+    }
+}
+
+/// Convert a JSON parameter into a SQLite value.
+pub fn json_to_value(json: &Json) -> ::std::result::Result<Value, String> {
+    match *json {
+        Json::Null => Ok(Value::Null),
+        Json::Bool(b) => Ok(Value::from(b)),
+        Json::Number(ref n) => match n.as_i64() {
+            Some(i) => Ok(Value::Integer(i)),
+            None => Ok(Value::Real(n.as_f64().unwrap_or(0f64))),
+        },
+        Json::String(ref s) => Ok(Value::Text(s.clone())),
+        Json::Array(ref bytes) => bytes
+            .iter()
+            .map(|b| match b.as_u64() {
+                Some(b) if b <= 0xff => Ok(b as u8),
+                _ => Err(format!("invalid blob byte: {}", b)),
+            })
+            .collect::<::std::result::Result<Vec<u8>, String>>()
+            .map(Value::Blob),
+        Json::Object(_) => Err(format!("unsupported parameter: {}", json)),
+    }
+}
+
+fn params_to_json(params: &[Value]) -> Json {
+    Json::Array(
+        params
+            .iter()
+            .map(|v| value_to_json(ValueRef::from(v)))
+            .collect(),
+    )
+}
+
+fn message_id(json: &Json) -> ::std::result::Result<u32, String> {
+    json["id"]
+        .as_u64()
+        .map(|id| id as u32)
+        .ok_or_else(|| "missing message id".to_owned())
+}
+
+fn sql_of(json: &Json) -> ::std::result::Result<String, String> {
+    json["sql"]
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| "missing sql".to_owned())
+}
+
+fn params_of(json: &Json) -> ::std::result::Result<Vec<Value>, String> {
+    match json["params"] {
+        Json::Null => Ok(Vec::new()),
+        Json::Array(ref params) => params.iter().map(json_to_value).collect(),
+        ref other => Err(format!("invalid params: {}", other)),
+    }
+}
+
+/// Parse a list of `{"sql": ..., "params": [...]}` objects.
+pub fn statements_of(json: &Json) -> ::std::result::Result<Statements, String> {
+    json.as_array()
+        .ok_or_else(|| "statements must be an array".to_owned())?
+        .iter()
+        .map(|s| Ok((sql_of(s)?, params_of(s)?)))
+        .collect()
+}
+
+/// Serialize a request with the given message id.
+pub fn encode_request(id: u32, request: &Request) -> String {
+    let mut json = match *request {
+        Request::Exec(ref sql) => json!({ "op": "exec", "sql": sql }),
+        Request::Query(ref sql, ref params) => {
+            json!({ "op": "query", "sql": sql, "params": params_to_json(params) })
+        }
+        Request::Transaction(ref statements) => {
+            let statements: Vec<Json> = statements
+                .iter()
+                .map(|(sql, params)| json!({ "sql": sql, "params": params_to_json(params) }))
+                .collect();
+            json!({ "op": "transaction", "statements": statements })
+        }
+    };
+    json["id"] = Json::from(id);
+    json.to_string()
+}
+
+/// Parse a message produced by `encode_request`.
+pub fn decode_request(message: &str) -> ::std::result::Result<(u32, Request), String> {
+    let json: Json = serde_json::from_str(message).map_err(|e| e.to_string())?;
+    let id = message_id(&json)?;
+    let request = match json["op"].as_str() {
+        Some("exec") => Request::Exec(sql_of(&json)?),
+        Some("query") => Request::Query(sql_of(&json)?, params_of(&json)?),
+        Some("transaction") => Request::Transaction(statements_of(&json["statements"])?),
+        _ => return Err(format!("unknown op: {}", json["op"])),
+    };
+    Ok((id, request))
+}
+
+/// Serialize a response to the request with the given message id.
+pub fn encode_response(id: u32, response: &Response) -> String {
+    match *response {
+        Ok(ref result) => json!({ "id": id, "ok": true, "result": result }),
+        Err(ref message) => json!({ "id": id, "ok": false, "error": message }),
+    }
+    .to_string()
+}
+
+/// Parse a message produced by `encode_response`.
+pub fn decode_response(message: &str) -> ::std::result::Result<(u32, Response), String> {
+    let mut json: Json = serde_json::from_str(message).map_err(|e| e.to_string())?;
+    let id = message_id(&json)?;
+    if json["ok"].as_bool() == Some(true) {
+        Ok((id, Ok(json["result"].take())))
+    } else {
+        let error = json["error"].as_str().unwrap_or("unknown error").to_owned();
+        Ok((id, Err(error)))
+    }
+}
+
+/// Owns the `Connection` on the worker side and answers requests.
+pub struct WorkerHost {
+    conn: Connection,
+}
+
+impl WorkerHost {
+    pub fn new(conn: Connection) -> WorkerHost {
+        WorkerHost { conn }
+    }
+
+    /// Decode `message`, run it and return the encoded response.
+    ///
+    /// A malformed request is answered with an error under its id, or under
+    /// id 0, which no client uses, if the id itself cannot be read.
+    pub fn handle(&mut self, message: &str) -> String {
+        match decode_request(message) {
+            Ok((id, request)) => {
+                let response = self.run(request).map_err(|e| e.to_string());
+                encode_response(id, &response)
+            }
+            Err(e) => {
+                let id = serde_json::from_str(message)
+                    .ok()
+                    .and_then(|json: Json| message_id(&json).ok());
+                encode_response(id.unwrap_or(0), &Err(e))
+            }
+        }
+    }
+
+    fn run(&mut self, request: Request) -> Result<Json> {
+        match request {
+            Request::Exec(sql) => self.conn.execute_batch(&sql).map(|_| Json::Null),
+            Request::Query(sql, params) => {
+                let mut stmt = self.conn.prepare(&sql)?;
+                let columns: Vec<Json> = stmt.column_names().into_iter().map(Json::from).collect();
+                let mut rows = stmt.query(&params)?;
+                let mut result = Vec::new();
+                while let Some(row) = rows.next()? {
+                    let values = (0..columns.len())
+                        .map(|i| value_to_json(row.get_raw(i)))
+                        .collect();
+                    result.push(Json::Array(values));
+                }
+                Ok(json!({ "columns": columns, "rows": result }))
+            }
+            Request::Transaction(statements) => {
+                let tx = self.conn.transaction()?;
+                let mut changes = Vec::with_capacity(statements.len());
+                for (sql, params) in &statements {
+                    changes.push(Json::from(tx.execute(sql, params)?));
+                }
+                tx.commit()?;
+                Ok(Json::Array(changes))
+            }
+        }
+    }
+}
+
+/// Delivers encoded requests to a `WorkerHost`.
+pub trait Transport {
+    fn send(&self, message: String);
+}
+
+type Callback = Box<dyn FnOnce(Response)>;
+
+/// Sends requests and routes responses back to their callbacks.
+pub struct Client<T: Transport> {
+    transport: T,
+    next_id: Cell<u32>,
+    pending: RefCell<HashMap<u32, Callback>>,
+}
+
+impl<T: Transport> Client<T> {
+    pub fn new(transport: T) -> Client<T> {
+        Client {
+            transport,
+            next_id: Cell::new(0),
+            pending: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Send `request`; `callback` runs when the matching response arrives.
+    pub fn request<F>(&self, request: &Request, callback: F)
+    where
+        F: FnOnce(Response) + 'static,
+    {
+        // id 0 answers requests whose id the worker could not read
+        let id = match self.next_id.get().wrapping_add(1) {
+            0 => 1,
+            id => id,
+        };
+        self.next_id.set(id);
+        self.pending.borrow_mut().insert(id, Box::new(callback));
+        self.transport.send(encode_request(id, request));
+    }
+
+    /// Handle a message received from the worker. Malformed messages and
+    /// responses to unknown requests are ignored.
+    pub fn receive(&self, message: &str) {
+        if let Ok((id, response)) = decode_response(message) {
+            let callback = self.pending.borrow_mut().remove(&id);
+            if let Some(callback) = callback {
+                callback(response);
+            }
+        }
+    }
+
+    /// Returns the number of requests still waiting for a response.
+    pub fn pending(&self) -> usize {
+        self.pending.borrow().len()
+    }
+}
+
+/// Transport that hands requests to an in-process `WorkerHost`.
+///
+/// Responses are queued until `Client::flush` delivers them, mimicking the
+/// asynchronous delivery of `postMessage`.
+pub struct Loopback {
+    host: RefCell<WorkerHost>,
+    responses: RefCell<VecDeque<String>>,
+}
+
+impl Loopback {
+    pub fn new(conn: Connection) -> Loopback {
+        Loopback {
+            host: RefCell::new(WorkerHost::new(conn)),
+            responses: RefCell::new(VecDeque::new()),
+        }
+    }
+}
+
+impl Transport for Loopback {
+    fn send(&self, message: String) {
+        let response = self.host.borrow_mut().handle(&message);
+        self.responses.borrow_mut().push_back(response);
+    }
+}
+
+impl Client<Loopback> {
+    /// Deliver every queued response.
+    pub fn flush(&self) {
+        loop {
+            let response = self.transport.responses.borrow_mut().pop_front();
+            match response {
+                Some(response) => self.receive(&response),
+                None => break,
+            }
+        }
+    }
+}
+
+fn post_message(port: &JsValue, message: &JsValue) {
+    let post = Reflect::get(port, &JsValue::from_str("postMessage")).unwrap_throw();
+    post.unchecked_ref::<Function>()
+        .call1(port, message)
+        .unwrap_throw();
+}
+
+/// Add `handler` as a listener for the messages of `port`, next to any
+/// other listeners: with `addEventListener` on DOM ports, or with `on` on
+/// Node `EventEmitter`s such as `parentPort` and `worker_threads.Worker`.
+fn on_message(
+    port: &JsValue,
+    handler: &Closure<dyn FnMut(JsValue)>,
+) -> ::std::result::Result<(), JsValue> {
+    for &method in &["addEventListener", "on"] {
+        let listen = Reflect::get(port, &JsValue::from_str(method))?;
+        if let Some(listen) = listen.dyn_ref::<Function>() {
+            listen.call2(port, &JsValue::from_str("message"), handler.as_ref())?;
+            return Ok(());
+        }
+    }
+    Err(js_sys::TypeError::new("expected a Worker, a MessagePort or a worker global scope").into())
+}
+
+/// The message of `event`: a `MessageEvent` from `addEventListener`, or the
+/// message itself from a Node `on` listener.
+fn message_data(event: &JsValue) -> Option<String> {
+    if let Some(message) = event.as_string() {
+        return Some(message);
+    }
+    Reflect::get(event, &JsValue::from_str("data"))
+        .ok()
+        .and_then(|data| data.as_string())
+}
+
+fn json_to_js(json: &Json) -> JsValue {
+    JSON::parse(&json.to_string()).unwrap_or(JsValue::NULL)
+}
+
+fn js_to_json(value: &JsValue) -> ::std::result::Result<Json, JsValue> {
+    if value.is_undefined() {
+        return Ok(Json::Null);
+    }
+    let s: String = JSON::stringify(value)?.into();
+    serde_json::from_str(&s).map_err(|e| JsValue::from(js_sys::Error::new(&e.to_string())))
+}
+
+struct PortTransport {
+    port: JsValue,
+}
+
+impl Transport for PortTransport {
+    fn send(&self, message: String) {
+        post_message(&self.port, &JsValue::from(message));
+    }
+}
+
+/// Serve an in-memory database on `port`, which is the worker global scope
+/// in the browser or `parentPort` under Node.
+#[wasm_bindgen(js_name = startWorker)]
+pub fn start_worker(port: JsValue) -> ::std::result::Result<(), JsValue> {
+    let conn = Connection::open_in_memory()?;
+    let host = RefCell::new(WorkerHost::new(conn));
+    let reply_to = port.clone();
+    let onmessage = Closure::wrap(Box::new(move |event: JsValue| {
+        if let Some(message) = message_data(&event) {
+            let response = host.borrow_mut().handle(&message);
+            post_message(&reply_to, &JsValue::from(response));
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+    on_message(&port, &onmessage)?;
+    // The worker serves requests for as long as it lives.
+    onmessage.forget();
+    Ok(())
+}
+
+/// Main thread proxy for a database owned by a worker started with
+/// `startWorker`.
+#[wasm_bindgen]
+pub struct AsyncDatabase {
+    client: Rc<Client<PortTransport>>,
+    _onmessage: Closure<dyn FnMut(JsValue)>,
+}
+
+#[wasm_bindgen]
+impl AsyncDatabase {
+    /// Wrap `worker`, a `Worker` in the browser or a `worker_threads.Worker`
+    /// under Node.
+    #[wasm_bindgen(constructor)]
+    pub fn new(worker: JsValue) -> ::std::result::Result<AsyncDatabase, JsValue> {
+        let client = Rc::new(Client::new(PortTransport {
+            port: worker.clone(),
+        }));
+        let receiver = Rc::clone(&client);
+        let onmessage = Closure::wrap(Box::new(move |event: JsValue| {
+            if let Some(message) = message_data(&event) {
+                receiver.receive(&message);
+            }
+        }) as Box<dyn FnMut(JsValue)>);
+        on_message(&worker, &onmessage)?;
+        Ok(AsyncDatabase {
+            client,
+            _onmessage: onmessage,
+        })
+    }
+
+    /// Run one or more statements; resolves to `null`.
+    pub fn exec(&self, sql: String) -> ::std::result::Result<Promise, JsValue> {
+        Ok(self.send(Request::Exec(sql)))
+    }
+
+    /// Run a single statement; resolves to `{ columns, rows }`.
+    pub fn query(&self, sql: String, params: JsValue) -> ::std::result::Result<Promise, JsValue> {
+        let params = match js_to_json(&params)? {
+            Json::Null => Vec::new(),
+            Json::Array(params) => params
+                .iter()
+                .map(json_to_value)
+                .collect::<::std::result::Result<Vec<_>, String>>()
+                .map_err(|e| JsValue::from(js_sys::Error::new(&e)))?,
+            _ => return Err(js_sys::Error::new("params must be an array").into()),
+        };
+        Ok(self.send(Request::Query(sql, params)))
+    }
+
+    /// Run an array of `{ sql, params }` objects inside one transaction;
+    /// resolves to the number of rows changed by each statement.
+    pub fn transaction(&self, statements: Array) -> ::std::result::Result<Promise, JsValue> {
+        let statements = statements_of(&js_to_json(&statements)?)
+            .map_err(|e| JsValue::from(js_sys::Error::new(&e)))?;
+        Ok(self.send(Request::Transaction(statements)))
+    }
+
+    fn send(&self, request: Request) -> Promise {
+        let client = &self.client;
+        Promise::new(&mut |resolve: Function, reject: Function| {
+            client.request(&request, move |response| {
+                let _ = match response {
+                    Ok(result) => resolve.call1(&JsValue::NULL, &json_to_js(&result)),
+                    Err(message) => reject.call1(&JsValue::NULL, &js_sys::Error::new(&message)),
+                };
+            });
+        })
+    }
+}